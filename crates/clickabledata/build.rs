@@ -0,0 +1,10 @@
+// `mlua` is built in module mode everywhere in this workspace, which means it
+// expects the host process (DCS) to provide the Lua symbols. On Windows those
+// come from the import library in `vendor/lua5.1`; everywhere else there is no
+// host, so build and link a copy of Lua 5.1 for the tests.
+fn main() {
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        let artifacts = lua_src::Build::new().build(lua_src::Lua51);
+        artifacts.print_cargo_metadata();
+    }
+}
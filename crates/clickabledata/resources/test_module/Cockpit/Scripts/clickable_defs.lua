@@ -0,0 +1,76 @@
+-- A few helpers in the shape of the ones modules define in their own
+-- clickable_defs.lua, enough to cover every kind of switch.
+
+function default_2_position_tumb(hint_, device_, command_, arg_)
+    return {
+        class = {class_type.TUMB, class_type.TUMB},
+        hint = hint_,
+        device = device_,
+        action = {command_, command_},
+        arg = {arg_, arg_},
+        arg_value = {1, -1},
+        arg_lim = {{0, 1}, {0, 1}},
+    }
+end
+
+function multiposition_switch(hint_, device_, command_, arg_, count_, delta_)
+    return {
+        class = {class_type.TUMB, class_type.TUMB},
+        hint = hint_,
+        device = device_,
+        action = {command_, command_},
+        arg = {arg_, arg_},
+        arg_value = {delta_, -delta_},
+        arg_lim = {{0, delta_ * (count_ - 1)}, {0, delta_ * (count_ - 1)}},
+    }
+end
+
+function default_3_position_tumb(hint_, device_, command_down_, command_up_, arg_)
+    return {
+        class = {class_type.TUMB, class_type.TUMB},
+        hint = hint_,
+        device = device_,
+        action = {command_up_, command_down_},
+        arg = {arg_, arg_},
+        arg_value = {1, -1},
+        arg_lim = {{-1, 1}, {-1, 1}},
+    }
+end
+
+function springloaded_3_pos_tumb(hint_, device_, command_down_, command_up_, arg_)
+    return {
+        class = {class_type.BTN, class_type.BTN},
+        hint = hint_,
+        device = device_,
+        action = {command_down_, command_up_},
+        stop_action = {command_down_, command_up_},
+        arg = {arg_, arg_},
+        arg_value = {-1, 1},
+        arg_lim = {{-1, 0}, {0, 1}},
+    }
+end
+
+function default_button(hint_, device_, command_, arg_)
+    return {
+        class = {class_type.BTN},
+        hint = hint_,
+        device = device_,
+        action = {command_},
+        stop_action = {command_},
+        arg = {arg_},
+        arg_value = {1},
+        arg_lim = {{0, 1}},
+    }
+end
+
+function default_axis(hint_, device_, command_, arg_)
+    return {
+        class = {class_type.LEV},
+        hint = hint_,
+        device = device_,
+        action = {command_},
+        arg = {arg_},
+        arg_value = {0.1},
+        arg_lim = {{0, 1}},
+    }
+end
@@ -0,0 +1,21 @@
+dofile(LockOn_Options.common_script_path.."elements_defs.lua")
+dofile(LockOn_Options.script_path.."clickable_defs.lua")
+dofile(LockOn_Options.script_path.."command_defs.lua")
+dofile(LockOn_Options.script_path.."devices.lua")
+
+local gettext = require("i_18n")
+_ = gettext.translate
+
+show_element_boxes = get_option_value("Cockpit.ShowElementBoxes", "local")
+
+elements = {}
+
+elements["PNT-MAIN-PWR"] = multiposition_switch(_("Main Power Switch, MAIN PWR/BATT/OFF"), devices.ELEC_INTERFACE, elec_commands.MainPwrSw, 510, 3, 0.5)
+elements["PNT-BATT-TEST"] = default_button(_("Battery Test Button"), devices.ELEC_INTERFACE, elec_commands.BatteryTest, 511)
+elements["PNT-JFS"] = springloaded_3_pos_tumb(_("JFS Switch, START 2/OFF/START 1"), devices.ENGINE_INTERFACE, engine_commands.JfsSwStart2, engine_commands.JfsSwStart1, 447)
+elements["PNT-CANOPY-HANDLE"] = default_2_position_tumb(_("Canopy Handle, UP/DOWN"), devices.CPT_MECH, cpt_commands.CanopyHandle, 600)
+elements["PNT-CANOPY-SW"] = default_3_position_tumb(_("Canopy Switch, OPEN/HOLD/CLOSE"), devices.CPT_MECH, cpt_commands.CanopySwClose, cpt_commands.CanopySwOpen, 606)
+elements["PNT-THROTTLE"] = default_axis(_("Throttle"), devices.CPT_MECH, cpt_commands.Throttle, 700)
+elements["PNT-INS-KNOB"] = multiposition_switch(_("INS Mode Knob"), devices.INS, ins_commands.ModeKnob, 719, 7, 0.1)
+-- removed in a later version of the module, left pointing at nothing
+elements["PNT-OLD-RADIO"] = default_button(_("Radio Button"), devices.OLD_RADIO, 3001, 800)
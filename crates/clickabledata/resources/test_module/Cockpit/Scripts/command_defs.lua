@@ -0,0 +1,26 @@
+local count = 3000
+local function counter()
+    count = count + 1
+    return count
+end
+
+elec_commands = {
+    MainPwrSw = counter(),
+    BatteryTest = counter(),
+}
+
+engine_commands = {
+    JfsSwStart1 = counter(),
+    JfsSwStart2 = counter(),
+}
+
+cpt_commands = {
+    CanopyHandle = counter(),
+    CanopySwOpen = counter(),
+    CanopySwClose = counter(),
+    Throttle = counter(),
+}
+
+ins_commands = {
+    ModeKnob = counter(),
+}
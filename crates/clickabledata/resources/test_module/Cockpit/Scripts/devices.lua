@@ -0,0 +1,12 @@
+-- Hand written stand-in for a module's devices.lua, used by the importer tests.
+local count = 0
+local function counter()
+    count = count + 1
+    return count
+end
+
+devices = {}
+devices["ELEC_INTERFACE"] = counter()
+devices["ENGINE_INTERFACE"] = counter()
+devices["CPT_MECH"] = counter()
+devices["INS"] = counter()
@@ -0,0 +1,437 @@
+//! Imports cockpit switch definitions from a module's `clickabledata.lua`.
+//!
+//! The module's cockpit scripts are run in a plain Lua 5.1 state with just
+//! enough of DCS's script environment stubbed out for them to load: the
+//! `LockOn_Options` paths, `class_type`, the `i_18n` translation module and a
+//! `dofile` that skips files which aren't part of the module. Any other global
+//! function the scripts call does nothing and is reported as a warning. The
+//! helper functions a module defines for itself, like
+//! `default_2_position_tumb`, come from its own `clickable_defs.lua`.
+//!
+//! Every element of the resulting `elements` table becomes a [`SwitchDef`]
+//! named after its key. [`rename()`] picks out the ones an aircraft's `Switch`
+//! enum uses, under the enum's names.
+
+use mlua::{Lua, MultiValue, Table, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use switch_schema::{Info, SwitchDef, SwitchFile, SCHEMA_VERSION};
+
+/// `class_type` values from DCS's cockpit scripts.
+const CLASS_NULL: i32 = 0;
+const CLASS_BTN: i32 = 1;
+const CLASS_TUMB: i32 = 2;
+const CLASS_SNGBTN: i32 = 3;
+const CLASS_LEV: i32 = 4;
+const CLASS_MOVABLE_LEV: i32 = 5;
+
+/// Where modules load the common cockpit scripts from, which aren't part of
+/// the module and are skipped.
+const COMMON_SCRIPT_PATH: &str = "Scripts/Aircrafts/_Common/Cockpit/";
+
+const PRELUDE: &str = r#"
+package.preload["i_18n"] = function()
+    return { translate = function(s) return s end }
+end
+_ = function(s) return s end
+setmetatable(_G, {
+    __index = function(_, name)
+        return function() __undefined_function(name) end
+    end,
+})
+"#;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Lua(mlua::Error),
+    NoElements,
+    /// Elements that [`rename()`] was asked for but the module doesn't have.
+    UnknownElements(Vec<String>),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "Can't read {}: {e}", path.display()),
+            Error::Lua(e) => write!(f, "Lua error: {e}"),
+            Error::NoElements => write!(f, "the scripts didn't define an `elements` table"),
+            Error::UnknownElements(names) => {
+                write!(f, "no such elements: {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl From<mlua::Error> for Error {
+    fn from(value: mlua::Error) -> Self {
+        Error::Lua(value)
+    }
+}
+
+/// An imported switch table, and everything that was skipped along the way.
+#[derive(Debug)]
+pub struct Import {
+    pub table: SwitchFile,
+    pub warnings: Vec<String>,
+}
+
+type Warnings = Rc<RefCell<Vec<String>>>;
+
+/// Runs `clickabledata.lua` from a module's `Cockpit/Scripts` directory and
+/// converts every clickable element it defines, sorted by name.
+pub fn import(scripts_dir: &Path, aircraft: &str) -> Result<Import, Error> {
+    let path = scripts_dir.join("clickabledata.lua");
+    let text = std::fs::read_to_string(&path).map_err(|e| Error::Io(path.clone(), e))?;
+
+    let lua = Lua::new();
+    let warnings = Warnings::default();
+    install_stubs(&lua, scripts_dir, warnings.clone())?;
+    lua.load(&text)
+        .set_name(path.to_string_lossy().as_ref())?
+        .exec()?;
+
+    let Some(elements) = lua.globals().raw_get::<_, Option<Table>>("elements")? else {
+        return Err(Error::NoElements);
+    };
+    let mut switches = vec![];
+    for pair in elements.pairs::<String, Value>() {
+        let (name, element) = pair?;
+        let Value::Table(element) = element else {
+            warnings
+                .borrow_mut()
+                .push(format!("Skipping {name}: not a table"));
+            continue;
+        };
+        match classify(&element) {
+            Ok(Some(info)) => switches.push(SwitchDef {
+                name,
+                info,
+                positions: BTreeMap::new(),
+                hint: element.get("hint")?,
+            }),
+            Ok(None) => {}
+            Err(problem) => warnings
+                .borrow_mut()
+                .push(format!("Skipping {name}: {problem}")),
+        }
+    }
+    switches.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let warnings = warnings.take();
+    Ok(Import {
+        table: SwitchFile {
+            version: SCHEMA_VERSION,
+            aircraft: aircraft.to_string(),
+            switches,
+        },
+        warnings,
+    })
+}
+
+/// Keeps only the elements named in `names`, which maps a `Switch` name to
+/// the element key, and renames them. The result is in the order of `names`.
+pub fn rename(file: &SwitchFile, names: &BTreeMap<String, String>) -> Result<SwitchFile, Error> {
+    let by_element: BTreeMap<&str, &SwitchDef> =
+        file.switches.iter().map(|s| (s.name.as_str(), s)).collect();
+    let mut switches = Vec::with_capacity(names.len());
+    let mut unknown = vec![];
+    for (name, element) in names {
+        match by_element.get(element.as_str()) {
+            Some(&def) => switches.push(SwitchDef {
+                name: name.clone(),
+                ..def.clone()
+            }),
+            None => unknown.push(element.clone()),
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(Error::UnknownElements(unknown));
+    }
+    Ok(SwitchFile {
+        version: file.version,
+        aircraft: file.aircraft.clone(),
+        switches,
+    })
+}
+
+fn install_stubs(lua: &Lua, scripts_dir: &Path, warnings: Warnings) -> mlua::Result<()> {
+    let globals = lua.globals();
+
+    let options = lua.create_table()?;
+    options.set("script_path", format!("{}/", scripts_dir.display()))?;
+    options.set("common_script_path", COMMON_SCRIPT_PATH)?;
+    globals.set("LockOn_Options", options)?;
+
+    let class_type = lua.create_table()?;
+    class_type.set("NULL", CLASS_NULL)?;
+    class_type.set("BTN", CLASS_BTN)?;
+    class_type.set("TUMB", CLASS_TUMB)?;
+    class_type.set("SNGBTN", CLASS_SNGBTN)?;
+    class_type.set("LEV", CLASS_LEV)?;
+    class_type.set("MOVABLE_LEV", CLASS_MOVABLE_LEV)?;
+    globals.set("class_type", class_type)?;
+
+    let dofile_warnings = warnings.clone();
+    let dofile = lua.create_function(move |lua, path: String| {
+        let path = PathBuf::from(path);
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            dofile_warnings
+                .borrow_mut()
+                .push(format!("Skipping dofile of {}", path.display()));
+            return Ok(MultiValue::new());
+        };
+        lua.load(&text)
+            .set_name(path.to_string_lossy().as_ref())?
+            .call::<_, MultiValue>(())
+    })?;
+    globals.set("dofile", dofile)?;
+
+    let mut reported = BTreeSet::new();
+    let undefined = lua.create_function_mut(move |_, name: String| {
+        if reported.insert(name.clone()) {
+            warnings
+                .borrow_mut()
+                .push(format!("Ignoring call to undefined function {name}"));
+        }
+        Ok(())
+    })?;
+    globals.set("__undefined_function", undefined)?;
+
+    lua.load(PRELUDE).set_name("prelude")?.exec()
+}
+
+/// Works out the kind of switch from an element's class and actions, or
+/// `None` for elements that can't be clicked.
+fn classify(element: &Table) -> Result<Option<Info>, String> {
+    let classes = integers(element, "class")?;
+    let Some(&class) = classes.first() else {
+        return Err("no class".to_string());
+    };
+    if class == CLASS_NULL {
+        return Ok(None);
+    }
+    let Some(&device_id) = integers(element, "device")?.first() else {
+        return Err("device is not defined".to_string());
+    };
+    let actions = integers(element, "action")?;
+    let Some(&command) = actions.first() else {
+        return Err("no action".to_string());
+    };
+    let Some(&argument) = integers(element, "arg")?.first() else {
+        return Err("no argument".to_string());
+    };
+    let values = numbers(element, "arg_value")?;
+
+    let two_commands = actions.len() >= 2 && actions[0] != actions[1];
+    // the down command is the one that decreases the argument
+    let (command_down, command_up) = if values.first().is_some_and(|&v| v < 0.0) {
+        (actions[0], *actions.get(1).unwrap_or(&actions[0]))
+    } else {
+        (*actions.get(1).unwrap_or(&actions[0]), actions[0])
+    };
+
+    let info = match class {
+        CLASS_LEV | CLASS_MOVABLE_LEV => Info::Axis {
+            device_id,
+            command,
+            argument,
+        },
+        CLASS_BTN | CLASS_SNGBTN if two_commands => Info::SpringLoaded3Pos {
+            device_id,
+            command_down,
+            command_up,
+            argument,
+        },
+        CLASS_BTN | CLASS_SNGBTN => Info::Momentary {
+            device_id,
+            command,
+            argument,
+        },
+        CLASS_TUMB if two_commands => Info::DualCommand3Pos {
+            device_id,
+            command_down,
+            command_up,
+            argument,
+        },
+        CLASS_TUMB if count_positions(element, &values)? > 2 => Info::MultiToggle {
+            device_id,
+            command,
+            argument,
+        },
+        CLASS_TUMB => Info::Toggle {
+            device_id,
+            command,
+            argument,
+        },
+        class => return Err(format!("unknown class {class}")),
+    };
+    Ok(Some(info))
+}
+
+/// Number of positions of a rotary or toggle switch, from the argument's
+/// range and how far one click moves it.
+fn count_positions(element: &Table, values: &[f64]) -> Result<usize, String> {
+    let limits: Vec<Vec<f64>> = match element.get::<_, Value>("arg_lim") {
+        Ok(Value::Table(t)) => t
+            .sequence_values()
+            .collect::<mlua::Result<_>>()
+            .map_err(|e| format!("arg_lim: {e}"))?,
+        _ => vec![],
+    };
+    let (Some(&[low, high]), Some(&step)) = (limits.first().map(Vec::as_slice), values.first())
+    else {
+        return Ok(2);
+    };
+    if step == 0.0 {
+        return Ok(2);
+    }
+    Ok(((high - low) / step.abs()).round() as usize + 1)
+}
+
+/// A field that is either a number or a list of them.
+fn numbers(element: &Table, key: &str) -> Result<Vec<f64>, String> {
+    match element.get::<_, Value>(key) {
+        Ok(Value::Nil) => Ok(vec![]),
+        Ok(Value::Integer(i)) => Ok(vec![i as f64]),
+        Ok(Value::Number(n)) => Ok(vec![n]),
+        Ok(Value::Table(t)) => t
+            .sequence_values()
+            .collect::<mlua::Result<_>>()
+            .map_err(|e| format!("{key}: {e}")),
+        Ok(v) => Err(format!("{key} is a {}", v.type_name())),
+        Err(e) => Err(format!("{key}: {e}")),
+    }
+}
+
+fn integers(element: &Table, key: &str) -> Result<Vec<i32>, String> {
+    numbers(element, key)?
+        .into_iter()
+        .map(|n| {
+            if n.fract() == 0.0 {
+                Ok(n as i32)
+            } else {
+                Err(format!("{key} {n} is not a whole number"))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{import, rename};
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use switch_schema::{Info, SwitchFile};
+
+    fn test_module() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test_module/Cockpit/Scripts")
+    }
+
+    #[test]
+    fn test_import() {
+        let import = import(&test_module(), "Test").unwrap();
+        let info = |name: &str| {
+            import
+                .table
+                .switches
+                .iter()
+                .find(|s| s.name == name)
+                .unwrap_or_else(|| panic!("{name} was not imported"))
+                .info
+        };
+        assert_eq!(
+            info("PNT-MAIN-PWR"),
+            Info::MultiToggle {
+                device_id: 1,
+                command: 3001,
+                argument: 510
+            }
+        );
+        assert_eq!(
+            info("PNT-BATT-TEST"),
+            Info::Momentary {
+                device_id: 1,
+                command: 3002,
+                argument: 511
+            }
+        );
+        assert_eq!(
+            info("PNT-JFS"),
+            Info::SpringLoaded3Pos {
+                device_id: 2,
+                command_down: 3004,
+                command_up: 3003,
+                argument: 447
+            }
+        );
+        assert_eq!(
+            info("PNT-CANOPY-HANDLE"),
+            Info::Toggle {
+                device_id: 3,
+                command: 3005,
+                argument: 600
+            }
+        );
+        assert_eq!(
+            info("PNT-CANOPY-SW"),
+            Info::DualCommand3Pos {
+                device_id: 3,
+                command_down: 3007,
+                command_up: 3006,
+                argument: 606
+            }
+        );
+        assert_eq!(
+            info("PNT-THROTTLE"),
+            Info::Axis {
+                device_id: 3,
+                command: 3008,
+                argument: 700
+            }
+        );
+        assert_eq!(
+            import.table.switches[0].hint.as_deref(),
+            Some("Battery Test Button")
+        );
+
+        let names: Vec<&str> = import
+            .table
+            .switches
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert!(!names.contains(&"PNT-OLD-RADIO"));
+
+        let warnings = import.warnings.join("\n");
+        assert!(warnings.contains("elements_defs.lua"), "{warnings}");
+        assert!(warnings.contains("get_option_value"), "{warnings}");
+        assert!(warnings.contains("Skipping PNT-OLD-RADIO"), "{warnings}");
+
+        let text = import.table.to_json().unwrap();
+        let reparsed: SwitchFile = serde_json::from_str(&text).unwrap();
+        assert_eq!(reparsed, import.table);
+    }
+
+    #[test]
+    fn test_rename() {
+        let import = import(&test_module(), "Test").unwrap();
+        let names = BTreeMap::from([
+            ("Jfs".to_string(), "PNT-JFS".to_string()),
+            ("MainPower".to_string(), "PNT-MAIN-PWR".to_string()),
+        ]);
+        let renamed = rename(&import.table, &names).unwrap();
+        let names: Vec<&str> = renamed.switches.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Jfs", "MainPower"]);
+        assert_eq!(renamed.switches[0].info.argument(), 447);
+
+        let names = BTreeMap::from([("Warp".to_string(), "PNT-WARP".to_string())]);
+        assert!(rename(&import.table, &names).is_err());
+    }
+}
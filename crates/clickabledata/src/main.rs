@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: clickabledata <Cockpit/Scripts dir> <aircraft> [--names <names.json>] [--out <table.json>]
+
+Writes a switch table for every clickable element in the module's
+clickabledata.lua. <aircraft> is the module's AircraftId name, e.g. F-16C_50.
+
+  --names  JSON object mapping Switch names to element names; only those
+           elements are written, under the Switch names
+  --out    file to write instead of stdout";
+
+struct Args {
+    scripts_dir: PathBuf,
+    aircraft: String,
+    names: Option<PathBuf>,
+    out: Option<PathBuf>,
+}
+
+fn parse_args() -> Option<Args> {
+    let mut positional = vec![];
+    let mut names = None;
+    let mut out = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--names" => names = Some(PathBuf::from(args.next()?)),
+            "--out" => out = Some(PathBuf::from(args.next()?)),
+            _ if arg.starts_with("--") => return None,
+            _ => positional.push(arg),
+        }
+    }
+    let [scripts_dir, aircraft] = <[String; 2]>::try_from(positional).ok()?;
+    Some(Args {
+        scripts_dir: PathBuf::from(scripts_dir),
+        aircraft,
+        names,
+        out,
+    })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let import =
+        clickabledata::import(&args.scripts_dir, &args.aircraft).map_err(|e| e.to_string())?;
+    for warning in &import.warnings {
+        eprintln!("warning: {warning}");
+    }
+
+    let table = match &args.names {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| format!("Can't read {}: {e}", path.display()))?;
+            let names: BTreeMap<String, String> =
+                serde_json::from_str(&text).map_err(|e| format!("In {}: {e}", path.display()))?;
+            clickabledata::rename(&import.table, &names).map_err(|e| e.to_string())?
+        }
+        None => import.table,
+    };
+
+    let text = table.to_json().map_err(|e| e.to_string())?;
+    match &args.out {
+        Some(path) => std::fs::write(path, text)
+            .map_err(|e| format!("Can't write {}: {e}", path.display()))?,
+        None => print!("{text}"),
+    }
+    eprintln!("{} switches", table.switches.len());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let Some(args) = parse_args() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! The interface between `yawe_shim` and the `yawe` library it loads.
+//!
+//! The two are built separately and only meet through the functions the
+//! library exports, so only C types cross between them: the Lua state is
+//! passed as a raw `lua_State*`, and strings and the [`Config`] as byte
+//! buffers. The shim calls [`VERSION_SYMBOL`] before anything else and refuses
+//! a library built against a different [`VERSION`].
+//!
+//! Each side wraps the raw state in its own `mlua::Lua`, so no Rust object
+//! from one side is ever touched by the other. The library's wrappers leave
+//! finalizers in the state, which its `detach` removes again; the shim calls
+//! it in each environment after `stop`, before unloading the library.
+
+use crate::Config;
+use mlua::lua_State;
+use std::os::raw::c_int;
+
+/// Bump whenever an exported function, or the layout of anything passed to
+/// one, changes.
+pub const VERSION: u32 = 2;
+
+/// `extern "C" fn() -> u32`, returns the library's [`VERSION`].
+pub const VERSION_SYMBOL: &[u8] = b"yawe_abi_version";
+
+pub type VersionFn = unsafe extern "C" fn() -> u32;
+/// `start(state, config)` with the [`Config`] as JSON.
+pub type StartFn = unsafe extern "C" fn(*mut lua_State, Bytes) -> c_int;
+/// The callbacks that take no arguments besides the state, and `detach`.
+pub type CallbackFn = unsafe extern "C" fn(*mut lua_State) -> c_int;
+/// `on_player_change_slot(state, player_id)`.
+pub type PlayerChangeSlotFn = unsafe extern "C" fn(*mut lua_State, c_int) -> c_int;
+/// `on_game_event(state, event)` with the [`GameEvent`] as JSON.
+pub type GameEventFn = unsafe extern "C" fn(*mut lua_State, Bytes) -> c_int;
+
+/// A borrowed byte buffer. Only valid during the call it is passed to.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Bytes {
+    pub data: *const u8,
+    pub len: usize,
+}
+
+impl Bytes {
+    pub fn new(bytes: &[u8]) -> Self {
+        Self {
+            data: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    /// # Safety
+    /// The buffer must still be alive, which it is for the duration of the
+    /// call that received it.
+    pub unsafe fn as_slice<'a>(&self) -> &'a [u8] {
+        if self.data.is_null() {
+            return &[];
+        }
+        std::slice::from_raw_parts(self.data, self.len)
+    }
+}
+
+/// The arguments of the GameGUI `onGameEvent(name, ...)` callback, which are
+/// all numbers, strings or nil.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct GameEvent {
+    pub name: String,
+    pub args: Vec<serde_json::Value>,
+}
+
+pub fn encode_config(config: &Config) -> Vec<u8> {
+    serde_json::to_vec(config).expect("config serializes to JSON")
+}
+
+pub fn decode_config(bytes: &[u8]) -> serde_json::Result<Config> {
+    serde_json::from_slice(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_config, encode_config, Bytes};
+    use crate::Config;
+
+    #[test]
+    fn test_config_round_trip() {
+        let config = Config {
+            write_dir: String::from(r"C:\Users\pilot\Saved Games\DCS\"),
+            lua_path: String::from("lua"),
+            record_lua: true,
+            ..Default::default()
+        };
+        let encoded = encode_config(&config);
+        let bytes = Bytes::new(&encoded);
+        let decoded = decode_config(unsafe { bytes.as_slice() }).unwrap();
+        assert_eq!(decoded.write_dir, config.write_dir);
+        assert_eq!(decoded.lua_path, config.lua_path);
+        assert!(decoded.record_lua);
+    }
+}
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+pub mod abi;
+
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Config {
@@ -6,6 +8,11 @@ pub struct Config {
     pub lua_path: String,
     pub dll_path: String,
     pub log_level: String,
+    /// Record all Lua traffic to a file in the write dir for offline replay.
+    pub record_lua: bool,
+    /// `Name` of the aircraft the player is already in when the library
+    /// starts. Set by the shim when it reloads a new build mid-mission.
+    pub aircraft: String,
 }
 
 impl<'lua> mlua::FromLua<'lua> for Config {
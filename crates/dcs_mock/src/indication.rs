@@ -0,0 +1,69 @@
+//! Builds text in the format returned by DCS's `list_indication`.
+
+const SEPARATOR: &str = "-----------------------------------------";
+
+/// One named element of an indication, e.g. a line of text on the DED.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub value: String,
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            children: vec![],
+        }
+    }
+
+    /// An element with an empty value, like the placeholders DCS uses to
+    /// position other elements.
+    pub fn group(name: &str, children: Vec<Element>) -> Self {
+        Self {
+            name: name.to_string(),
+            value: String::new(),
+            children,
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push_str(SEPARATOR);
+        out.push('\n');
+        out.push_str(&self.name);
+        out.push('\n');
+        out.push_str(&self.value);
+        out.push('\n');
+        if self.children.is_empty() {
+            return;
+        }
+        out.push_str("children are {\n");
+        for child in &self.children {
+            child.write(out);
+        }
+        out.push_str("}\n");
+    }
+}
+
+/// Formats top level `elements` the way `list_indication` does.
+pub fn format(elements: &[Element]) -> String {
+    let mut out = String::new();
+    for element in elements {
+        element.write(&mut out);
+    }
+    out
+}
+
+/// Builds an element nested inside empty placeholder groups, one per entry
+/// of `path`, with the last entry of `path` holding `value`.
+pub fn nested(path: &[&str], value: &str) -> Element {
+    let (last, parents) = path.split_last().expect("empty indication path");
+    parents
+        .iter()
+        .rev()
+        .fold(Element::new(last, value), |child, parent| {
+            Element::group(parent, vec![child])
+        })
+}
@@ -0,0 +1,430 @@
+//! A headless stand-in for the DCS Lua environments, so that code which talks
+//! to DCS through a [`Lua`] can be exercised without the game running.
+//!
+//! [`MockDcs`] owns a Lua state with scriptable fakes of the globals yawe
+//! uses (`Export.GetDevice`, `Export.LoGetSelfData`, `Export.LoGetMechInfo`,
+//! `DCS.getModelTime`, `net.get_my_player_id`, `list_indication`, ...).
+//! Everything the fakes serve or record lives in a [`State`] that tests can
+//! inspect and modify between frames.
+//!
+//! On its own the mock only echoes whatever the test put in [`State`]. A
+//! [`Model`] (see [`models`]) can be installed to make the fake cockpit react
+//! to clicks over simulated time, like the real aircraft would.
+
+pub mod indication;
+pub mod models;
+
+use mlua::prelude::{LuaResult, LuaTable, LuaValue};
+use mlua::Lua;
+use offload::PackagedTask;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A single `performClickableAction` call made on a fake cockpit device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Click {
+    pub device_id: i32,
+    pub command: i32,
+    pub value: f32,
+    /// Model time at which the click was made.
+    pub time: f32,
+}
+
+/// Everything the fake globals serve, and everything they have recorded.
+#[derive(Debug, Default, Clone)]
+pub struct State {
+    /// Value returned by `DCS.getModelTime()`.
+    pub model_time: f32,
+    /// Value returned by `DCS.getPause()`.
+    pub paused: bool,
+    /// Value returned by `net.get_my_player_id()`.
+    pub my_player_id: i32,
+    /// `Name` field of `Export.LoGetSelfData()`. `None` means there is no
+    /// ownship, and `LoGetSelfData` returns `nil`.
+    pub aircraft_name: Option<String>,
+    /// Cockpit arguments served by `get_argument_value`, keyed by argument id.
+    /// Arguments that were never set read as 0.
+    pub arguments: HashMap<i32, f32>,
+    /// Text returned by `list_indication(device)`, keyed by indication
+    /// device. Missing devices return an empty string, as DCS does.
+    pub indications: HashMap<i32, String>,
+    /// Parameters listed by `list_cockpit_params()`.
+    pub cockpit_params: BTreeMap<String, String>,
+    /// Every click made, in order.
+    pub clicks: Vec<Click>,
+    /// Every command sent through `Export.LoSetCommand`, in order.
+    pub lockon_commands: Vec<i32>,
+    /// Value returned by `lfs.writedir()`.
+    pub write_dir: String,
+    /// Value returned by `Export.LoGetAltitudeAboveGroundLevel()`, in meters.
+    pub altitude_agl: f32,
+    /// Value returned by `Export.LoGetVectorVelocity()`, in meters per second
+    /// with `y` pointing up.
+    pub velocity: [f32; 3],
+    /// Whether `Export.LoGetMechInfo()` reports the main gear struts
+    /// compressed.
+    pub weight_on_wheels: bool,
+}
+
+impl State {
+    pub fn argument(&self, argument: i32) -> f32 {
+        self.arguments.get(&argument).copied().unwrap_or_default()
+    }
+
+    pub fn set_argument(&mut self, argument: i32, value: f32) {
+        self.arguments.insert(argument, value);
+    }
+
+    pub fn set_cockpit_param(&mut self, name: &str, value: impl ToString) {
+        self.cockpit_params
+            .insert(name.to_string(), value.to_string());
+    }
+
+    /// Clicks made on `device_id` with `command`, in order.
+    pub fn clicks_on(&self, device_id: i32, command: i32) -> Vec<Click> {
+        self.clicks
+            .iter()
+            .filter(|c| c.device_id == device_id && c.command == command)
+            .copied()
+            .collect()
+    }
+
+    fn format_cockpit_params(&self) -> String {
+        self.cockpit_params
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect()
+    }
+}
+
+/// Called with every click as it is made, after it has been recorded.
+pub type ClickHook = Box<dyn FnMut(&mut State, &Click) + Send>;
+
+/// Simulated aircraft behind the fake globals.
+///
+/// A model keeps whatever internal state it needs and publishes what the
+/// cockpit shows (arguments, indications, cockpit params) into [`State`].
+pub trait Model: Send {
+    /// Called with every click, after it has been recorded and before any
+    /// [`ClickHook`] runs.
+    fn on_click(&mut self, state: &mut State, click: &Click);
+
+    /// Called with every command sent through `Export.LoSetCommand`.
+    fn on_lockon_command(&mut self, _state: &mut State, _command: i32) {}
+
+    /// Moves the aircraft forward by `dt` seconds of model time. Called once
+    /// with `dt == 0` when the model is installed.
+    fn step(&mut self, state: &mut State, dt: f32);
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    hooks: Mutex<Vec<ClickHook>>,
+    model: Mutex<Option<Box<dyn Model>>>,
+}
+
+impl Shared {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn click(&self, device_id: i32, command: i32, value: f32) {
+        let mut state = self.state();
+        let click = Click {
+            device_id,
+            command,
+            value,
+            time: state.model_time,
+        };
+        log::trace!("Mock click {click:?}");
+        state.clicks.push(click);
+        if let Some(model) = self.model.lock().unwrap().as_mut() {
+            model.on_click(&mut state, &click);
+        }
+        for hook in self.hooks.lock().unwrap().iter_mut() {
+            hook(&mut state, &click);
+        }
+    }
+}
+
+/// A Lua state with fake DCS globals installed.
+pub struct MockDcs {
+    lua: Lua,
+    shared: Arc<Shared>,
+}
+
+impl MockDcs {
+    pub fn new() -> LuaResult<Self> {
+        let me = Self {
+            lua: Lua::new(),
+            shared: Arc::new(Shared::default()),
+        };
+        me.install_globals()?;
+        Ok(me)
+    }
+
+    /// Creates a mock with `aircraft_name` as the ownship.
+    pub fn with_aircraft(aircraft_name: &str) -> LuaResult<Self> {
+        let me = Self::new()?;
+        me.state().aircraft_name = Some(aircraft_name.to_string());
+        Ok(me)
+    }
+
+    /// Creates a mock with `aircraft_name` as the ownship, simulated by
+    /// `model`.
+    pub fn with_model(aircraft_name: &str, model: Box<dyn Model>) -> LuaResult<Self> {
+        let me = Self::with_aircraft(aircraft_name)?;
+        me.set_model(model);
+        Ok(me)
+    }
+
+    /// Replaces the simulated aircraft.
+    pub fn set_model(&self, mut model: Box<dyn Model>) {
+        model.step(&mut self.state(), 0.0);
+        *self.shared.model.lock().unwrap() = Some(model);
+    }
+
+    pub fn lua(&self) -> &Lua {
+        &self.lua
+    }
+
+    /// Locks and returns the state behind the fake globals.
+    ///
+    /// Don't hold on to the guard while running Lua, the fakes lock it too.
+    pub fn state(&self) -> MutexGuard<'_, State> {
+        self.shared.state()
+    }
+
+    /// Registers `hook` to be called with every click.
+    pub fn on_click<F>(&self, hook: F)
+    where
+        F: FnMut(&mut State, &Click) + Send + 'static,
+    {
+        self.shared.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// Moves the model time forward by `dt` seconds, stepping the installed
+    /// [`Model`] if there is one.
+    pub fn advance(&self, dt: f32) {
+        let mut state = self.state();
+        state.model_time += dt;
+        if let Some(model) = self.shared.model.lock().unwrap().as_mut() {
+            model.step(&mut state, dt);
+        }
+    }
+
+    /// Runs every job currently queued on `rx` against the mock Lua state, as
+    /// `App::on_frame` does with the real one. Returns the number of jobs run.
+    pub fn run_pending(&self, rx: &Receiver<PackagedTask<Lua>>) -> usize {
+        let mut count = 0;
+        while let Ok(job) = rx.try_recv() {
+            job(&self.lua);
+            count += 1;
+        }
+        count
+    }
+
+    fn install_globals(&self) -> LuaResult<()> {
+        let lua = &self.lua;
+        let globals = lua.globals();
+
+        let export = lua.create_table()?;
+        let shared = self.shared.clone();
+        export.set(
+            "GetDevice",
+            lua.create_function(move |lua, device_id: i32| {
+                make_device(lua, shared.clone(), device_id)
+            })?,
+        )?;
+        let shared = self.shared.clone();
+        export.set(
+            "LoGetSelfData",
+            lua.create_function(move |lua, ()| {
+                let Some(name) = shared.state().aircraft_name.clone() else {
+                    return Ok(LuaValue::Nil);
+                };
+                let self_data = lua.create_table()?;
+                self_data.set("Name", name)?;
+                Ok(LuaValue::Table(self_data))
+            })?,
+        )?;
+        let shared = self.shared.clone();
+        export.set(
+            "LoSetCommand",
+            lua.create_function(move |_, command: i32| {
+                let mut state = shared.state();
+                state.lockon_commands.push(command);
+                if let Some(model) = shared.model.lock().unwrap().as_mut() {
+                    model.on_lockon_command(&mut state, command);
+                }
+                Ok(())
+            })?,
+        )?;
+        let shared = self.shared.clone();
+        export.set(
+            "LoGetAltitudeAboveGroundLevel",
+            lua.create_function(move |_, ()| Ok(shared.state().altitude_agl))?,
+        )?;
+        let shared = self.shared.clone();
+        export.set(
+            "LoGetVectorVelocity",
+            lua.create_function(move |lua, ()| {
+                let [x, y, z] = shared.state().velocity;
+                let velocity = lua.create_table()?;
+                velocity.set("x", x)?;
+                velocity.set("y", y)?;
+                velocity.set("z", z)?;
+                Ok(velocity)
+            })?,
+        )?;
+        let shared = self.shared.clone();
+        export.set(
+            "LoGetMechInfo",
+            lua.create_function(move |lua, ()| {
+                let rod = if shared.state().weight_on_wheels {
+                    0.3
+                } else {
+                    0.0
+                };
+                let strut = |rod: f32| -> LuaResult<LuaTable> {
+                    let strut = lua.create_table()?;
+                    strut.set("rod", rod)?;
+                    Ok(strut)
+                };
+                let main = lua.create_table()?;
+                main.set("left", strut(rod)?)?;
+                main.set("right", strut(rod)?)?;
+                let gear = lua.create_table()?;
+                gear.set("main", main)?;
+                gear.set("nose", strut(rod)?)?;
+                let mech_info = lua.create_table()?;
+                mech_info.set("gear", gear)?;
+                Ok(mech_info)
+            })?,
+        )?;
+        globals.set("Export", export)?;
+
+        let dcs = lua.create_table()?;
+        let shared = self.shared.clone();
+        dcs.set(
+            "getModelTime",
+            lua.create_function(move |_, ()| Ok(shared.state().model_time))?,
+        )?;
+        let shared = self.shared.clone();
+        dcs.set(
+            "getPause",
+            lua.create_function(move |_, ()| Ok(shared.state().paused))?,
+        )?;
+        globals.set("DCS", dcs)?;
+
+        let net = lua.create_table()?;
+        let shared = self.shared.clone();
+        net.set(
+            "get_my_player_id",
+            lua.create_function(move |_, ()| Ok(shared.state().my_player_id))?,
+        )?;
+        globals.set("net", net)?;
+
+        let shared = self.shared.clone();
+        globals.set(
+            "list_indication",
+            lua.create_function(move |_, device: i32| {
+                Ok(shared
+                    .state()
+                    .indications
+                    .get(&device)
+                    .cloned()
+                    .unwrap_or_default())
+            })?,
+        )?;
+        let shared = self.shared.clone();
+        globals.set(
+            "list_cockpit_params",
+            lua.create_function(move |_, ()| Ok(shared.state().format_cockpit_params()))?,
+        )?;
+
+        let lfs = lua.create_table()?;
+        let shared = self.shared.clone();
+        lfs.set(
+            "writedir",
+            lua.create_function(move |_, ()| Ok(shared.state().write_dir.clone()))?,
+        )?;
+        globals.set("lfs", lfs)?;
+        Ok(())
+    }
+}
+
+fn make_device(lua: &Lua, shared: Arc<Shared>, device_id: i32) -> LuaResult<LuaTable<'_>> {
+    let device = lua.create_table()?;
+    let clicker = shared.clone();
+    device.set(
+        "performClickableAction",
+        lua.create_function(move |_, (_, command, value): (LuaTable, i32, f32)| {
+            clicker.click(device_id, command, value);
+            Ok(())
+        })?,
+    )?;
+    device.set(
+        "get_argument_value",
+        lua.create_function(move |_, (_, argument): (LuaTable, i32)| {
+            Ok(shared.state().argument(argument))
+        })?,
+    )?;
+    Ok(device)
+}
+
+#[cfg(test)]
+mod test {
+    use super::MockDcs;
+    use mlua::prelude::{LuaFunction, LuaTable};
+
+    fn get_device<'lua>(dcs: &'lua MockDcs, device_id: i32) -> LuaTable<'lua> {
+        let export: LuaTable = dcs.lua().globals().get("Export").unwrap();
+        let get_device: LuaFunction = export.get("GetDevice").unwrap();
+        get_device.call(device_id).unwrap()
+    }
+
+    #[test]
+    fn test_clicks_are_recorded_and_hooked() {
+        let dcs = MockDcs::new().unwrap();
+        dcs.on_click(|state, click| state.set_argument(100 + click.command, click.value));
+        dcs.advance(1.5);
+
+        let device = get_device(&dcs, 3);
+        let perform_click: LuaFunction = device.get("performClickableAction").unwrap();
+        perform_click.call::<_, ()>((device, 7, 0.25)).unwrap();
+
+        let state = dcs.state();
+        assert_eq!(state.clicks.len(), 1);
+        assert_eq!(state.clicks_on(3, 7)[0].time, 1.5);
+        assert_eq!(state.argument(107), 0.25);
+    }
+
+    #[test]
+    fn test_arguments_and_indications() {
+        let dcs = MockDcs::with_aircraft("F-16C_50").unwrap();
+        dcs.state().set_argument(510, 1.0);
+        dcs.state().indications.insert(6, "DED".to_string());
+        dcs.state().set_cockpit_param("BASE_SENSOR_CANOPY_POS", 0.5);
+
+        let device = get_device(&dcs, 0);
+        let get_value: LuaFunction = device.get("get_argument_value").unwrap();
+        assert_eq!(
+            get_value.call::<_, f32>((device.clone(), 510)).unwrap(),
+            1.0
+        );
+        assert_eq!(get_value.call::<_, f32>((device, 511)).unwrap(), 0.0);
+
+        let lua = dcs.lua();
+        let name: String = lua.load("Export.LoGetSelfData().Name").eval().unwrap();
+        assert_eq!(name, "F-16C_50");
+        let ded: String = lua.load("list_indication(6)").eval().unwrap();
+        assert_eq!(ded, "DED");
+        let hud: String = lua.load("list_indication(1)").eval().unwrap();
+        assert!(hud.is_empty());
+        let params: String = lua.load("list_cockpit_params()").eval().unwrap();
+        assert_eq!(params, "BASE_SENSOR_CANOPY_POS:0.5\n");
+    }
+}
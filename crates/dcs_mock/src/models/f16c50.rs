@@ -0,0 +1,155 @@
+//! F-16C block 50: canopy, JFS, engine spool up, generators and INS alignment.
+
+use super::{approach, move_control, Control};
+use crate::indication::{self, Element};
+use crate::{Click, State};
+use std::sync::OnceLock;
+
+/// Every control in yawe's F-16 switch table.
+fn controls() -> &'static [Control] {
+    static CONTROLS: OnceLock<Vec<Control>> = OnceLock::new();
+    CONTROLS.get_or_init(|| {
+        super::controls(include_str!(
+            "../../../yawe/resources/switches/F-16C_50.json"
+        ))
+    })
+}
+
+const MAIN_POWER: i32 = 510;
+const JFS_SWITCH: i32 = 447;
+const CANOPY_SWITCH: i32 = 606;
+const CANOPY_POSITION: i32 = 7;
+const ENGINE_RPM: i32 = 95;
+const INS_KNOB: i32 = 719;
+const INS_KNOB_STOR_HDG: f32 = 0.1;
+
+const LEFT_ENGINE_START: i32 = 311;
+const LEFT_ENGINE_STOP: i32 = 313;
+
+const HUD: i32 = 1;
+const DED: i32 = 6;
+
+/// Fraction of full travel the canopy moves per second.
+pub const CANOPY_RATE: f32 = 0.125;
+/// Change in normalized RPM per second, both spooling up and down.
+pub const SPOOL_RATE: f32 = 0.02;
+/// RPM the JFS alone can turn the engine to.
+pub const JFS_RPM: f32 = 0.25;
+/// RPM above which moving the throttle to idle lights the engine.
+pub const LIGHT_OFF_RPM: f32 = 0.12;
+/// RPM at which the JFS disengages.
+pub const JFS_CUTOUT_RPM: f32 = 0.55;
+/// RPM at which the main generators come online.
+pub const GENERATOR_RPM: f32 = 0.6;
+pub const IDLE_RPM: f32 = 0.7;
+/// Seconds the INS needs in STOR HDG with power on before it is aligned.
+pub const ALIGN_TIME: f32 = 90.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    canopy: f32,
+    rpm: f32,
+    jfs_running: bool,
+    engine_running: bool,
+    ins_alignment: f32,
+}
+
+impl Default for Model {
+    /// Cold and dark with the canopy open.
+    fn default() -> Self {
+        Self {
+            canopy: 1.0,
+            rpm: 0.0,
+            jfs_running: false,
+            engine_running: false,
+            ins_alignment: 0.0,
+        }
+    }
+}
+
+impl Model {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update_displays(&self, state: &mut State) {
+        if self.rpm < GENERATOR_RPM {
+            state.indications.remove(&HUD);
+            state.indications.remove(&DED);
+            return;
+        }
+
+        let aligning = (state.argument(INS_KNOB) - INS_KNOB_STOR_HDG).abs() < 0.05
+            && self.ins_alignment < ALIGN_TIME;
+        let mut hud = vec![Element::new("HUD_Window8_MasterMode", "NAV")];
+        if aligning {
+            hud.push(indication::nested(
+                &[
+                    "HUD_BlankRoot_PH_com",
+                    "HUD_Indication_bias",
+                    "HUD_Window7_origin",
+                    "HUD_AlignStatus_origin",
+                    "HUD_Window7_AlignmentStatus",
+                ],
+                "ALIGN",
+            ));
+        }
+        state.indications.insert(HUD, indication::format(&hud));
+
+        let ded = [Element::group(
+            "DED CNI TACAN PH",
+            vec![Element::new("DED CNI TACAN", "TCN")],
+        )];
+        state.indications.insert(DED, indication::format(&ded));
+    }
+}
+
+impl crate::Model for Model {
+    fn on_click(&mut self, state: &mut State, click: &Click) {
+        let argument = move_control(controls(), state, click);
+        let powered = state.argument(MAIN_POWER) > 0.0;
+        if argument == Some(JFS_SWITCH) && click.value != 0.0 && powered {
+            self.jfs_running = true;
+        }
+    }
+
+    fn on_lockon_command(&mut self, _state: &mut State, command: i32) {
+        match command {
+            LEFT_ENGINE_START if self.rpm >= LIGHT_OFF_RPM => self.engine_running = true,
+            LEFT_ENGINE_STOP => self.engine_running = false,
+            _ => {}
+        }
+    }
+
+    fn step(&mut self, state: &mut State, dt: f32) {
+        let powered = state.argument(MAIN_POWER) > 0.0;
+
+        let canopy_switch = state.argument(CANOPY_SWITCH);
+        if powered && canopy_switch < 0.0 {
+            self.canopy = approach(self.canopy, 0.0, CANOPY_RATE, dt);
+        } else if powered && canopy_switch > 0.0 {
+            self.canopy = approach(self.canopy, 1.0, CANOPY_RATE, dt);
+        }
+        state.set_argument(CANOPY_POSITION, self.canopy);
+
+        let target_rpm = if self.engine_running {
+            IDLE_RPM
+        } else if self.jfs_running {
+            JFS_RPM
+        } else {
+            0.0
+        };
+        self.rpm = approach(self.rpm, target_rpm, SPOOL_RATE, dt);
+        if self.rpm >= JFS_CUTOUT_RPM {
+            self.jfs_running = false;
+        }
+        state.set_argument(ENGINE_RPM, self.rpm);
+
+        let ins_in_stor_hdg = (state.argument(INS_KNOB) - INS_KNOB_STOR_HDG).abs() < 0.05;
+        if powered && ins_in_stor_hdg {
+            self.ins_alignment += dt;
+        }
+
+        self.update_displays(state);
+    }
+}
@@ -0,0 +1,109 @@
+//! MiG-21bis: canopy and engine start sequence.
+
+use super::{approach, move_control, Control};
+use crate::{Click, State};
+use std::sync::OnceLock;
+
+/// Every control in yawe's MiG-21 switch table.
+fn controls() -> &'static [Control] {
+    static CONTROLS: OnceLock<Vec<Control>> = OnceLock::new();
+    CONTROLS.get_or_init(|| {
+        super::controls(include_str!(
+            "../../../yawe/resources/switches/MiG-21Bis.json"
+        ))
+    })
+}
+
+const BATTERY: i32 = 165;
+const APU: i32 = 302;
+const CANOPY_OPEN: i32 = 375;
+const CANOPY_CLOSE: i32 = 385;
+const ENGINE_START: i32 = 289;
+const ENGINE_START_LIGHT: i32 = 509;
+const LEFT_ENGINE_STOP: i32 = 313;
+
+pub const CANOPY_POSITION_PARAM: &str = "BASE_SENSOR_CANOPY_POS";
+pub const ENGINE_RPM_PARAM: &str = "BASE_SENSOR_LEFT_ENGINE_RPM";
+
+/// Fraction of full travel the canopy moves per second.
+pub const CANOPY_RATE: f32 = 0.2;
+/// Seconds from pressing the start button to the engine running at idle.
+pub const START_TIME: f32 = 25.0;
+pub const IDLE_RPM: f32 = 0.65;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    canopy: f32,
+    rpm: f32,
+    /// Seconds since the start sequence began, while it is running.
+    starting: Option<f32>,
+    engine_running: bool,
+}
+
+impl Default for Model {
+    /// Cold and dark with the canopy open.
+    fn default() -> Self {
+        Self {
+            canopy: 1.0,
+            rpm: 0.0,
+            starting: None,
+            engine_running: false,
+        }
+    }
+}
+
+impl Model {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl crate::Model for Model {
+    fn on_click(&mut self, state: &mut State, click: &Click) {
+        let argument = move_control(controls(), state, click);
+        let can_start = state.argument(BATTERY) > 0.5 && state.argument(APU) > 0.5;
+        if argument == Some(ENGINE_START)
+            && click.value > 0.5
+            && can_start
+            && self.starting.is_none()
+            && !self.engine_running
+        {
+            self.starting = Some(0.0);
+            state.set_argument(ENGINE_START_LIGHT, 1.0);
+        }
+    }
+
+    fn on_lockon_command(&mut self, _state: &mut State, command: i32) {
+        if command == LEFT_ENGINE_STOP {
+            self.starting = None;
+            self.engine_running = false;
+        }
+    }
+
+    fn step(&mut self, state: &mut State, dt: f32) {
+        if state.argument(CANOPY_CLOSE) > 0.5 {
+            self.canopy = approach(self.canopy, 0.0, CANOPY_RATE, dt);
+        } else if state.argument(CANOPY_OPEN) > 0.5 {
+            self.canopy = approach(self.canopy, 1.0, CANOPY_RATE, dt);
+        }
+        state.set_cockpit_param(CANOPY_POSITION_PARAM, self.canopy);
+
+        if let Some(elapsed) = self.starting.as_mut() {
+            *elapsed += dt;
+            if *elapsed >= START_TIME {
+                self.starting = None;
+                self.engine_running = true;
+            }
+        }
+        let target_rpm = if self.engine_running || self.starting.is_some() {
+            IDLE_RPM
+        } else {
+            0.0
+        };
+        self.rpm = approach(self.rpm, target_rpm, IDLE_RPM / START_TIME, dt);
+        state.set_cockpit_param(ENGINE_RPM_PARAM, self.rpm);
+
+        let light = if self.starting.is_some() { 1.0 } else { 0.0 };
+        state.set_argument(ENGINE_START_LIGHT, light);
+    }
+}
@@ -0,0 +1,71 @@
+//! Simple deterministic models of the aircraft yawe automates.
+//!
+//! They are nowhere near the real systems: just enough behavior, driven by
+//! model time, for the startup procedures to see the cockpit respond the way
+//! they expect.
+
+pub mod f16c50;
+pub mod mig21bis;
+
+use crate::{Click, State};
+use switch_schema::{Info, SwitchFile};
+
+/// A cockpit control: the device and command(s) that move it, and the
+/// argument that reports its position.
+struct Control {
+    device_id: i32,
+    commands: Vec<i32>,
+    argument: i32,
+}
+
+/// Every control in one of yawe's switch tables, the files in its
+/// `resources/switches`. Values that can only be read have no control.
+fn controls(table: &str) -> Vec<Control> {
+    let file: SwitchFile = serde_json::from_str(table).expect("invalid switch table");
+    file.switches
+        .iter()
+        .filter_map(|switch| {
+            let commands = match switch.info {
+                Info::FloatValue { .. } => return None,
+                Info::SpringLoaded3Pos {
+                    command_down,
+                    command_up,
+                    ..
+                }
+                | Info::DualCommand3Pos {
+                    command_down,
+                    command_up,
+                    ..
+                } => vec![command_down, command_up],
+                info => vec![info.command()],
+            };
+            Some(Control {
+                device_id: switch.info.device_id(),
+                commands,
+                argument: switch.info.argument(),
+            })
+        })
+        .collect()
+}
+
+/// Moves the argument of the control `click` acts on to the clicked value.
+/// Returns the argument, or `None` if no control in `controls` matches.
+fn move_control(controls: &[Control], state: &mut State, click: &Click) -> Option<i32> {
+    let control = controls
+        .iter()
+        .find(|c| c.device_id == click.device_id && c.commands.contains(&click.command))?;
+    state.set_argument(control.argument, click.value);
+    Some(control.argument)
+}
+
+/// Moves `current` towards `target` by at most `rate * dt`.
+fn approach(current: f32, target: f32, rate: f32, dt: f32) -> f32 {
+    let step = rate * dt;
+    if (target - current).abs() <= step {
+        target
+    } else if target > current {
+        current + step
+    } else {
+        current - step
+    }
+}
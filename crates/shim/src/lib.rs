@@ -1,53 +1,220 @@
+use config::abi;
+use libloading::Library;
 use log::LevelFilter;
-use mlua::prelude::{LuaResult, LuaTable};
-use mlua::Lua;
-use std::os::windows::ffi::OsStrExt;
-use std::path::Path;
-use windows::Win32::Foundation::{BOOL, FARPROC, HMODULE};
-use windows::Win32::System::LibraryLoader::{FreeLibrary, GetProcAddress, LoadLibraryW};
+use mlua::prelude::{LuaFunction, LuaResult, LuaTable, LuaValue};
+use mlua::{lua_State, Lua, Variadic};
+use std::cell::Cell;
+use std::os::raw::c_int;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
 struct LibState {
-    lib: HMODULE,
-    start: ProcWrapper<extern "C" fn(&Lua, config::Config) -> i32>,
-    on_frame: ProcWrapper<extern "C" fn(&Lua) -> i32>,
-    on_frame_export: ProcWrapper<extern "C" fn(&Lua) -> i32>,
-    on_simulation_pause: ProcWrapper<extern "C" fn(&Lua) -> i32>,
-    on_simulation_resume: ProcWrapper<extern "C" fn(&Lua) -> i32>,
-    stop: ProcWrapper<extern "C" fn(&Lua) -> i32>,
+    start: abi::StartFn,
+    on_frame: abi::CallbackFn,
+    on_frame_export: abi::CallbackFn,
+    on_simulation_pause: abi::CallbackFn,
+    on_simulation_resume: abi::CallbackFn,
+    on_player_change_slot: abi::PlayerChangeSlotFn,
+    on_game_event: abi::GameEventFn,
+    on_simulation_stop: abi::CallbackFn,
+    stop: abi::CallbackFn,
+    detach: abi::CallbackFn,
+    /// Whether `on_frame_export` has been forwarded, which makes the library
+    /// wrap the export environment too.
+    exported: bool,
+    lib: Library,
+    /// The copy `lib` was loaded from.
+    copy: PathBuf,
+    /// What the library was started with, to start a new build with.
+    config: config::Config,
+    watch: Watch,
 }
 
 static mut LIB_STATE: Option<LibState> = None;
 
-struct ProcWrapper<T> {
-    proc: FARPROC,
-    _pd: std::marker::PhantomData<T>,
+/// A stopped build that the game GUI environment has let go of, but the export
+/// one hasn't yet, see `close_library()`.
+struct Retired {
+    detach: abi::CallbackFn,
+    lib: Library,
+    copy: PathBuf,
 }
 
-impl<T> std::ops::Deref for ProcWrapper<T> {
-    type Target = T;
-    fn deref(&self) -> &T {
-        assert!(self.proc.is_some());
-        unsafe { &*((&self.proc) as *const FARPROC as *const T) }
+static mut RETIRED: Vec<Retired> = Vec::new();
+
+/// Builds that are loaded, the running one and those not unloaded yet.
+static LOADED: AtomicU32 = AtomicU32::new(0);
+
+/// The function `lib` exports as `sym`, which must be of type `T`.
+fn load_export<T: Copy>(lib: &Library, path: &Path, sym: &[u8]) -> Result<T, String> {
+    match unsafe { lib.get::<T>(sym) } {
+        Ok(export) => Ok(*export),
+        Err(_) => Err(format!(
+            "{path:?} doesn't export `{}`",
+            String::from_utf8_lossy(sym)
+        )),
+    }
+}
+
+/// Watches the library for a new build.
+struct Watch {
+    path: PathBuf,
+    modified: SystemTime,
+    /// When the library was last seen written, if it has been since it was
+    /// loaded.
+    changed: Option<SystemTime>,
+    next_check: Instant,
+}
+
+impl Watch {
+    const PERIOD: Duration = Duration::from_secs(1);
+
+    fn new(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            modified: std::fs::metadata(path)?.modified()?,
+            changed: None,
+            next_check: Instant::now() + Self::PERIOD,
+        })
+    }
+
+    /// Whether a new build has been written, and left alone for a check
+    /// period so that the linker is done with it.
+    fn poll(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next_check {
+            return false;
+        }
+        self.next_check = now + Self::PERIOD;
+        let Ok(modified) = std::fs::metadata(&self.path).and_then(|m| m.modified()) else {
+            return false;
+        };
+        if modified == self.modified {
+            self.changed = None;
+            return false;
+        }
+        let settled = self.changed == Some(modified);
+        self.changed = Some(modified);
+        settled
     }
 }
 
-fn load_export<T>(lib: HMODULE, sym: &[u8]) -> ProcWrapper<T> {
-    use windows::core::PCSTR;
+/// Copies the library at `path` to a file of its own, which is what gets
+/// loaded so that cargo can still overwrite the original.
+fn copy_library(path: &Path) -> std::io::Result<PathBuf> {
+    static COPIES: AtomicU32 = AtomicU32::new(0);
+    let dir = std::env::temp_dir().join("yawe");
+    std::fs::create_dir_all(&dir)?;
+    let prefix = copy_prefix(std::process::id());
+    remove_copies(&dir, &prefix)?;
+    let copies = COPIES.fetch_add(1, Ordering::SeqCst);
+    let copy = dir.join(format!(
+        "{prefix}{copies}.{}",
+        std::env::consts::DLL_EXTENSION
+    ));
+    std::fs::copy(path, &copy)?;
+    Ok(copy)
+}
+
+/// How the copies the process with id `pid` loads are named.
+fn copy_prefix(pid: u32) -> String {
+    format!("yawe-{pid}-")
+}
+
+/// Removes the copies in `dir` whose names start with `prefix`, which only
+/// this process's are, as other DCS instances may still be using theirs.
+/// Copies that are still loaded can't be removed, which is fine.
+fn remove_copies(dir: &Path, prefix: &str) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(prefix) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Loads a copy of the library at `path` if it speaks the same ABI as the
+/// shim, otherwise explains why not.
+fn open_library(path: &Path, config: config::Config) -> Result<LibState, String> {
+    let watch = Watch::new(path).map_err(|e| format!("Couldn't read {path:?}: {e}"))?;
+    let copy = copy_library(path).map_err(|e| format!("Couldn't copy {path:?}: {e}"))?;
+    log::info!("Loading {path:?} from {copy:?}");
+    let path = copy.as_path();
+    let lib = unsafe { Library::new(path) }.map_err(|e| format!("Couldn't load {path:?}: {e}"))?;
 
-    assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<FARPROC>());
+    let version: abi::VersionFn = load_export(&lib, path, abi::VERSION_SYMBOL).map_err(|e| {
+        format!(
+            "{e}, so it predates the versioned interface. Rebuild yawe and yawe_shim from \
+             the same sources."
+        )
+    })?;
+    let version = unsafe { version() };
+    if version != abi::VERSION {
+        return Err(format!(
+            "{path:?} speaks ABI version {version}, but this shim speaks version {}. \
+             Rebuild yawe and yawe_shim from the same sources.",
+            abi::VERSION
+        ));
+    }
+
+    let state = LibState {
+        detach: load_export(&lib, path, b"detach")?,
+        start: load_export(&lib, path, b"start")?,
+        on_frame: load_export(&lib, path, b"on_frame")?,
+        on_frame_export: load_export(&lib, path, b"on_frame_export")?,
+        on_simulation_pause: load_export(&lib, path, b"on_simulation_pause")?,
+        on_simulation_resume: load_export(&lib, path, b"on_simulation_resume")?,
+        on_player_change_slot: load_export(&lib, path, b"on_player_change_slot")?,
+        on_game_event: load_export(&lib, path, b"on_game_event")?,
+        on_simulation_stop: load_export(&lib, path, b"on_simulation_stop")?,
+        stop: load_export(&lib, path, b"stop")?,
+        exported: false,
+        config,
+        watch,
+        lib,
+        copy,
+    };
+    let loaded = LOADED.fetch_add(1, Ordering::SeqCst) + 1;
+    log::info!("{loaded} builds of yawe are loaded");
+    Ok(state)
+}
 
-    let terminated = std::ffi::CString::new(sym).expect("Null bytes in sym");
-    let pointer = unsafe { GetProcAddress(lib, PCSTR(terminated.as_ptr() as *const u8)) };
-    ProcWrapper {
-        proc: pointer,
-        _pd: std::marker::PhantomData,
+/// Unloads a build that no Lua state points into anymore, and removes the
+/// copy it was loaded from.
+fn unload_library(lib: Library, copy: &Path) {
+    if let Err(e) = lib.close() {
+        log::warn!("Couldn't unload {copy:?}: {e}");
+        return;
     }
+    let loaded = LOADED.fetch_sub(1, Ordering::SeqCst) - 1;
+    log::info!("Unloaded {copy:?}, {loaded} builds of yawe are loaded");
+    let _ = std::fs::remove_file(copy);
 }
 
-fn load_library(p: &Path) -> windows::core::Result<HMODULE> {
-    use windows::core::PCWSTR;
-    let wide_path: Vec<u16> = p.as_os_str().encode_wide().chain(Some(0)).collect();
-    unsafe { LoadLibraryW(PCWSTR(wide_path.as_ptr())) }
+/// The raw state behind a `Lua`, kept in its app data.
+struct RawState(*mut lua_State);
+
+/// The raw state behind `lua`, which is what crosses into the library. It is
+/// captured on the first call from each environment.
+fn raw_state(lua: &Lua) -> LuaResult<*mut lua_State> {
+    thread_local! {
+        static CAPTURED: Cell<*mut lua_State> = const { Cell::new(std::ptr::null_mut()) };
+    }
+    // mlua doesn't hand out the state, but a C function is called with it
+    unsafe extern "C" fn capture(state: *mut lua_State) -> c_int {
+        CAPTURED.with(|captured| captured.set(state));
+        0
+    }
+    if let Some(state) = lua.app_data_ref::<RawState>() {
+        return Ok(state.0);
+    }
+    let capture = unsafe { lua.create_c_function(capture)? };
+    capture.call::<_, ()>(())?;
+    let state = CAPTURED.with(|captured| captured.get());
+    lua.set_app_data(RawState(state));
+    Ok(state)
 }
 
 fn setup_logging(write_dir: &str) {
@@ -56,112 +223,337 @@ fn setup_logging(write_dir: &str) {
     let p = logdir.join("shim.log");
     simple_logging::log_to_file(p.as_os_str(), LevelFilter::Info)
         .expect("Unable to create log file");
+    log_panics::init();
 }
 
-fn close_library() -> BOOL {
-    let free_result = unsafe { FreeLibrary(LIB_STATE.as_ref().unwrap().lib) };
-    log::info!("Freeing library result: {:?}", free_result);
-    unsafe { LIB_STATE = None };
-    free_result
+/// Stops the running library and lets go of it, from the game GUI
+/// environment. Returns what its `stop` returned.
+///
+/// The library wraps the Lua states in its own `mlua::Lua`, which leaves
+/// finalizers pointing into its code in them, so it may only be unloaded once
+/// its `detach` has removed them from every state it was given. The export
+/// environment is only reachable from `on_frame_export`, so if the library
+/// was given that one too, it is unloaded from there, see `release_retired()`.
+fn close_library(lua: &Lua) -> LuaResult<i32> {
+    let state = raw_state(lua)?;
+    let Some(old) = (unsafe { LIB_STATE.take() }) else {
+        return Ok(-1);
+    };
+    let stop_result = unsafe { (old.stop)(state) };
+    log::info!("Stopping the library returned {stop_result}");
+    unsafe { (old.detach)(state) };
+    if old.exported {
+        unsafe {
+            RETIRED.push(Retired {
+                detach: old.detach,
+                lib: old.lib,
+                copy: old.copy,
+            })
+        };
+    } else {
+        unload_library(old.lib, &old.copy);
+    }
+    Ok(stop_result)
 }
 
-#[no_mangle]
-pub fn start(lua: &Lua, config: config::Config) -> LuaResult<i32> {
-    setup_logging(&config.write_dir);
-    log::info!("Log file created.");
-    let dll_path = Path::new(config.dll_path.as_str()).join("yawe.dll");
-    let lib = load_library(&dll_path).unwrap();
-    let ls = Some(LibState {
-        lib: lib,
-        start: load_export(lib, b"start"),
-        on_frame: load_export(lib, b"on_frame"),
-        on_frame_export: load_export(lib, b"on_frame_export"),
-        on_simulation_pause: load_export(lib, b"on_simulation_pause"),
-        on_simulation_resume: load_export(lib, b"on_simulation_resume"),
-        stop: load_export(lib, b"stop"),
-    });
-    unsafe { LIB_STATE = ls };
+/// Detaches the stopped builds from the export environment and unloads them.
+/// Detaching a build from a state it never wrapped, as when a new mission
+/// started since it was stopped, does nothing.
+fn release_retired(lua: &Lua) -> LuaResult<()> {
+    if unsafe { RETIRED.is_empty() } {
+        return Ok(());
+    }
+    let state = raw_state(lua)?;
+    for old in unsafe { std::mem::take(&mut RETIRED) } {
+        unsafe { (old.detach)(state) };
+        unload_library(old.lib, &old.copy);
+    }
+    Ok(())
+}
+
+/// Set once an entry point has panicked, after which they only return errors
+/// until DCS is restarted and loads the shim afresh.
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Runs the body of an entry point, turning a panic into a Lua error. The panic
+/// hook logs the panic with a backtrace; the library is then stopped and the
+/// shim disabled, since either may be in any state. The stopped library stays
+/// loaded until DCS exits, as the shim no longer gets to detach it from both
+/// environments.
+fn guard<R>(lua: &Lua, name: &str, body: impl FnOnce() -> LuaResult<R>) -> LuaResult<R> {
+    if DISABLED.load(Ordering::SeqCst) {
+        return Err(mlua::Error::RuntimeError(String::from(
+            "yawe_shim is disabled after a panic until DCS is restarted, see shim.log",
+        )));
+    }
+    match std::panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(_) => {
+            DISABLED.store(true, Ordering::SeqCst);
+            log::error!("`{name}` panicked, yawe_shim is disabled until DCS is restarted");
+            if stop_after_panic(lua) {
+                Err(mlua::Error::RuntimeError(format!(
+                    "yawe_shim `{name}` panicked and stopped yawe, restart DCS to use yawe \
+                     again, see shim.log"
+                )))
+            } else {
+                Err(mlua::Error::RuntimeError(format!(
+                    "yawe_shim `{name}` panicked and couldn't stop yawe, which may keep running \
+                     until DCS is restarted, see shim.log"
+                )))
+            }
+        }
+    }
+}
+
+/// Stops the library, if one is running, after a panic in the shim. Returns
+/// whether nothing is left running.
+fn stop_after_panic(lua: &Lua) -> bool {
+    let Some(old) = (unsafe { LIB_STATE.take() }) else {
+        return true;
+    };
+    let stop = old.stop;
+    // the shim may be in any state, so stopping may panic too
+    let stopped = std::panic::catch_unwind(AssertUnwindSafe(|| -> LuaResult<i32> {
+        Ok(unsafe { stop(raw_state(lua)?) })
+    }));
+    // the Lua states still hold finalizers in its code
+    std::mem::forget(old.lib);
+    match stopped {
+        Ok(Ok(stop_result)) => {
+            log::info!("Stopping the library after the panic returned {stop_result}");
+            true
+        }
+        Ok(Err(e)) => {
+            log::error!("Couldn't stop the library after the panic: {e}");
+            false
+        }
+        Err(_) => {
+            log::error!("Stopping the library after the panic panicked too");
+            false
+        }
+    }
+}
+
+/// The `Name` of the aircraft the player is in, if any.
+fn current_aircraft(lua: &Lua) -> Option<String> {
+    let export: LuaTable = lua.globals().get("Export").ok()?;
+    let get_self_data: LuaFunction = export.get("LoGetSelfData").ok()?;
+    let self_data: LuaTable = get_self_data.call(()).ok()?;
+    self_data.get("Name").ok()
+}
+
+fn start_library(lua: &Lua, config: config::Config) -> LuaResult<i32> {
+    let dll_path = Path::new(config.dll_path.as_str()).join(libloading::library_filename("yawe"));
+    let encoded = abi::encode_config(&config);
+    let ls = match open_library(&dll_path, config) {
+        Ok(ls) => ls,
+        Err(reason) => {
+            log::error!("Refusing to start yawe: {reason}");
+            return Ok(-1);
+        }
+    };
+    unsafe { LIB_STATE = Some(ls) };
 
     let start = unsafe { &LIB_STATE.as_ref().unwrap().start };
-    let result = start(&lua, config);
+    let result = unsafe { start(raw_state(lua)?, abi::Bytes::new(&encoded)) };
 
     Ok(result)
 }
 
+/// Stops the running library and starts the new build in its place.
+fn reload_library(lua: &Lua) -> LuaResult<i32> {
+    let Some(old) = (unsafe { LIB_STATE.as_ref() }) else {
+        return Ok(-1);
+    };
+    log::info!("{:?} was rebuilt, reloading it", old.watch.path);
+    let mut config = old.config.clone();
+    close_library(lua)?;
+
+    config.aircraft = current_aircraft(lua).unwrap_or_default();
+    start_library(lua, config)
+}
+
+#[no_mangle]
+pub fn start(lua: &Lua, config: config::Config) -> LuaResult<i32> {
+    guard(lua, "start", || {
+        setup_logging(&config.write_dir);
+        log::info!("Log file created.");
+        start_library(lua, config)
+    })
+}
+
 #[no_mangle]
 pub fn on_frame(lua: &Lua, _: ()) -> LuaResult<i32> {
-    let maybe_lib_state = unsafe { &LIB_STATE.as_ref() };
-    if let None = &maybe_lib_state {
-        return Ok(-1);
-    }
-    let on_frame = &maybe_lib_state.unwrap().on_frame;
-    let result = on_frame(&lua);
-    if result < 0 {
-        log::info!("Development: user asked to close library\n");
-        close_library();
-    }
-    Ok(result)
+    guard(lua, "on_frame", || {
+        let Some(lib_state) = (unsafe { LIB_STATE.as_mut() }) else {
+            return Ok(-1);
+        };
+        if lib_state.watch.poll() {
+            return reload_library(lua);
+        }
+        let result = unsafe { (lib_state.on_frame)(raw_state(lua)?) };
+        if result < 0 {
+            log::info!("Development: user asked to close library\n");
+            close_library(lua)?;
+        }
+        Ok(result)
+    })
 }
 
 #[no_mangle]
 pub fn on_frame_export(lua: &Lua, _: ()) -> LuaResult<i32> {
-    let maybe_lib_state = unsafe { &LIB_STATE.as_ref() };
+    guard(lua, "on_frame_export", || {
+        release_retired(lua)?;
+        let maybe_lib_state = unsafe { &mut LIB_STATE.as_mut() };
 
-    if let None = &maybe_lib_state {
-        return Ok(-1);
-    }
+        if let None = &maybe_lib_state {
+            return Ok(-1);
+        }
 
-    let on_frame = &maybe_lib_state.unwrap().on_frame_export;
-    let result = on_frame(&lua);
-    Ok(result)
+        let lib_state = maybe_lib_state.as_mut().unwrap();
+        lib_state.exported = true;
+        let result = unsafe { (lib_state.on_frame_export)(raw_state(lua)?) };
+        Ok(result)
+    })
 }
 
 #[no_mangle]
 pub fn on_simulation_pause(lua: &Lua, _: ()) -> LuaResult<i32> {
-    if !unsafe { LIB_STATE.is_some() } {
-        return Ok(-1);
-    }
-    let on_simulation_pause = unsafe { &LIB_STATE.as_ref().unwrap().on_simulation_pause };
-    let result = on_simulation_pause(&lua);
-    Ok(result)
+    guard(lua, "on_simulation_pause", || {
+        if !unsafe { LIB_STATE.is_some() } {
+            return Ok(-1);
+        }
+        let on_simulation_pause = unsafe { &LIB_STATE.as_ref().unwrap().on_simulation_pause };
+        let result = unsafe { on_simulation_pause(raw_state(lua)?) };
+        Ok(result)
+    })
 }
 
 #[no_mangle]
 pub fn on_simulation_resume(lua: &Lua, _: ()) -> LuaResult<i32> {
-    if !unsafe { LIB_STATE.is_some() } {
-        return Ok(-1);
-    }
-    let on_simulation_resume = unsafe { &LIB_STATE.as_ref().unwrap().on_simulation_resume };
-    let result = on_simulation_resume(&lua);
-    Ok(result)
+    guard(lua, "on_simulation_resume", || {
+        if !unsafe { LIB_STATE.is_some() } {
+            return Ok(-1);
+        }
+        let on_simulation_resume = unsafe { &LIB_STATE.as_ref().unwrap().on_simulation_resume };
+        let result = unsafe { on_simulation_resume(raw_state(lua)?) };
+        Ok(result)
+    })
+}
+
+#[no_mangle]
+pub fn on_player_change_slot(lua: &Lua, player_id: i32) -> LuaResult<i32> {
+    guard(lua, "on_player_change_slot", || {
+        if !unsafe { LIB_STATE.is_some() } {
+            return Ok(-1);
+        }
+        let on_player_change_slot = unsafe { &LIB_STATE.as_ref().unwrap().on_player_change_slot };
+        let result = unsafe { on_player_change_slot(raw_state(lua)?, player_id) };
+        Ok(result)
+    })
+}
+
+/// Forwards `onGameEvent(event, ...)`. DCS only passes numbers, strings and
+/// nil after the event name.
+#[no_mangle]
+pub fn on_game_event(lua: &Lua, (name, args): (String, Variadic<LuaValue>)) -> LuaResult<i32> {
+    guard(lua, "on_game_event", || {
+        if !unsafe { LIB_STATE.is_some() } {
+            return Ok(-1);
+        }
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                LuaValue::Integer(i) => serde_json::Value::from(*i),
+                LuaValue::Number(n) => serde_json::Value::from(*n),
+                LuaValue::String(s) => serde_json::Value::from(s.to_string_lossy()),
+                _ => serde_json::Value::Null,
+            })
+            .collect();
+        let event =
+            serde_json::to_vec(&abi::GameEvent { name, args }).map_err(mlua::Error::external)?;
+        let on_game_event = unsafe { &LIB_STATE.as_ref().unwrap().on_game_event };
+        let result = unsafe { on_game_event(raw_state(lua)?, abi::Bytes::new(&event)) };
+        Ok(result)
+    })
+}
+
+#[no_mangle]
+pub fn on_simulation_stop(lua: &Lua, _: ()) -> LuaResult<i32> {
+    guard(lua, "on_simulation_stop", || {
+        if !unsafe { LIB_STATE.is_some() } {
+            return Ok(-1);
+        }
+        let on_simulation_stop = unsafe { &LIB_STATE.as_ref().unwrap().on_simulation_stop };
+        let result = unsafe { on_simulation_stop(raw_state(lua)?) };
+        Ok(result)
+    })
 }
 
 #[no_mangle]
 pub fn stop(lua: &Lua, _: ()) -> LuaResult<i32> {
-    if !unsafe { LIB_STATE.is_some() } {
-        return Ok(-1);
-    }
-    let stop = unsafe { &LIB_STATE.as_ref().unwrap().stop };
-    let stop_result = stop(&lua);
-    log::info!("Stopping main library returned {:?}", stop_result);
-    let free_result = close_library();
-    Ok(free_result.as_bool().into())
+    guard(lua, "stop", || {
+        if !unsafe { LIB_STATE.is_some() } {
+            return Ok(-1);
+        }
+        close_library(lua)
+    })
 }
 
 #[mlua::lua_module]
 pub fn yawe_shim(lua: &Lua) -> LuaResult<LuaTable> {
-    let exports = lua.create_table()?;
-    exports.set("start", lua.create_function(start)?)?;
-    exports.set("on_frame", lua.create_function(on_frame)?)?;
-    exports.set("on_frame_export", lua.create_function(on_frame_export)?)?;
-    exports.set(
-        "on_simulation_pause",
-        lua.create_function(on_simulation_pause)?,
-    )?;
-    exports.set(
-        "on_simulation_resume",
-        lua.create_function(on_simulation_resume)?,
-    )?;
-    exports.set("stop", lua.create_function(stop)?)?;
-    Ok(exports)
+    guard(lua, "yawe_shim", || {
+        let exports = lua.create_table()?;
+        exports.set("start", lua.create_function(start)?)?;
+        exports.set("on_frame", lua.create_function(on_frame)?)?;
+        exports.set("on_frame_export", lua.create_function(on_frame_export)?)?;
+        exports.set(
+            "on_simulation_pause",
+            lua.create_function(on_simulation_pause)?,
+        )?;
+        exports.set(
+            "on_simulation_resume",
+            lua.create_function(on_simulation_resume)?,
+        )?;
+        exports.set(
+            "on_player_change_slot",
+            lua.create_function(on_player_change_slot)?,
+        )?;
+        exports.set("on_game_event", lua.create_function(on_game_event)?)?;
+        exports.set(
+            "on_simulation_stop",
+            lua.create_function(on_simulation_stop)?,
+        )?;
+        exports.set("stop", lua.create_function(stop)?)?;
+        Ok(exports)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{copy_prefix, remove_copies};
+
+    #[test]
+    fn test_remove_copies_leaves_other_processes_alone() {
+        let dir = std::env::temp_dir().join(format!("yawe-shim-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "yawe-7-0.dll",
+            "yawe-7-1.dll",
+            "yawe-71-0.dll",
+            "yawe-8-0.dll",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        remove_copies(&dir, &copy_prefix(7)).unwrap();
+        let mut left: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["yawe-71-0.dll", "yawe-8-0.dll"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
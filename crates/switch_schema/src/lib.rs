@@ -0,0 +1,169 @@
+//! The format of the cockpit switch table files read by yawe, one per
+//! aircraft. See `yawe::dcs::switches` for how they are loaded and checked.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+
+/// Bump when the format changes in a way older DLLs can't read.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How a switch is operated, and the ids needed to operate it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Info {
+    Toggle {
+        device_id: i32,
+        command: i32,
+        argument: i32,
+    },
+    MultiToggle {
+        device_id: i32,
+        command: i32,
+        argument: i32,
+    },
+    Momentary {
+        device_id: i32,
+        command: i32,
+        argument: i32,
+    },
+    /// Moved down with `command_down` and up with `command_up`, springs back
+    /// to the middle.
+    SpringLoaded3Pos {
+        device_id: i32,
+        command_down: i32,
+        command_up: i32,
+        argument: i32,
+    },
+    DualCommand3Pos {
+        device_id: i32,
+        command_down: i32,
+        command_up: i32,
+        argument: i32,
+    },
+    /// Can only be read, e.g. a gauge.
+    FloatValue { argument: i32 },
+    Axis {
+        device_id: i32,
+        command: i32,
+        argument: i32,
+    },
+}
+
+impl Info {
+    /// 0 for values that can only be read.
+    pub fn device_id(&self) -> i32 {
+        match *self {
+            Info::Toggle { device_id, .. }
+            | Info::MultiToggle { device_id, .. }
+            | Info::Momentary { device_id, .. }
+            | Info::SpringLoaded3Pos { device_id, .. }
+            | Info::DualCommand3Pos { device_id, .. }
+            | Info::Axis { device_id, .. } => device_id,
+            Info::FloatValue { .. } => 0,
+        }
+    }
+
+    /// The command that moves the switch, the downwards one for three
+    /// position switches. 0 for values that can only be read.
+    pub fn command(&self) -> i32 {
+        match *self {
+            Info::Toggle { command, .. }
+            | Info::MultiToggle { command, .. }
+            | Info::Momentary { command, .. }
+            | Info::Axis { command, .. } => command,
+            Info::SpringLoaded3Pos { command_down, .. }
+            | Info::DualCommand3Pos { command_down, .. } => command_down,
+            Info::FloatValue { .. } => 0,
+        }
+    }
+
+    pub fn argument(&self) -> i32 {
+        match *self {
+            Info::Toggle { argument, .. }
+            | Info::MultiToggle { argument, .. }
+            | Info::Momentary { argument, .. }
+            | Info::SpringLoaded3Pos { argument, .. }
+            | Info::DualCommand3Pos { argument, .. }
+            | Info::FloatValue { argument }
+            | Info::Axis { argument, .. } => argument,
+        }
+    }
+}
+
+/// One entry of a switch table file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwitchDef {
+    pub name: String,
+    #[serde(flatten)]
+    pub info: Info,
+    /// Argument values of the switch's positions, by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub positions: BTreeMap<String, f32>,
+    /// The tooltip DCS shows for the switch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+/// The contents of a switch table file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwitchFile {
+    pub version: u32,
+    pub aircraft: String,
+    pub switches: Vec<SwitchDef>,
+}
+
+impl SwitchFile {
+    /// Formats the file the way the checked in tables are, one switch per
+    /// line so diffs stay readable.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let mut lines = Vec::with_capacity(self.switches.len());
+        for switch in &self.switches {
+            lines.push(format!("        {}", to_spaced_string(switch)?));
+        }
+        Ok(format!(
+            "{{\n    \"version\": {},\n    \"aircraft\": {},\n    \"switches\": [\n{}\n    ]\n}}\n",
+            self.version,
+            serde_json::to_string(&self.aircraft)?,
+            lines.join(",\n")
+        ))
+    }
+}
+
+/// Compact JSON, but with a space after every `,` and `:`.
+struct Spaced;
+
+impl serde_json::ser::Formatter for Spaced {
+    fn begin_array_value<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        if first {
+            Ok(())
+        } else {
+            writer.write_all(b", ")
+        }
+    }
+
+    fn begin_object_key<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.begin_array_value(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+}
+
+fn to_spaced_string<T: Serialize>(value: &T) -> serde_json::Result<String> {
+    let mut out = Vec::new();
+    value.serialize(&mut serde_json::Serializer::with_formatter(
+        &mut out, Spaced,
+    ))?;
+    // serde_json only writes valid UTF-8
+    Ok(String::from_utf8(out).unwrap())
+}
@@ -0,0 +1,30 @@
+-- Startup for a made up Yak-52 cockpit, as an example of an aircraft script
+-- and for the tests. Copy scripts like this into the `aircraft` directory
+-- under `lua_path`, named after the aircraft's type in DCS.
+
+display_name = "Yak-52"
+
+switches = {
+    Battery = { device = 1, command = 3001, argument = 101 },
+    Starter = { device = 2, command = 3005, argument = 102 },
+}
+
+local function battery_voltage()
+    return yawe.param("VOLTAGE") or 0
+end
+
+function startup()
+    yawe.progress(0.1, "Turning on battery")
+    yawe.set_switch("Battery", 1)
+    yawe.wait_until(function() return battery_voltage() > 20 end, 5, "battery voltage")
+
+    yawe.progress(0.4, "Waiting for the voltage to settle")
+    yawe.wait(2)
+
+    yawe.progress(0.6, "Starting engine")
+    yawe.set_switch("Starter", 1)
+    yawe.wait_until(function()
+        return yawe.indication(5, "Panel", "Status") == "READY"
+    end, 10, "engine start")
+    yawe.log("engine running")
+end
@@ -1,21 +1,34 @@
+use crate::clock::SimClock;
 use crate::dcs;
-use crate::dcs::AircraftFsm;
+use crate::dcs::events::{self, GameEvent};
+use crate::dcs::recording::{self, Channel, Recorder};
+use crate::dcs::safety::{FlightState, Verdict};
+use crate::dcs::subscriptions::{IndicationChange, Subscriptions};
+use crate::dcs::{AircraftFsm, CockpitParams};
 use crate::gui;
 use mlua::Lua;
 use offload::{PackagedTask, TaskSender};
 use rsevents::Awaitable;
 use rsevents::{AutoResetEvent, EventState};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
 use std::thread::JoinHandle;
 
 static AWAKEN_APP_THREAD: AutoResetEvent = AutoResetEvent::new(EventState::Unset);
 static STOP_APP_THREAD: AtomicBool = AtomicBool::new(false);
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FsmMessage {
     StartupAircraft,
-    _InterruptAircraftStart,
+    ShutdownAircraft,
+    /// Stops a startup or shutdown in progress.
+    InterruptAircraftStart,
+    /// The simulation was paused. The FSM isn't run again until `Resumed`.
+    Paused,
+    Resumed,
     None,
 }
 
@@ -23,6 +36,17 @@ pub enum FsmMessage {
 pub enum AppMessage {
     AircraftChanged(dcs::AircraftId),
     FsmEvent(FsmMessage),
+    /// The user confirmed a request the interlocks held back. It is still
+    /// refused if the aircraft is now doing something the interlocks refuse.
+    Confirmed(FsmMessage),
+    /// The player moved to another slot, possibly in the same aircraft type.
+    SlotChanged,
+    GameEvent(GameEvent),
+    SimulationStopped,
+    /// An indication element the FSM subscribed to changed.
+    IndicationChanged(IndicationChange),
+    /// The cockpit params changed while the FSM wants them.
+    CockpitParamsChanged(Arc<CockpitParams>),
 }
 
 pub struct App {
@@ -32,19 +56,22 @@ pub struct App {
     ownship_type: dcs::AircraftId,
     rx_from_dcs_gamegui: Option<Receiver<PackagedTask<Lua>>>,
     rx_from_dcs_export: Option<Receiver<PackagedTask<Lua>>>,
-    tx_time: Sender<f32>,
+    subscriptions: Subscriptions,
+    clock: SimClock,
     paused: bool,
 }
 
 impl App {
     // Start the main application (scoped) thread, return an interface handle to
-    // allow the outside world to talk to it.
-    pub fn new() -> Self {
+    // allow the outside world to talk to it. With a `recorder`, all Lua traffic
+    // and FSM events are recorded for offline replay.
+    pub fn new(recorder: Option<Arc<Recorder>>) -> Self {
         //
         let (tx_to_dcs_gamegui, rx_from_dcs_gamegui) = TaskSender::new();
         let (tx_to_dcs_export, rx_from_dcs_export) = TaskSender::new();
         let (tx_to_app, rx_from_gui) = channel::<AppMessage>();
-        let (tx_time, rx_time) = channel::<f32>();
+        let clock = SimClock::default();
+        let subscriptions = Subscriptions::default();
         let (app_runner, runner_from_gui) = TaskSender::<(TaskSender<Lua>, TaskSender<Lua>)>::new();
 
         let gui = gui::Handle::new(
@@ -55,6 +82,11 @@ impl App {
         );
 
         let handle = gui.tx_handle();
+        let app_clock = clock.clone();
+        let app_subscriptions = subscriptions.clone();
+        if let Some(recorder) = &recorder {
+            recorder.install();
+        }
 
         let thread = std::thread::Builder::new()
             .name("yawe-app".to_string())
@@ -65,27 +97,33 @@ impl App {
                     handle,
                     rx_from_gui,
                     runner_from_gui,
-                    rx_time,
+                    app_clock,
+                    app_subscriptions,
+                    recorder,
                 )
             })
             .unwrap();
 
-        let me = Self {
+        Self {
             thread: Some(thread),
             _tx_to_app: tx_to_app.clone(),
-            gui: gui,
+            gui,
             ownship_type: dcs::AircraftId::Unknown(String::from("")),
             rx_from_dcs_gamegui: Some(rx_from_dcs_gamegui),
             rx_from_dcs_export: Some(rx_from_dcs_export),
-            tx_time: tx_time,
+            subscriptions,
+            clock,
             paused: false,
-        };
-        me
+        }
     }
 
     fn set_paused(&mut self) {
         self.paused = true;
         self.gui.set_paused();
+        let _ = self
+            ._tx_to_app
+            .send(AppMessage::FsmEvent(FsmMessage::Paused));
+        AWAKEN_APP_THREAD.set();
     }
 
     fn set_unpaused(&mut self) {
@@ -94,9 +132,19 @@ impl App {
         }
         self.paused = false;
         self.gui.set_unpaused();
+        let _ = self
+            ._tx_to_app
+            .send(AppMessage::FsmEvent(FsmMessage::Resumed));
+        AWAKEN_APP_THREAD.set();
     }
 
-    pub fn on_start(&mut self, lua: &Lua) -> i32 {
+    /// `aircraft` is the `Name` of the aircraft the player is already in, or
+    /// empty. It is only known when the library was reloaded mid-mission.
+    pub fn on_start(&mut self, lua: &Lua, aircraft: &str) -> i32 {
+        if !aircraft.is_empty() {
+            log::info!("Starting in {aircraft}");
+            self.set_ownship_type(dcs::str_to_ship_enum(aircraft));
+        }
         if dcs::is_paused(lua).unwrap_or(false) {
             log::info!("Game is starting paused");
             self.set_paused();
@@ -104,7 +152,17 @@ impl App {
         0
     }
 
+    fn set_ownship_type(&mut self, ownship_type: dcs::AircraftId) {
+        log::info!("Got new aircraft type {:?}", ownship_type);
+        self.ownship_type = ownship_type.clone();
+        if self.gui.is_running() {
+            self.gui.set_ownship_type(ownship_type.clone());
+        }
+        self.send(AppMessage::AircraftChanged(ownship_type));
+    }
+
     pub fn on_frame(&mut self, lua: &Lua) -> i32 {
+        recording::begin_frame();
         let ownship_type = match dcs::get_ownship_type(lua) {
             Ok(t) => t,
             Err(_) => dcs::AircraftId::Unknown(String::from("")),
@@ -114,27 +172,18 @@ impl App {
             return -1;
         };
 
-        let _ = self.tx_time.send(sim_time);
+        self.clock.set(sim_time);
 
         if self.ownship_type != ownship_type {
-            log::info!("Got new aircraft type {:?}", ownship_type);
-            self.ownship_type = ownship_type.clone();
-            if self.gui.is_running() {
-                self.gui.set_ownship_type(ownship_type.clone());
-            }
-            let _ = self
-                ._tx_to_app
-                .send(AppMessage::AircraftChanged(ownship_type));
-        }
-
-        // todo: implement timeout, but for now just process all pending messages ASAP.
-        while let Ok(_) = self
-            .rx_from_dcs_gamegui
-            .as_ref()
-            .unwrap()
-            .try_recv()
-            .map(|job| job(lua))
-        {}
+            self.set_ownship_type(ownship_type);
+        }
+
+        run_jobs(
+            self.rx_from_dcs_gamegui.as_ref().unwrap(),
+            lua,
+            Channel::Gamegui,
+        );
+        self.subscriptions.sample_arguments(lua);
         AWAKEN_APP_THREAD.set();
 
         if self.gui.is_running() {
@@ -145,17 +194,55 @@ impl App {
     }
 
     pub fn on_frame_export(&mut self, lua: &Lua) -> i32 {
-        // todo: implement timeout, but for now just process all pending messages ASAP.
-        while let Ok(_) = self
-            .rx_from_dcs_export
-            .as_ref()
-            .unwrap()
-            .try_recv()
-            .map(|job| job(lua))
-        {}
+        run_jobs(
+            self.rx_from_dcs_export.as_ref().unwrap(),
+            lua,
+            Channel::Export,
+        );
+        for change in self.subscriptions.sample(lua) {
+            self.send(AppMessage::IndicationChanged(change));
+        }
+        if let Some(params) = self.subscriptions.sample_cockpit_params(lua) {
+            self.send(AppMessage::CockpitParamsChanged(params));
+        }
+        0
+    }
+
+    pub fn on_player_change_slot(&mut self, lua: &Lua, player_id: i32) -> i32 {
+        match events::get_my_player_id(lua) {
+            Ok(my_id) if my_id == player_id => {
+                log::info!("Player changed slot");
+                self.send(AppMessage::SlotChanged);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Failed to get the player id: {e}"),
+        }
+        0
+    }
+
+    pub fn on_game_event(&mut self, lua: &Lua, event: &config::abi::GameEvent) -> i32 {
+        match GameEvent::parse(lua, &event.name, &event.args) {
+            Ok(Some(event)) => {
+                log::info!("Game event {event:?}");
+                self.send(AppMessage::GameEvent(event));
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Failed to parse game event {event:?}: {e}"),
+        }
+        0
+    }
+
+    pub fn on_simulation_stop(&mut self, _lua: &Lua) -> i32 {
+        log::info!("Simulation stopped");
+        self.send(AppMessage::SimulationStopped);
         0
     }
 
+    fn send(&self, msg: AppMessage) {
+        let _ = self._tx_to_app.send(msg);
+        AWAKEN_APP_THREAD.set();
+    }
+
     pub fn on_simulation_pause(&mut self, _lua: &Lua) -> i32 {
         log::info!("Simulation paused");
         self.set_paused();
@@ -177,32 +264,45 @@ impl App {
         self.gui.stop();
         log::info!("joining on thread finishing");
         thread_finish.unwrap().join().unwrap();
+        recording::uninstall();
         log::info!("App thread stopped");
     }
 }
 
+fn run_jobs(rx: &Receiver<PackagedTask<Lua>>, lua: &Lua, channel: Channel) {
+    // todo: implement timeout, but for now just process all pending messages ASAP.
+    while let Ok(job) = rx.try_recv() {
+        recording::in_channel(channel, || job(lua));
+    }
+}
+
 impl Default for App {
     fn default() -> Self {
-        Self::new()
+        Self::new(None)
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn app_thread_entry(
     sender_to_dcs_gamegui: TaskSender<Lua>,
     sender_to_dcs_export: TaskSender<Lua>,
     gui_handle: gui::TxHandle,
     rx_from_gui: Receiver<AppMessage>,
     runner_from_gui: Receiver<PackagedTask<(TaskSender<Lua>, TaskSender<Lua>)>>,
-    time_channel: Receiver<f32>,
+    clock: SimClock,
+    subscriptions: Subscriptions,
+    recorder: Option<Arc<Recorder>>,
 ) {
-    // need to dispatch between several
-    let mut fsm: Box<dyn AircraftFsm> = Box::new(dcs::EmptyFsm::new(
-        sender_to_dcs_gamegui.clone(),
-        sender_to_dcs_export.clone(),
-        gui_handle.clone(),
-    ));
-
-    let mut sim_time = 0 as f32;
+    let mut app = AppThread::new(
+        sender_to_dcs_gamegui,
+        sender_to_dcs_export,
+        gui_handle,
+        rx_from_gui,
+        runner_from_gui,
+        clock,
+        subscriptions,
+        recorder,
+    );
 
     loop {
         AWAKEN_APP_THREAD.wait();
@@ -212,34 +312,526 @@ fn app_thread_entry(
             return;
         }
 
-        sim_time = time_channel.try_recv().unwrap_or(sim_time);
+        if !app.on_wake() {
+            return;
+        }
+    }
+}
 
-        while let Ok(_) = runner_from_gui
-            .try_recv()
-            .map(|job| job(&(sender_to_dcs_gamegui.clone(), sender_to_dcs_export.clone())))
-        {
+struct AppThread {
+    sender_to_dcs_gamegui: TaskSender<Lua>,
+    sender_to_dcs_export: TaskSender<Lua>,
+    gui_handle: gui::TxHandle,
+    rx_from_gui: Receiver<AppMessage>,
+    runner_from_gui: Receiver<PackagedTask<(TaskSender<Lua>, TaskSender<Lua>)>>,
+    clock: SimClock,
+    /// Told what the FSM subscribes to whenever it has run, and where the
+    /// arguments read in `on_frame` are taken from.
+    subscriptions: Subscriptions,
+    recorder: Option<Arc<Recorder>>,
+    fsm: Box<dyn AircraftFsm>,
+    aircraft: dcs::AircraftId,
+    paused: bool,
+    /// Requests from the GUI that arrived while paused, handled on resume.
+    /// Other messages are handled as they come.
+    deferred: VecDeque<AppMessage>,
+}
+
+impl AppThread {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        sender_to_dcs_gamegui: TaskSender<Lua>,
+        sender_to_dcs_export: TaskSender<Lua>,
+        gui_handle: gui::TxHandle,
+        rx_from_gui: Receiver<AppMessage>,
+        runner_from_gui: Receiver<PackagedTask<(TaskSender<Lua>, TaskSender<Lua>)>>,
+        clock: SimClock,
+        subscriptions: Subscriptions,
+        recorder: Option<Arc<Recorder>>,
+    ) -> Self {
+        // need to dispatch between several
+        let fsm: Box<dyn AircraftFsm> = Box::new(dcs::EmptyFsm::new(
+            sender_to_dcs_gamegui.clone(),
+            sender_to_dcs_export.clone(),
+            gui_handle.clone(),
+        ));
+        Self {
+            sender_to_dcs_gamegui,
+            sender_to_dcs_export,
+            gui_handle,
+            rx_from_gui,
+            runner_from_gui,
+            clock,
+            subscriptions,
+            recorder,
+            fsm,
+            aircraft: dcs::AircraftId::Unknown(String::from("")),
+            paused: false,
+            deferred: VecDeque::new(),
+        }
+    }
+
+    /// Handles one wake up of the app thread: every message waiting, then a
+    /// run of the FSM. Returns false once it should stop.
+    fn on_wake(&mut self) -> bool {
+        // jobs from the GUI wait in their queue until the simulation resumes
+        if !self.paused {
+            while let Ok(job) = self.runner_from_gui.try_recv() {
+                job(&(
+                    self.sender_to_dcs_gamegui.clone(),
+                    self.sender_to_dcs_export.clone(),
+                ));
+            }
+        }
+        if let Some(values) = self.subscriptions.take_arguments() {
+            self.fsm.on_arguments(&values);
         }
 
-        match rx_from_gui.try_recv() {
-            Ok(msg) => match msg {
-                AppMessage::AircraftChanged(aircraft) => {
-                    fsm = dcs::get_aircraft(
-                        aircraft,
-                        sender_to_dcs_gamegui.clone(),
-                        sender_to_dcs_export.clone(),
-                        gui_handle.clone(),
-                    )
+        // the wake ups of a frame's messages fold into one, so take them all
+        loop {
+            match self.rx_from_gui.try_recv() {
+                Ok(msg) => self.handle(msg),
+                Err(TryRecvError::Disconnected) => {
+                    log::info!("stopping app thread via disconnected");
+                    return false;
+                }
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        if !self.paused {
+            self.fsm.run_fsm(FsmMessage::None, &self.clock);
+        }
+        if self.paused {
+            self.subscriptions.set(vec![]);
+            self.subscriptions.set_cockpit_params(false);
+            self.subscriptions.set_arguments(vec![]);
+        } else {
+            self.subscriptions.set(self.fsm.subscriptions());
+            self.subscriptions
+                .set_cockpit_params(self.fsm.wants_cockpit_params());
+            self.subscriptions
+                .set_arguments(self.fsm.wanted_arguments());
+        }
+        true
+    }
+
+    fn handle(&mut self, msg: AppMessage) {
+        match msg {
+            AppMessage::FsmEvent(FsmMessage::Paused) => {
+                self.paused = true;
+                self.run_event(FsmMessage::Paused);
+            }
+            AppMessage::FsmEvent(FsmMessage::Resumed) => {
+                self.paused = false;
+                self.run_event(FsmMessage::Resumed);
+                for msg in std::mem::take(&mut self.deferred) {
+                    self.handle(msg);
+                }
+            }
+            AppMessage::FsmEvent(_) | AppMessage::Confirmed(_) if self.paused => {
+                log::info!("Deferring {msg:?} until the simulation resumes");
+                self.deferred.push_back(msg);
+            }
+            // nothing is sampled while paused, and everything is reported
+            // again once the FSM subscribes on resume
+            AppMessage::IndicationChanged(_) | AppMessage::CockpitParamsChanged(_)
+                if self.paused => {}
+            AppMessage::AircraftChanged(aircraft) => {
+                self.aircraft = aircraft;
+                self.reset_fsm();
+            }
+            AppMessage::FsmEvent(fsm_msg) => self.request(fsm_msg, false),
+            AppMessage::Confirmed(fsm_msg) => self.request(fsm_msg, true),
+            AppMessage::SlotChanged => self.reset_fsm(),
+            AppMessage::GameEvent(event) => match event {
+                GameEvent::Crash | GameEvent::Eject | GameEvent::MissionEnd { .. } => {
+                    log::info!("Abandoning the aircraft after {event:?}");
+                    self.reset_fsm();
                 }
-                AppMessage::FsmEvent(fsm_msg) => fsm.run_fsm(fsm_msg, sim_time),
+                GameEvent::Landing { .. } | GameEvent::Takeoff { .. } => {}
             },
-            Err(e) => {
-                if let TryRecvError::Disconnected = e {
-                    log::info!("stopping app thread via disconnected");
-                    return;
-                } else {
-                    fsm.run_fsm(FsmMessage::None, sim_time);
+            AppMessage::SimulationStopped => {
+                self.aircraft = dcs::AircraftId::Unknown(String::from(""));
+                self.reset_fsm();
+            }
+            // the FSM sees these when it runs at the end of the wake up
+            AppMessage::IndicationChanged(change) => self.fsm.on_indication(&change),
+            AppMessage::CockpitParamsChanged(params) => self.fsm.on_cockpit_params(&params),
+        }
+    }
+
+    /// Replaces the FSM with a fresh one for the current aircraft, dropping
+    /// whatever procedure was running.
+    fn reset_fsm(&mut self) {
+        self.fsm = dcs::aircraft::make_fsm(
+            &self.aircraft,
+            self.sender_to_dcs_gamegui.clone(),
+            self.sender_to_dcs_export.clone(),
+            self.gui_handle.clone(),
+        );
+        self.gui_handle.set_startup_progress(0.0);
+        self.gui_handle.set_startup_text("");
+    }
+
+    /// Runs `fsm_msg` if the aircraft's interlocks let it through.
+    fn request(&mut self, fsm_msg: FsmMessage, confirmed: bool) {
+        let guarded = matches!(
+            fsm_msg,
+            FsmMessage::StartupAircraft | FsmMessage::ShutdownAircraft
+        );
+        if !guarded {
+            return self.run_event(fsm_msg);
+        }
+        let verdict = match self.read_flight_state() {
+            Ok(state) => dcs::aircraft::interlocks(&self.aircraft).check(fsm_msg, &state),
+            Err(e) => Verdict::Refuse(format!("couldn't tell what the aircraft is doing: {e}")),
+        };
+        match verdict {
+            Verdict::Allow => self.run_event(fsm_msg),
+            Verdict::Confirm(_) if confirmed => self.run_event(fsm_msg),
+            Verdict::Confirm(reason) => {
+                log::info!("Asking for confirmation of {fsm_msg:?}, {reason}");
+                self.gui_handle.ask_confirmation(fsm_msg, reason);
+            }
+            Verdict::Refuse(reason) => {
+                log::warn!("Refusing {fsm_msg:?}, {reason}");
+                self.gui_handle.set_refused(&reason);
+            }
+        }
+    }
+
+    fn read_flight_state(&self) -> Result<FlightState, crate::Error> {
+        self.sender_to_dcs_gamegui
+            .send(FlightState::read)
+            .wait()
+            .map_err(|_| crate::Error::CommError)?
+            .map_err(crate::Error::LuaError)
+    }
+
+    fn run_event(&mut self, fsm_msg: FsmMessage) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record_event(fsm_msg);
+        }
+        self.fsm.run_fsm(fsm_msg, &self.clock)
+    }
+}
+
+/// Runs the app thread without DCS or the GUI. The caller drives it one
+/// frame at a time, supplying the Lua state that jobs from the FSMs run on
+/// and standing in for the GUI with `send()`.
+#[cfg(test)]
+pub struct Headless {
+    thread: Option<JoinHandle<()>>,
+    tx_wake: Option<Sender<()>>,
+    rx_woken: Receiver<bool>,
+    tx_to_app: Sender<AppMessage>,
+    clock: SimClock,
+    subscriptions: Subscriptions,
+    rx_from_dcs_gamegui: Receiver<PackagedTask<Lua>>,
+    rx_from_dcs_export: Receiver<PackagedTask<Lua>>,
+    ownship_type: dcs::AircraftId,
+}
+
+#[cfg(test)]
+impl Headless {
+    pub fn new(gui_handle: gui::TxHandle, recorder: Option<Arc<Recorder>>) -> Self {
+        let (tx_to_dcs_gamegui, rx_from_dcs_gamegui) = TaskSender::new();
+        let (tx_to_dcs_export, rx_from_dcs_export) = TaskSender::new();
+        let (tx_to_app, rx_from_gui) = channel::<AppMessage>();
+        let clock = SimClock::default();
+        let app_clock = clock.clone();
+        let subscriptions = Subscriptions::default();
+        let app_subscriptions = subscriptions.clone();
+        let (_, runner_from_gui) = TaskSender::<(TaskSender<Lua>, TaskSender<Lua>)>::new();
+        let (tx_wake, rx_wake) = channel::<()>();
+        let (tx_woken, rx_woken) = channel::<bool>();
+
+        let thread = std::thread::Builder::new()
+            .name("yawe-app-headless".to_string())
+            .spawn(move || {
+                let mut app = AppThread::new(
+                    tx_to_dcs_gamegui,
+                    tx_to_dcs_export,
+                    gui_handle,
+                    rx_from_gui,
+                    runner_from_gui,
+                    app_clock,
+                    app_subscriptions,
+                    recorder,
+                );
+                while let Ok(()) = rx_wake.recv() {
+                    let running = app.on_wake();
+                    if tx_woken.send(running).is_err() || !running {
+                        return;
+                    }
+                }
+            })
+            .unwrap();
+
+        Self {
+            thread: Some(thread),
+            tx_wake: Some(tx_wake),
+            rx_woken,
+            tx_to_app,
+            clock,
+            subscriptions,
+            rx_from_dcs_gamegui,
+            rx_from_dcs_export,
+            ownship_type: dcs::AircraftId::Unknown(String::from("")),
+        }
+    }
+
+    pub fn send(&self, msg: AppMessage) {
+        let _ = self.tx_to_app.send(msg);
+    }
+
+    /// Does what `App::on_frame` and `App::on_frame_export` do in one DCS
+    /// frame, then runs jobs on `lua` until the app thread has finished
+    /// with the frame. Returns false once the app thread has stopped.
+    pub fn frame(&mut self, lua: &Lua) -> bool {
+        for event in recording::begin_frame() {
+            // recorded events already got past the interlocks
+            self.send(match event {
+                FsmMessage::StartupAircraft | FsmMessage::ShutdownAircraft => {
+                    AppMessage::Confirmed(event)
                 }
+                _ => AppMessage::FsmEvent(event),
+            });
+        }
+        let ownship_type = match dcs::get_ownship_type(lua) {
+            Ok(t) => t,
+            Err(_) => dcs::AircraftId::Unknown(String::from("")),
+        };
+        let Ok(sim_time) = dcs::get_sim_time(lua) else {
+            log::warn!("Failed to get DCS simulation time");
+            return true;
+        };
+        self.clock.set(sim_time);
+        if self.ownship_type != ownship_type {
+            self.ownship_type = ownship_type.clone();
+            self.send(AppMessage::AircraftChanged(ownship_type));
+        }
+        for change in self.subscriptions.sample(lua) {
+            self.send(AppMessage::IndicationChanged(change));
+        }
+        if let Some(params) = self.subscriptions.sample_cockpit_params(lua) {
+            self.send(AppMessage::CockpitParamsChanged(params));
+        }
+
+        let Some(tx_wake) = self.tx_wake.as_ref() else {
+            return false;
+        };
+        run_jobs(&self.rx_from_dcs_gamegui, lua, Channel::Gamegui);
+        self.subscriptions.sample_arguments(lua);
+        if tx_wake.send(()).is_err() {
+            return false;
+        }
+        // unlike DCS, keep going until the app thread is done so that runs
+        // don't depend on thread timing
+        let running = loop {
+            run_jobs(&self.rx_from_dcs_gamegui, lua, Channel::Gamegui);
+            run_jobs(&self.rx_from_dcs_export, lua, Channel::Export);
+            match self.rx_woken.try_recv() {
+                Ok(running) => break running,
+                Err(TryRecvError::Empty) => std::thread::yield_now(),
+                Err(TryRecvError::Disconnected) => break false,
+            }
+        };
+        run_jobs(&self.rx_from_dcs_export, lua, Channel::Export);
+        running
+    }
+}
+
+#[cfg(test)]
+impl Drop for Headless {
+    fn drop(&mut self) {
+        self.tx_wake = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// What happened while replaying a recording.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport {
+    pub frames: usize,
+    /// Calls that did not match the recording.
+    pub divergences: Vec<String>,
+    /// Recorded calls that were never made.
+    pub calls_left: usize,
+}
+
+/// Feeds a recording made with a `Recorder` through a headless app thread,
+/// including the FSM events the GUI sent while recording.
+#[cfg(test)]
+pub fn replay(
+    path: &std::path::Path,
+    gui_handle: gui::TxHandle,
+) -> Result<ReplayReport, crate::Error> {
+    let player = recording::Player::load(path)?;
+    // calls never reach Lua, it just has to exist for the jobs
+    let lua = Lua::new();
+    player.install();
+
+    let mut app = Headless::new(gui_handle, None);
+    let mut frames = 0;
+    while player.frames_left() > 0 {
+        frames += 1;
+        if !app.frame(&lua) {
+            break;
+        }
+    }
+    drop(app);
+    recording::uninstall();
+
+    Ok(ReplayReport {
+        frames,
+        divergences: player.divergences(),
+        calls_left: player.calls_left(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{replay, AppMessage, FsmMessage, GameEvent, Headless};
+    use crate::dcs::recording::{self, Recorder};
+    use crate::dcs::{IndicationChange, Subscription};
+    use crate::gui::TxHandle;
+    use dcs_mock::models::f16c50::Model;
+    use dcs_mock::models::mig21bis;
+    use dcs_mock::MockDcs;
+
+    #[test]
+    fn test_record_and_replay() {
+        let path =
+            std::env::temp_dir().join(format!("yawe-test-recording-{}.jsonl", std::process::id()));
+        let recorder = Recorder::create_at(path.clone()).unwrap();
+        recorder.install();
+
+        let dcs = MockDcs::with_model("F-16C_50", Box::new(Model::new())).unwrap();
+        let mut app = Headless::new(TxHandle::detached(), Some(recorder));
+        for frame in 0..300 {
+            if frame == 10 {
+                app.send(AppMessage::FsmEvent(FsmMessage::StartupAircraft));
+            }
+            assert!(app.frame(dcs.lua()));
+            dcs.advance(0.1);
+        }
+        drop(app);
+        recording::uninstall();
+        assert!(!dcs.state().clicks.is_empty());
+
+        let report = replay(&path, TxHandle::detached()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.frames, 300);
+        assert_eq!(report.divergences, Vec::<String>::new());
+        assert_eq!(report.calls_left, 0);
+    }
+
+    #[test]
+    fn test_requests_wait_for_resume() {
+        let dcs = MockDcs::with_model("F-16C_50", Box::new(Model::new())).unwrap();
+        let mut app = Headless::new(TxHandle::detached(), None);
+        for frame in 0..20 {
+            match frame {
+                5 => app.send(AppMessage::FsmEvent(FsmMessage::Paused)),
+                10 => app.send(AppMessage::FsmEvent(FsmMessage::StartupAircraft)),
+                15 => app.send(AppMessage::FsmEvent(FsmMessage::Resumed)),
+                _ => {}
             }
+            assert!(app.frame(dcs.lua()));
+            // the simulation doesn't move while paused
+            if !(5..15).contains(&frame) {
+                dcs.advance(0.1);
+            }
+            if frame < 15 {
+                assert!(dcs.state().clicks.is_empty(), "clicked on frame {frame}");
+            }
+        }
+        assert!(!dcs.state().clicks.is_empty());
+    }
+
+    #[test]
+    fn test_requests_not_held_up_by_samples() {
+        let dcs = MockDcs::with_model("F-16C_50", Box::new(Model::new())).unwrap();
+        let mut app = Headless::new(TxHandle::detached(), None);
+        for _ in 0..5 {
+            assert!(app.frame(dcs.lua()));
+            dcs.advance(0.1);
+        }
+        let change = IndicationChange {
+            subscription: Subscription {
+                device: 1,
+                query: "**/Status",
+            },
+            value: None,
         };
+        for _ in 0..50 {
+            app.send(AppMessage::IndicationChanged(change.clone()));
+        }
+        app.send(AppMessage::FsmEvent(FsmMessage::StartupAircraft));
+        assert!(app.frame(dcs.lua()));
+        assert!(!dcs.state().clicks.is_empty());
+    }
+
+    #[test]
+    fn test_crash_abandons_startup() {
+        let dcs = MockDcs::with_model("F-16C_50", Box::new(Model::new())).unwrap();
+        let mut app = Headless::new(TxHandle::detached(), None);
+        let mut clicks_at_crash = 0;
+        for frame in 0..300 {
+            match frame {
+                5 => app.send(AppMessage::FsmEvent(FsmMessage::StartupAircraft)),
+                50 => app.send(AppMessage::GameEvent(GameEvent::Crash)),
+                // the crash is handled at the start of this frame
+                51 => clicks_at_crash = dcs.state().clicks.len(),
+                _ => {}
+            }
+            assert!(app.frame(dcs.lua()));
+            dcs.advance(0.1);
+        }
+        assert!(clicks_at_crash > 0);
+        assert_eq!(dcs.state().clicks.len(), clicks_at_crash);
+    }
+
+    #[test]
+    fn test_refused_when_airborne() {
+        let dcs = MockDcs::with_model("F-16C_50", Box::new(Model::new())).unwrap();
+        dcs.state().altitude_agl = 2000.0;
+        let mut app = Headless::new(TxHandle::detached(), None);
+        for frame in 0..50 {
+            if frame == 5 {
+                app.send(AppMessage::FsmEvent(FsmMessage::StartupAircraft));
+            }
+            assert!(app.frame(dcs.lua()));
+            dcs.advance(0.1);
+        }
+        assert!(dcs.state().clicks.is_empty());
+    }
+
+    #[test]
+    fn test_confirmed_when_moving() {
+        let dcs = MockDcs::with_model("MiG-21Bis", Box::new(mig21bis::Model::new())).unwrap();
+        {
+            let mut state = dcs.state();
+            state.weight_on_wheels = true;
+            state.velocity = [4.0, 0.0, 3.0];
+        }
+        let mut app = Headless::new(TxHandle::detached(), None);
+        for frame in 0..30 {
+            match frame {
+                5 => app.send(AppMessage::FsmEvent(FsmMessage::StartupAircraft)),
+                20 => app.send(AppMessage::Confirmed(FsmMessage::StartupAircraft)),
+                _ => {}
+            }
+            assert!(app.frame(dcs.lua()));
+            dcs.advance(0.1);
+            if frame < 20 {
+                assert!(dcs.state().clicks.is_empty(), "clicked on frame {frame}");
+            }
+        }
+        assert!(!dcs.state().clicks.is_empty());
     }
 }
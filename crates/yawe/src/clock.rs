@@ -0,0 +1,155 @@
+//! Simulation time.
+//!
+//! Everything that waits does so against DCS's model time rather than the
+//! wall clock, so it stops while the game is paused and speeds up with time
+//! acceleration. Waits are [`Timer`]s that are checked each frame instead of
+//! sleeping.
+
+use crate::dcs;
+use mlua::Lua;
+use offload::TaskSender;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// A source of simulation time, in seconds.
+pub trait Clock {
+    /// The time, or None if it can't be read.
+    fn now(&self) -> Option<f32>;
+}
+
+/// The simulation time last reported by DCS. `App::on_frame` sets it every
+/// frame, and the app thread reads it.
+#[derive(Debug, Clone, Default)]
+pub struct SimClock {
+    // the bits of an f32
+    time: Arc<AtomicU32>,
+}
+
+impl SimClock {
+    pub fn set(&self, time: f32) {
+        self.time.store(time.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> Option<f32> {
+        Some(f32::from_bits(self.time.load(Ordering::Relaxed)))
+    }
+}
+
+/// Asks DCS for the time on every read, one frame apart, for jobs that block
+/// the app thread and only have a way to reach DCS. Can't be read once DCS
+/// can't be reached, so every timer on it expires.
+#[derive(Debug, Clone)]
+pub struct DcsClock {
+    to_gamegui: TaskSender<Lua>,
+}
+
+impl DcsClock {
+    pub fn new(to_gamegui: TaskSender<Lua>) -> Self {
+        Self { to_gamegui }
+    }
+}
+
+impl Clock for DcsClock {
+    fn now(&self) -> Option<f32> {
+        match self.to_gamegui.send(dcs::get_sim_time).wait() {
+            Ok(Ok(time)) => Some(time),
+            _ => None,
+        }
+    }
+}
+
+/// A clock that only moves when told to.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    time: std::cell::Cell<f32>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn set(&self, time: f32) {
+        self.time.set(time);
+    }
+
+    pub fn advance(&self, seconds: f32) {
+        self.time.set(self.time.get() + seconds);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Option<f32> {
+        Some(self.time.get())
+    }
+}
+
+/// Expires `duration` seconds of simulation time after it was started, or as
+/// soon as the clock can't be read.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub struct Timer {
+    start_time: Option<f32>,
+    duration: f32,
+}
+
+impl Timer {
+    pub fn start(clock: &dyn Clock, duration: f32) -> Self {
+        Self {
+            start_time: clock.now(),
+            duration,
+        }
+    }
+
+    pub fn is_expired(&self, clock: &dyn Clock) -> bool {
+        self.elapsed(clock)
+            .is_none_or(|elapsed| elapsed >= self.duration)
+    }
+
+    /// None if the clock couldn't be read, now or when the timer was started.
+    pub fn elapsed(&self, clock: &dyn Clock) -> Option<f32> {
+        Some(clock.now()? - self.start_time?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Clock, DcsClock, ManualClock, SimClock, Timer};
+    use crate::dcs;
+    use mlua::Lua;
+    use offload::TaskSender;
+
+    #[test]
+    fn test_timer_follows_the_clock() {
+        let clock = ManualClock::default();
+        clock.set(10.0);
+        let timer = Timer::start(&clock, 2.0);
+        assert!(!timer.is_expired(&clock));
+        clock.advance(1.5);
+        assert_eq!(timer.elapsed(&clock), Some(1.5));
+        assert!(!timer.is_expired(&clock));
+        clock.advance(0.5);
+        assert!(timer.is_expired(&clock));
+    }
+
+    #[test]
+    fn test_sim_clock_is_shared() {
+        let clock = SimClock::default();
+        let reader = clock.clone();
+        clock.set(42.5);
+        assert_eq!(reader.now(), Some(42.5));
+    }
+
+    #[test]
+    fn test_retry_gives_up_once_dcs_is_gone() {
+        let (to_gamegui, rx) = TaskSender::<Lua>::new();
+        // a game GUI environment that drops everything sent to it
+        let worker = std::thread::spawn(move || while rx.recv().is_ok() {});
+        let clock = DcsClock::new(to_gamegui);
+        assert_eq!(clock.now(), None);
+        assert!(Timer::start(&clock, 5.0).is_expired(&clock));
+        assert_eq!(dcs::retry_until(&clock, || None::<()>, 5.0), None);
+        drop(clock);
+        worker.join().unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Registry of the aircraft yawe knows about.
+//!
+//! Each aircraft registers one [`AircraftModule`] under its [`AircraftId`],
+//! and the app thread and the GUI find everything aircraft specific through
+//! it: the name to show, what the autostart can do, the FSM, extra controls
+//! and the switch table. Supporting a new aircraft means writing a module and
+//! adding it to [`registry()`], or writing a script (see [`super::script`]).
+
+use super::safety::Interlocks;
+use super::switches::SwitchDef;
+use super::{AircraftFsm, AircraftId, EmptyFsm};
+use crate::gui::TxHandle;
+use egui_backend::egui;
+use mlua::Lua;
+use offload::TaskSender;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// What the autostart can do for an aircraft.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Capabilities {
+    pub startup: bool,
+    pub shutdown: bool,
+}
+
+/// What an aircraft's widget can use to reach DCS.
+pub struct WidgetContext<'a> {
+    /// Runs jobs on the app thread, which may block on DCS.
+    pub app_runner: &'a TaskSender<(TaskSender<Lua>, TaskSender<Lua>)>,
+}
+
+/// Aircraft specific controls shown in the GUI. Lives on the GUI thread.
+pub trait AircraftWidget {
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &WidgetContext);
+}
+
+pub trait AircraftModule: Sync {
+    fn display_name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn make_fsm(
+        &self,
+        to_gamegui: TaskSender<Lua>,
+        to_export: TaskSender<Lua>,
+        gui: TxHandle,
+    ) -> Box<dyn AircraftFsm> {
+        Box::new(EmptyFsm::new(to_gamegui, to_export, gui))
+    }
+
+    /// When startup and shutdown requests are refused or need confirming.
+    fn interlocks(&self) -> Interlocks {
+        Interlocks::default()
+    }
+
+    fn make_widget(&self) -> Option<Box<dyn AircraftWidget>> {
+        None
+    }
+
+    /// The loaded switch table, in the order of the aircraft's `Switch` enum.
+    fn switches(&self) -> Option<&'static [SwitchDef]> {
+        None
+    }
+}
+
+/// An aircraft yawe can name but not fly.
+struct Unsupported(&'static str);
+
+impl AircraftModule for Unsupported {
+    fn display_name(&self) -> &'static str {
+        self.0
+    }
+}
+
+type Registry = HashMap<AircraftId, &'static dyn AircraftModule>;
+
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let mut modules = Registry::new();
+        let mut register = |id: AircraftId, module: &'static dyn AircraftModule| {
+            let previous = modules.insert(id, module);
+            assert!(previous.is_none(), "aircraft registered twice");
+        };
+        register(AircraftId::F_16C_50, &super::f16c50::Module);
+        register(AircraftId::MiG_21Bis, &super::mig21bis::Module);
+
+        for (id, script) in super::script::load_modules() {
+            if modules.contains_key(&id) {
+                log::warn!("Ignoring the script for {id:?}, which is built in");
+                continue;
+            }
+            modules.insert(id, Box::leak(Box::new(script)));
+        }
+
+        // scripts take the place of these
+        let mut register = |id: AircraftId, module: &'static dyn AircraftModule| {
+            modules.entry(id).or_insert(module);
+        };
+        register(AircraftId::A_10C, &Unsupported("A-10C"));
+        register(AircraftId::A_10C_2, &Unsupported("A-10C II"));
+        register(AircraftId::AH_64D_BLK_II, &Unsupported("AH-64D Apache"));
+        register(AircraftId::AJS37, &Unsupported("AJS37 Viggen"));
+        register(AircraftId::AV8BNA, &Unsupported("AV8BNA Harrier"));
+        register(AircraftId::F_14B, &Unsupported("F-14B Tomcat"));
+        register(AircraftId::F_15ESE, &Unsupported("F-15E Strike Eagle"));
+        register(
+            AircraftId::F_15ESE_WSO,
+            &Unsupported("F-15E Strike Eagle (WSO)"),
+        );
+        register(AircraftId::FA_18C_hornet, &Unsupported("F/A-18C Hornet"));
+        register(AircraftId::M_2000C, &Unsupported("Mirage 2000C"));
+        register(AircraftId::Mi_24P, &Unsupported("Mi-24P \"Hind E\""));
+        register(AircraftId::Mi_8MT, &Unsupported("Mi-8MT \"Hip\""));
+        register(
+            AircraftId::Mi_8MT_Copilot,
+            &Unsupported("Mi-8MT \"Hip\" (Copilot)"),
+        );
+        register(
+            AircraftId::Mi_8MT_FO,
+            &Unsupported("Mi-8MT \"Hip\" (First Officer)"),
+        );
+        register(AircraftId::SA342L, &Unsupported("SA342L Gazelle"));
+        register(AircraftId::Su_25, &Unsupported("Su-25 \"Frogfoot\""));
+        register(AircraftId::Su_25T, &Unsupported("Su-25T \"Frogfoot\""));
+        register(AircraftId::UH_1H, &Unsupported("UH-1H Huey"));
+        modules
+    })
+}
+
+pub fn interlocks(id: &AircraftId) -> Interlocks {
+    module(id).map_or_else(Interlocks::default, |module| module.interlocks())
+}
+
+pub fn module(id: &AircraftId) -> Option<&'static dyn AircraftModule> {
+    registry().get(id).copied()
+}
+
+pub fn display_name(id: &AircraftId) -> String {
+    match (module(id), id) {
+        (Some(module), _) => module.display_name().to_string(),
+        (None, AircraftId::Unknown(name)) => name.clone(),
+        (None, _) => format!("{id:?}"),
+    }
+}
+
+/// The FSM for `id`, which does nothing if the aircraft isn't supported.
+pub fn make_fsm(
+    id: &AircraftId,
+    to_gamegui: TaskSender<Lua>,
+    to_export: TaskSender<Lua>,
+    gui: TxHandle,
+) -> Box<dyn AircraftFsm> {
+    match module(id) {
+        Some(module) => module.make_fsm(to_gamegui, to_export, gui),
+        None => Box::new(EmptyFsm::new(to_gamegui, to_export, gui)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{display_name, module, AircraftId};
+
+    #[test]
+    fn test_registry() {
+        for id in [AircraftId::F_16C_50, AircraftId::MiG_21Bis] {
+            let module = module(&id).unwrap();
+            assert!(module.capabilities().startup, "{id:?}");
+            assert!(!module.switches().unwrap().is_empty(), "{id:?}");
+        }
+        let a10 = module(&AircraftId::A_10C).unwrap();
+        assert_eq!(a10.capabilities(), Default::default());
+        assert!(a10.switches().is_none());
+        let unknown = AircraftId::Unknown(String::from("Yak-52"));
+        assert_eq!(display_name(&unknown), "Yak-52");
+    }
+}
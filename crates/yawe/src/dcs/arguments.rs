@@ -0,0 +1,174 @@
+//! Cockpit arguments read many at a time.
+//!
+//! Every job sent to the game GUI thread waits for the next DCS frame, so
+//! reading arguments one job at a time costs a frame each. [`read_arguments`]
+//! reads any number of them with one `GetDevice(0)` lookup, which is how
+//! `on_frame` samples the arguments an FSM
+//! [wants](super::AircraftFsm::wanted_arguments) into an [`ArgumentValues`]
+//! snapshot, and a [`Batch`] runs a list of clicks and reads as one job.
+
+use super::recording::{tap, Call};
+use super::{get_cockpit_device, perform_click};
+use crate::Error;
+use mlua::prelude::{LuaFunction, LuaResult, LuaTable};
+use mlua::Lua;
+use offload::TaskSender;
+use std::collections::HashMap;
+
+/// The device arguments are read from.
+const COCKPIT_DEVICE: i32 = 0;
+
+/// Reads arguments from the cockpit device, looking it up on the first read.
+struct ArgumentReader<'lua> {
+    lua: &'lua Lua,
+    device: Option<LuaTable<'lua>>,
+}
+
+impl<'lua> ArgumentReader<'lua> {
+    fn new(lua: &'lua Lua) -> Self {
+        Self { lua, device: None }
+    }
+
+    fn read(&mut self, argument: i32) -> LuaResult<f32> {
+        let call = Call::GetArgumentValue {
+            device_id: COCKPIT_DEVICE,
+            argument,
+        };
+        // the lookup happens inside the tap, as there is nothing to look up
+        // in a replay
+        tap(call, || {
+            let device = match &self.device {
+                Some(device) => device.clone(),
+                None => self
+                    .device
+                    .insert(get_cockpit_device(self.lua, COCKPIT_DEVICE)?)
+                    .clone(),
+            };
+            let get_value: LuaFunction = device.get("get_argument_value")?;
+            get_value.call((device, argument))
+        })
+    }
+}
+
+/// Reads each of `arguments`, in order. Runs in the game GUI environment.
+pub fn read_arguments(lua: &Lua, arguments: &[i32]) -> LuaResult<Vec<f32>> {
+    let mut reader = ArgumentReader::new(lua);
+    arguments.iter().map(|&a| reader.read(a)).collect()
+}
+
+/// Arguments as they were read in one frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArgumentValues {
+    values: HashMap<i32, f32>,
+}
+
+impl ArgumentValues {
+    /// `None` if the argument wasn't read.
+    pub fn get(&self, argument: i32) -> Option<f32> {
+        self.values.get(&argument).copied()
+    }
+}
+
+impl FromIterator<(i32, f32)> for ArgumentValues {
+    fn from_iter<I: IntoIterator<Item = (i32, f32)>>(iter: I) -> Self {
+        Self {
+            values: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Click {
+        device_id: i32,
+        command: i32,
+        value: f32,
+    },
+    Read(i32),
+}
+
+/// Clicks and argument reads, run in order as one job so that together they
+/// take one frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Batch {
+    ops: Vec<Op>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn click(mut self, device_id: i32, command: i32, value: f32) -> Self {
+        self.ops.push(Op::Click {
+            device_id,
+            command,
+            value,
+        });
+        self
+    }
+
+    pub fn read(mut self, argument: i32) -> Self {
+        self.ops.push(Op::Read(argument));
+        self
+    }
+
+    /// Runs the batch, stopping at the first error. Returns the value of
+    /// each read, in order. Runs in the game GUI environment.
+    pub fn run(&self, lua: &Lua) -> LuaResult<Vec<f32>> {
+        let mut reader = ArgumentReader::new(lua);
+        let mut values = vec![];
+        for op in &self.ops {
+            match *op {
+                Op::Click {
+                    device_id,
+                    command,
+                    value,
+                } => perform_click(lua, device_id, command, value)?,
+                Op::Read(argument) => values.push(reader.read(argument)?),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Runs the batch on the game GUI thread and waits for it.
+    pub fn send(self, to_gamegui: &TaskSender<Lua>) -> Result<Vec<f32>, Error> {
+        to_gamegui
+            .send(move |lua| self.run(lua))
+            .wait()
+            .map_err(|_| Error::CommError)?
+            .map_err(Error::LuaError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_arguments, Batch};
+    use dcs_mock::MockDcs;
+
+    #[test]
+    fn test_batch() {
+        let dcs = MockDcs::new().unwrap();
+        dcs.state().set_argument(101, 0.0);
+        dcs.state().set_argument(102, 0.5);
+        dcs.on_click(|state, click| {
+            if (click.device_id, click.command) == (1, 3001) {
+                state.set_argument(101, click.value);
+            }
+        });
+
+        let batch = Batch::new()
+            .read(101)
+            .click(1, 3001, 1.0)
+            .read(101)
+            .read(102);
+        assert_eq!(batch.run(dcs.lua()).unwrap(), vec![0.0, 1.0, 0.5]);
+        assert_eq!(dcs.state().clicks.len(), 1);
+
+        assert_eq!(
+            read_arguments(dcs.lua(), &[102, 101]).unwrap(),
+            vec![0.5, 1.0]
+        );
+        assert!(read_arguments(dcs.lua(), &[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+//! Values from `list_cockpit_params()`.
+//!
+//! DCS lists one parameter per line as `NAME:value`. Most values are numbers,
+//! the rest are text, which DCS puts in double quotes. Lines without a `:`
+//! are ignored, and so are names listed twice after the first.
+
+use super::list_cockpit_params;
+use crate::Error;
+use mlua::Lua;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    Number(f32),
+    Text(String),
+}
+
+impl ParamValue {
+    pub fn number(&self) -> Option<f32> {
+        match self {
+            ParamValue::Number(x) => Some(*x),
+            ParamValue::Text(_) => None,
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if let Some(text) = value
+            .strip_prefix('"')
+            .and_then(|text| text.strip_suffix('"'))
+        {
+            return ParamValue::Text(text.to_string());
+        }
+        match value.parse() {
+            Ok(x) => ParamValue::Number(x),
+            Err(_) => ParamValue::Text(value.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::Number(x) => write!(f, "{x}"),
+            ParamValue::Text(text) => write!(f, "\"{text}\""),
+        }
+    }
+}
+
+/// Every parameter listed in one frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CockpitParams {
+    values: HashMap<String, ParamValue>,
+}
+
+impl CockpitParams {
+    pub fn parse(text: &str) -> Self {
+        let mut values = HashMap::new();
+        for line in text.lines() {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            values
+                .entry(name.trim().to_string())
+                .or_insert_with(|| ParamValue::parse(value));
+        }
+        Self { values }
+    }
+
+    /// Lists and parses the parameters. Runs in the export environment.
+    pub fn read(lua: &Lua) -> Result<Self, Error> {
+        let text = list_cockpit_params(lua).map_err(Error::LuaError)?;
+        Ok(Self::parse(&text))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ParamValue> {
+        self.values.get(name)
+    }
+
+    /// A numeric parameter, `IndexError` if it isn't listed and `ParseError`
+    /// if it is text.
+    pub fn number(&self, name: &str) -> Result<f32, Error> {
+        match self.get(name) {
+            Some(ParamValue::Number(x)) => Ok(*x),
+            Some(ParamValue::Text(text)) => Err(Error::ParseError(format!(
+                "cockpit param {name} is \"{text}\", not a number"
+            ))),
+            None => Err(Error::IndexError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CockpitParams, ParamValue};
+    use crate::Error;
+
+    #[test]
+    fn test_parse() {
+        let params = CockpitParams::parse(
+            "BASE_SENSOR_CANOPY_POS:0.000000\n\
+             BASE_SENSOR_LEFT_ENGINE_RPM:0.51\r\n\
+             \x20 SIGHT_MODE:\"GRID: 1\"\n\
+             MISSION_TIME:12:00\n\
+             not a param\n\
+             BASE_SENSOR_CANOPY_POS:1\n\
+             EMPTY:",
+        );
+        let get = |name| params.get(name).cloned();
+        assert_eq!(get("BASE_SENSOR_CANOPY_POS"), Some(ParamValue::Number(0.0)));
+        assert_eq!(
+            get("BASE_SENSOR_LEFT_ENGINE_RPM"),
+            Some(ParamValue::Number(0.51))
+        );
+        assert_eq!(get("SIGHT_MODE"), Some(ParamValue::Text("GRID: 1".into())));
+        assert_eq!(get("MISSION_TIME"), Some(ParamValue::Text("12:00".into())));
+        assert_eq!(get("EMPTY"), Some(ParamValue::Text("".into())));
+        assert_eq!(get("not a param"), None);
+
+        assert_eq!(params.number("BASE_SENSOR_LEFT_ENGINE_RPM").unwrap(), 0.51);
+        assert!(matches!(
+            params.number("SIGHT_MODE"),
+            Err(Error::ParseError(_))
+        ));
+        assert!(matches!(params.number("VOLTAGE"), Err(Error::IndexError)));
+    }
+}
@@ -0,0 +1,116 @@
+//! Game events from the DCS GameGUI `onGameEvent` callback, for the ones that
+//! matter to the FSMs.
+
+use super::recording::{tap, Call};
+use crate::Error;
+use mlua::prelude::{LuaFunction, LuaResult, LuaTable};
+use mlua::Lua;
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    /// The player's aircraft was destroyed.
+    Crash,
+    Eject,
+    /// `airdrome` is `None` away from an airfield, e.g. on a carrier.
+    Landing {
+        airdrome: Option<String>,
+    },
+    Takeoff {
+        airdrome: Option<String>,
+    },
+    MissionEnd {
+        winner: String,
+        message: String,
+    },
+}
+
+impl GameEvent {
+    /// Parses the arguments DCS passed to `onGameEvent(name, ...)`. Returns
+    /// `None` for other events, and for events about other players.
+    pub fn parse(lua: &Lua, name: &str, args: &[Value]) -> Result<Option<Self>, Error> {
+        let event = match name {
+            "mission_end" => {
+                return Ok(Some(Self::MissionEnd {
+                    winner: string_arg(args, 0)?.unwrap_or_default(),
+                    message: string_arg(args, 1)?.unwrap_or_default(),
+                }))
+            }
+            "crash" => Self::Crash,
+            "eject" => Self::Eject,
+            "landing" => Self::Landing {
+                airdrome: string_arg(args, 2)?,
+            },
+            "takeoff" => Self::Takeoff {
+                airdrome: string_arg(args, 2)?,
+            },
+            _ => return Ok(None),
+        };
+        let player_id = args.first().and_then(Value::as_i64);
+        let Some(player_id) = player_id else {
+            return Err(Error::ParseError(format!("`{name}` without a player id")));
+        };
+        if player_id != get_my_player_id(lua).map_err(Error::LuaError)? as i64 {
+            return Ok(None);
+        }
+        Ok(Some(event))
+    }
+}
+
+/// Argument `index` if it is a string, `None` if it is nil or missing.
+fn string_arg(args: &[Value], index: usize) -> Result<Option<String>, Error> {
+    match args.get(index) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(other) => Err(Error::ParseError(format!(
+            "expected a string argument, got {other}"
+        ))),
+    }
+}
+
+pub fn get_my_player_id(lua: &Lua) -> LuaResult<i32> {
+    tap(Call::GetMyPlayerId, || {
+        let net: LuaTable = lua.globals().get("net")?;
+        let get_my_player_id: LuaFunction = net.get("get_my_player_id")?;
+        get_my_player_id.call(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::GameEvent;
+    use dcs_mock::MockDcs;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_parse_game_events() {
+        let dcs = MockDcs::new().unwrap();
+        dcs.state().my_player_id = 1;
+        let parse = |name: &str, args: Value| {
+            let args = args.as_array().unwrap();
+            GameEvent::parse(dcs.lua(), name, args).unwrap()
+        };
+
+        assert_eq!(parse("crash", json!([1, 16])), Some(GameEvent::Crash));
+        assert_eq!(parse("eject", json!([2, 17])), None);
+        assert_eq!(
+            parse("takeoff", json!([1, 16, "Batumi"])),
+            Some(GameEvent::Takeoff {
+                airdrome: Some(String::from("Batumi"))
+            })
+        );
+        assert_eq!(
+            parse("landing", json!([1, 16, null])),
+            Some(GameEvent::Landing { airdrome: None })
+        );
+        assert_eq!(
+            parse("mission_end", json!(["blue", "Mission complete"])),
+            Some(GameEvent::MissionEnd {
+                winner: String::from("blue"),
+                message: String::from("Mission complete"),
+            })
+        );
+        assert_eq!(parse("friendly_fire", json!([1])), None);
+        assert!(GameEvent::parse(dcs.lua(), "crash", &[]).is_err());
+    }
+}
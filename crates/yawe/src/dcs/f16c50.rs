@@ -2,9 +2,8 @@
 #![allow(unused_variables)]
 
 use crate::app::FsmMessage;
-use crate::dcs::{
-    self, list_indication, retry_default, set_lockon_command, LockonCommand, SwitchInfo,
-};
+use crate::clock::{Clock, DcsClock};
+use crate::dcs::{self, retry_default, LockonCommand, SwitchInfo};
 use egui_backend::egui;
 use egui_extras::TableRow;
 use mlua::prelude::LuaResult;
@@ -12,51 +11,21 @@ use mlua::Lua;
 use offload::TaskSender;
 use std::fmt::Display;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
-use strum::IntoStaticStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use strum::{EnumIter, IntoStaticStr};
 use trace::trace;
 trace::init_depth_var!();
 
-use super::{lookup_tree, perform_click, IndicationNode};
+use super::aircraft::{AircraftModule, AircraftWidget, Capabilities, WidgetContext};
+use super::safety::{Interlocks, Response};
+use super::sequence::{self, Action, Cockpit, CockpitState, Condition, Expect};
+use super::sequence::{Procedures, Step, ThreePos};
+use super::switches::{Info, SwitchDef, SwitchEnum, SwitchTable};
+use super::{with_avionics_indication, Indication};
 
 type Si = SwitchInfo<Switch>;
-enum Info {
-    Toggle(SwitchInfo<Switch>),
-    MultiToggle(SwitchInfo<Switch>),
-    Momentary(SwitchInfo<Switch>),
-    SpringLoaded3Pos(SpringLoaded3PosInfo),
-    DualCommand3Pos(DualCommand3PosInfo),
-    FloatValue(SwitchInfo<Switch>),
-    Axis(SwitchInfo<Switch>),
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ThreePosState {
-    Down,
-    Stop,
-    Up,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ThreePosToggleState {
-    Down,
-    Middle,
-    Up,
-}
-
-struct SpringLoaded3PosInfo {
-    pub info: Si,
-    pub command_up: i32,
-}
-
-struct DualCommand3PosInfo {
-    pub device_id: i32,
-    pub command_up: i32,
-    pub command_down: i32,
-    pub argument: i32,
-}
 
-#[derive(Debug, Clone, Copy, IntoStaticStr)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, EnumIter)]
 #[allow(dead_code)]
 pub enum Switch {
     MainPower,
@@ -122,118 +91,63 @@ pub enum Switch {
     NumSwitches,
 }
 
-const SWITCH_INFO_MAP: [Info; Switch::NumSwitches as usize] = [
-    Info::MultiToggle(Si::new(Switch::MainPower, 3, 3001, 510)),
-    Info::SpringLoaded3Pos(SpringLoaded3PosInfo {
-        info: Si::new(Switch::Jfs, 6, 3006, 447),
-        command_up: (3005),
-    }),
-    Info::SpringLoaded3Pos(SpringLoaded3PosInfo {
-        info: Si::new(Switch::CanopyRetract, 10, 3003, 606),
-        command_up: 3002,
-    }),
-    Info::FloatValue(Si::new_float(Switch::CanopyValue, 7)),
-    Info::MultiToggle(Si::new(Switch::CanopyLock, 10, 3004, 600)),
-    Info::FloatValue(Si::new_float(Switch::EngineTachometer, 95)),
-    Info::Toggle(Si::new(Switch::MmcPower, 19, 3001, 715)),
-    Info::Toggle(Si::new(Switch::StoresStationPower, 22, 3001, 716)),
-    Info::Toggle(Si::new(Switch::MfdPower, 19, 3014, 717)),
-    Info::Toggle(Si::new(Switch::UfcPower, 17, 3001, 718)),
-    Info::Toggle(Si::new(Switch::GpsPower, 59, 3001, 720)),
-    Info::Toggle(Si::new(Switch::MapPower, 61, 3001, 722)),
-    Info::Toggle(Si::new(Switch::DlPower, 60, 3001, 721)),
-    Info::MultiToggle(Si::new(Switch::MidsLvtControl, 41, 3001, 723)),
-    Info::Toggle(Si::new(Switch::LeftHardpointPower, 22, 3002, 670)),
-    Info::Toggle(Si::new(Switch::RightHardpointPower, 22, 3003, 671)),
-    Info::Toggle(Si::new(Switch::FcrPower, 31, 3001, 672)),
-    Info::Toggle(Si::new(Switch::RadAltPower, 15, 3001, 673)),
-    Info::MultiToggle(Si::new(Switch::IffMasterKnob, 35, 3002, 540)),
-    Info::MultiToggle(Si::new(Switch::UhfFunctionKnob, 37, 3008, 417)),
-    Info::Toggle(Si::new(Switch::CmdsPower, 32, 3001, 375)),
-    Info::Toggle(Si::new(Switch::CmdsJammerPower, 32, 3002, 374)),
-    Info::Toggle(Si::new(Switch::CmdsMwsPower, 32, 3003, 373)),
-    Info::Toggle(Si::new(Switch::CmdsExpendable1Power, 32, 3005, 365)),
-    Info::Toggle(Si::new(Switch::CmdsExpendable2Power, 32, 3006, 366)),
-    Info::Toggle(Si::new(Switch::CmdsExpendable3Power, 32, 3007, 367)),
-    Info::Toggle(Si::new(Switch::CmdsExpendable4Power, 32, 3008, 368)),
-    Info::MultiToggle(Si::new(Switch::CmdsProgramKnob, 32, 3009, 377)),
-    Info::MultiToggle(Si::new(Switch::CmdsModeKnob, 32, 3010, 378)),
-    Info::Axis(Si::new(Switch::HudBrightnessKnob, 17, 3022, 190)),
-    Info::Axis(Si::new(Switch::HmdIntensityKnob, 30, 3001, 392)),
-    Info::Toggle(Si::new(Switch::LaserArm, 22, 3004, 103)),
-    Info::Toggle(Si::new(Switch::RwrPower, 33, 3011, 401)),
-    Info::Momentary(Si::new(Switch::SaiCage, 47, 3002, 67)),
-    Info::Axis(Si::new(Switch::SaiPitchTrim, 47, 3003, 66)),
-    Info::DualCommand3Pos(DualCommand3PosInfo {
-        device_id: 7,
-        command_up: 3010,
-        command_down: 3004,
-        argument: 357,
-    }),
-    Info::Toggle(Si::new(Switch::EjectionSafety, 10, 3009, 785)),
-    Info::SpringLoaded3Pos(SpringLoaded3PosInfo {
-        info: Si::new(Switch::AltimeterModeLever, 45, 3002, 60),
-        command_up: 3001,
-    }),
-    Info::MultiToggle(Si::new(Switch::InsKnob, 14, 3001, 719)),
-    Info::Momentary(Si::new(Switch::Icp1, 17, 3003, 171)),
-    Info::Momentary(Si::new(Switch::Icp2, 17, 3004, 172)),
-    Info::Momentary(Si::new(Switch::Icp3, 17, 3005, 173)),
-    Info::Momentary(Si::new(Switch::Icp4, 17, 3006, 175)),
-    Info::Momentary(Si::new(Switch::Icp5, 17, 3007, 176)),
-    Info::Momentary(Si::new(Switch::Icp6, 17, 3008, 177)),
-    Info::Momentary(Si::new(Switch::Icp7, 17, 3009, 179)),
-    Info::Momentary(Si::new(Switch::Icp8, 17, 3010, 180)),
-    Info::Momentary(Si::new(Switch::Icp9, 17, 3011, 181)),
-    Info::Momentary(Si::new(Switch::Icp0, 17, 3002, 182)),
-    Info::Momentary(Si::new(Switch::IcpCom1, 17, 3012, 165)),
-    Info::Momentary(Si::new(Switch::IcpCom2, 17, 3013, 166)),
-    Info::Momentary(Si::new(Switch::IcpIff, 17, 3014, 167)),
-    Info::Momentary(Si::new(Switch::IcpList, 17, 3015, 168)),
-    Info::Momentary(Si::new(Switch::IcpAaMode, 17, 3018, 169)),
-    Info::Momentary(Si::new(Switch::IcpAgMode, 17, 3019, 170)),
-    Info::Momentary(Si::new(Switch::IcpRcl, 17, 3017, 174)),
-    Info::Momentary(Si::new(Switch::IcpEnter, 17, 3016, 178)),
-    Info::SpringLoaded3Pos(SpringLoaded3PosInfo {
-        info: Si::new(Switch::IcpDedInc, 17, 3031, 183),
-        command_up: 3030,
-    }),
-    Info::SpringLoaded3Pos(SpringLoaded3PosInfo {
-        info: Si::new(Switch::IcpDataRtnSeq, 17, 3032, 184),
-        command_up: 3033,
-    }),
-    Info::SpringLoaded3Pos(SpringLoaded3PosInfo {
-        info: Si::new(Switch::IcpDataUpDown, 17, 3035, 185),
-        command_up: 3034,
-    }),
-];
+impl SwitchEnum for Switch {
+    const AIRCRAFT: &'static str = "F-16C_50";
+    const COUNT: usize = Switch::NumSwitches as usize;
+    const EMBEDDED: &'static str = include_str!("../../resources/switches/F-16C_50.json");
+
+    fn index(self) -> usize {
+        self as usize
+    }
 
-fn get_switch_info(s: Switch) -> Option<&'static Si> {
-    let info = &SWITCH_INFO_MAP[s as usize];
-    match info {
-        Info::Toggle(i) => Some(i),
-        Info::MultiToggle(i) => Some(i),
-        Info::Momentary(i) => Some(i),
-        Info::FloatValue(i) => Some(i),
-        Info::SpringLoaded3Pos(i) => Some(&i.info),
-        Info::Axis(i) => Some(i),
-        Info::DualCommand3Pos(_) => None,
+    fn table() -> &'static SwitchTable<Self> {
+        switches()
     }
 }
 
-fn get_switch_argument(s: Switch) -> i32 {
-    let info = &SWITCH_INFO_MAP[s as usize];
-    match info {
-        Info::Toggle(i) => i.argument,
-        Info::MultiToggle(i) => i.argument,
-        Info::Momentary(i) => i.argument,
-        Info::SpringLoaded3Pos(i) => i.info.argument,
-        Info::DualCommand3Pos(i) => i.argument,
-        Info::FloatValue(i) => i.argument,
-        Info::Axis(i) => i.argument,
+static SWITCHES: OnceLock<SwitchTable<Switch>> = OnceLock::new();
+
+pub fn switches() -> &'static SwitchTable<Switch> {
+    SWITCHES.get_or_init(SwitchTable::load)
+}
+
+fn get_switch_info(s: Switch) -> Option<Si> {
+    match *switches().info(s) {
+        Info::Toggle {
+            device_id,
+            command,
+            argument,
+        }
+        | Info::MultiToggle {
+            device_id,
+            command,
+            argument,
+        }
+        | Info::Momentary {
+            device_id,
+            command,
+            argument,
+        }
+        | Info::Axis {
+            device_id,
+            command,
+            argument,
+        } => Some(Si::new(s, device_id, command, argument)),
+        Info::SpringLoaded3Pos {
+            device_id,
+            command_down,
+            argument,
+            ..
+        } => Some(Si::new(s, device_id, command_down, argument)),
+        Info::FloatValue { argument } => Some(Si::new_float(s, argument)),
+        Info::DualCommand3Pos { .. } => None,
     }
 }
 
+fn get_switch_argument(s: Switch) -> i32 {
+    switches().info(s).argument()
+}
+
 #[trace(logging)]
 fn toggle_switch(lua: &Lua, s: Switch) -> LuaResult<()> {
     let i = get_switch_info(s);
@@ -256,26 +170,56 @@ pub fn set_switch_state(lua: &Lua, s: Switch, state: f32) -> LuaResult<()> {
     }
 }
 
+/// Moves `s` to one of the named positions in its switch table entry.
+#[trace(logging)]
+pub fn set_switch_position(lua: &Lua, s: Switch, position: &str) -> LuaResult<()> {
+    let Some(state) = switches().position(s, position) else {
+        log::warn!("{s:?} has no position {position} in the switch table");
+        return Ok(());
+    };
+    set_switch_state(lua, s, state)
+}
+
 #[trace(logging)]
 pub fn get_switch_state(lua: &Lua, s: Switch) -> LuaResult<f32> {
     let argument = get_switch_argument(s);
     dcs::get_switch_state(lua, 0, argument)
 }
 
+/// Seconds of simulation time the avionics helpers wait for the jet to react.
+const AVIONICS_TIMEOUT: f32 = 5.0;
+
+/// Polls `ready` until it returns true, for at most [`AVIONICS_TIMEOUT`]
+/// seconds of simulation time. `what` is logged if it times out.
+fn wait_for<F>(to_gamegui: &TaskSender<Lua>, what: &str, mut ready: F) -> Option<()>
+where
+    F: FnMut() -> bool,
+{
+    let clock = DcsClock::new(to_gamegui.clone());
+    let result = dcs::retry_until(&clock, || ready().then_some(()), AVIONICS_TIMEOUT);
+    if result.is_none() {
+        log::warn!("Timed out after {AVIONICS_TIMEOUT} s waiting for {what}");
+    }
+    result
+}
+
 fn wait_switch_state(to_gamegui: &TaskSender<Lua>, s: Switch, value: f32) {
-    loop {
+    let mut last = None;
+    let result = wait_for(to_gamegui, &format!("{s:?} to be {value}"), || {
         let Ok(lua_result) = to_gamegui.send(move |lua| get_switch_state(lua, s)).wait() else {
-            return;
+            return true;
         };
 
         let Ok(switch_state) = lua_result else {
             log::warn!("Polling {s:?} position failed!");
-            return;
+            return true;
         };
 
-        if switch_state == value {
-            return;
-        }
+        last = Some(switch_state);
+        switch_state == value
+    });
+    if result.is_none() {
+        log::warn!("{s:?} was last at {last:?}");
     }
 }
 
@@ -294,7 +238,7 @@ fn actuate_momentary(to_gamegui: &TaskSender<Lua>, s: Switch, value: f32) {
 }
 
 #[trace(logging)]
-fn actuate_3pos_spring(to_gamegui: &TaskSender<Lua>, s: Switch, state: ThreePosState) {
+fn actuate_3pos_spring(to_gamegui: &TaskSender<Lua>, s: Switch, state: ThreePos) {
     let _ = to_gamegui
         .send(move |lua| set_three_pos_springloaded(lua, s, state))
         .wait();
@@ -302,15 +246,15 @@ fn actuate_3pos_spring(to_gamegui: &TaskSender<Lua>, s: Switch, state: ThreePosS
         to_gamegui,
         s,
         match state {
-            ThreePosState::Down => -1.0,
-            ThreePosState::Stop => 0.0,
-            ThreePosState::Up => 1.0,
+            ThreePos::Down => -1.0,
+            ThreePos::Middle => 0.0,
+            ThreePos::Up => 1.0,
         },
     );
     wait_frame(to_gamegui);
     wait_frame(to_gamegui);
     let _ = to_gamegui
-        .send(move |lua| set_three_pos_springloaded(lua, s, ThreePosState::Stop))
+        .send(move |lua| set_three_pos_springloaded(lua, s, ThreePos::Middle))
         .wait();
     wait_switch_state(to_gamegui, s, 0.0);
     wait_frame(to_gamegui);
@@ -319,22 +263,12 @@ fn actuate_3pos_spring(to_gamegui: &TaskSender<Lua>, s: Switch, state: ThreePosS
 
 #[trace(logging)]
 fn ded_return(to_gamegui: &TaskSender<Lua>) {
-    actuate_3pos_spring(to_gamegui, Switch::IcpDataRtnSeq, ThreePosState::Down);
+    actuate_3pos_spring(to_gamegui, Switch::IcpDataRtnSeq, ThreePos::Down);
 }
 
 #[trace(logging)]
 fn ded_sequence(to_gamegui: &TaskSender<Lua>) {
-    actuate_3pos_spring(to_gamegui, Switch::IcpDataRtnSeq, ThreePosState::Up);
-}
-
-#[trace(logging)]
-fn ded_down(to_gamegui: &TaskSender<Lua>) {
-    actuate_momentary(to_gamegui, Switch::IcpDataUpDown, -1.0);
-}
-
-#[trace(logging)]
-fn ded_up(to_gamegui: &TaskSender<Lua>) {
-    actuate_momentary(to_gamegui, Switch::IcpDataUpDown, 1.0);
+    actuate_3pos_spring(to_gamegui, Switch::IcpDataRtnSeq, ThreePos::Up);
 }
 
 #[trace(logging)]
@@ -344,12 +278,12 @@ fn icp_list(to_gamegui: &TaskSender<Lua>) {
 
 #[trace(logging)]
 fn ded_rocker_up(to_gamegui: &TaskSender<Lua>) {
-    actuate_3pos_spring(to_gamegui, Switch::IcpDedInc, ThreePosState::Up);
+    actuate_3pos_spring(to_gamegui, Switch::IcpDedInc, ThreePos::Up);
 }
 
 #[trace(logging)]
 fn ded_rocker_down(to_gamegui: &TaskSender<Lua>) {
-    actuate_3pos_spring(to_gamegui, Switch::IcpDedInc, ThreePosState::Down);
+    actuate_3pos_spring(to_gamegui, Switch::IcpDedInc, ThreePos::Down);
 }
 
 #[trace(logging)]
@@ -376,119 +310,8 @@ fn icp_number(to_gamegui: &TaskSender<Lua>, number: i32) {
 }
 
 #[trace(logging)]
-pub fn is_switch_set(lua: &Lua, s: Switch) -> LuaResult<bool> {
-    Ok(get_switch_state(lua, s)? > 0.5)
-}
-
-#[trace(logging)]
-pub fn set_switch(lua: &Lua, s: Switch) -> LuaResult<()> {
-    if !is_switch_set(lua, s)? {
-        toggle_switch(lua, s)
-    } else {
-        Ok(())
-    }
-}
-
-#[trace(logging)]
-pub fn unset_switch(lua: &Lua, s: Switch) -> LuaResult<()> {
-    if is_switch_set(lua, s)? {
-        toggle_switch(lua, s)
-    } else {
-        Ok(())
-    }
-}
-
-#[trace(logging)]
-fn set_three_pos_springloaded(lua: &Lua, s: Switch, state: ThreePosState) -> LuaResult<()> {
-    let Info::SpringLoaded3Pos(three_pos_info) = &SWITCH_INFO_MAP[s as usize] else {
-        log::warn!(
-            "Tried to interpret {:?} as a three pos springloaded switch",
-            s
-        );
-        return LuaResult::Ok(());
-    };
-    let device_id = three_pos_info.info.device_id;
-    let down_command = three_pos_info.info.command;
-    let up_command = three_pos_info.command_up;
-    dcs::perform_click(lua, device_id, down_command, 0.0)?;
-    dcs::perform_click(lua, device_id, up_command, 0.0)?;
-
-    match state {
-        ThreePosState::Down => dcs::perform_click(lua, device_id, down_command, -1.0),
-        ThreePosState::Stop => LuaResult::Ok(()),
-        ThreePosState::Up => dcs::perform_click(lua, device_id, up_command, 1.0),
-    }?;
-
-    Ok(())
-}
-
-#[trace(logging)]
-#[trace(logging)]
-fn set_three_pos(
-    to_gamegui: &TaskSender<Lua>,
-    s: Switch,
-    state: ThreePosToggleState,
-) -> LuaResult<()> {
-    let Info::DualCommand3Pos(info) = &SWITCH_INFO_MAP[s as usize] else {
-        log::warn!(
-            "Tried to interpret {:?} as a three pos springloaded switch, but it is not",
-            s
-        );
-        return LuaResult::Ok(());
-    };
-
-    let Ok(lua_result) = to_gamegui
-        .send(move |lua| match state {
-            ThreePosToggleState::Down => {
-                perform_click(lua, info.device_id, info.command_down, -1.0)
-            }
-            ThreePosToggleState::Middle => {
-                perform_click(lua, info.device_id, info.command_down, 0.0)?;
-                perform_click(lua, info.device_id, info.command_up, -1.0)
-            }
-            ThreePosToggleState::Up => perform_click(lua, info.device_id, info.command_up, 1.0),
-        })
-        .wait()
-    else {
-        return Ok(());
-    };
-
-    // If we don't add this hack, the Jet won't allow the switch to properly spring
-    // back to center
-    std::thread::sleep(std::time::Duration::from_millis(100));
-
-    if state == ThreePosToggleState::Up {
-        let _ = to_gamegui
-            .send(|lua| perform_click(lua, info.device_id, info.command_up, 0.0))
-            .wait()
-            .unwrap_or(Ok(()));
-    };
-
-    Ok(())
-}
-
-#[trace(logging)]
-fn poll_argument(to_gamegui: &TaskSender<Lua>, switch: Switch) -> Result<f32, crate::Error> {
-    to_gamegui
-        .send(move |lua| get_switch_state(lua, switch))
-        .wait()
-        .map_err(|_| crate::Error::CommError)?
-        .map_err(|e| crate::Error::LuaError(e))
-}
-
-#[trace(logging)]
-fn handle_polling_err(r: &Result<f32, crate::Error>) {
-    if let Err(err) = r {
-        match err {
-            crate::Error::LuaError(e) => {
-                log::warn!(
-                    "Lua error encountered when polling Engine Start Light {:?}",
-                    e
-                )
-            }
-            _ => {}
-        }
-    }
+fn set_three_pos_springloaded(lua: &Lua, s: Switch, state: ThreePos) -> LuaResult<()> {
+    sequence::set_spring(lua, s, state)
 }
 
 #[derive(Default, Debug, PartialEq, Clone)]
@@ -526,22 +349,22 @@ pub struct AvionicsState {
 }
 
 #[trace(logging, disable(tree))]
-fn parse_quantity<T>(tree: &slab_tree::Tree<IndicationNode>, path: &Vec<&str>) -> Option<T>
+fn parse_quantity<T>(tree: &Indication, query: &str) -> Option<T>
 where
     T: std::str::FromStr + std::fmt::Debug,
 {
-    let value = &lookup_tree(tree, path)?.value;
+    let value = &tree.first(query)?.value;
     let parse_result = value.trim().parse::<T>();
     if let Err(e) = parse_result {
-        log::error!("Error parsing path {path:?}, value was {value}");
+        log::error!("Error parsing {query}, value was {value}");
         return None;
     };
     parse_result.ok()
 }
 
 #[trace(logging, disable(tree))]
-fn parse_bool(tree: &slab_tree::Tree<IndicationNode>, path: &Vec<&str>) -> Option<bool> {
-    match lookup_tree(tree, path)?.value.as_str() {
+fn parse_bool(tree: &Indication, query: &str) -> Option<bool> {
+    match tree.first(query)?.value.as_ref() {
         "ON" => Some(true),
         "OFF" => Some(false),
         _ => None,
@@ -550,63 +373,36 @@ fn parse_bool(tree: &slab_tree::Tree<IndicationNode>, path: &Vec<&str>) -> Optio
 
 #[trace(logging, pretty)]
 fn read_cmds_bingo_page(to_export: &TaskSender<Lua>) -> Option<CmdsBingo> {
-    let _ = super::get_avionics_indication(to_export, IndicationDevice::Ded as i32)?;
-    let ded_indication = super::get_avionics_indication(to_export, IndicationDevice::Ded as i32)?;
-
-    let chaff_count: i8 = parse_quantity(
-        &ded_indication,
-        &vec!["CMDS_CH_Scratchpad_placeholder", "CMDS_CH_Scratchpad"],
-    )?;
-
-    let flare_count: i8 = parse_quantity(
-        &ded_indication,
-        &vec!["CMDS_FL_Scratchpad_placeholder", "CMDS_FL_Scratchpad"],
-    )?;
-
-    Some(CmdsBingo {
-        chaff: chaff_count,
-        flare: flare_count,
-        feedback: parse_bool(
-            &ded_indication,
-            &vec!["CMDS_FDBK_value_placeholder", "CMDS_FDBK_value"],
-        )?,
-        reqctr: parse_bool(
-            &ded_indication,
-            &vec!["CMDS_REQCTR_value_placeholder", "CMDS_REQCTR_value"],
-        )?,
-        bingo: parse_bool(
-            &ded_indication,
-            &vec!["CMDS_BINGO_value_placeholder", "CMDS_BINGO_value"],
-        )?,
-    })
+    let ded = IndicationDevice::Ded as i32;
+    with_avionics_indication(to_export, ded, |_| ())?;
+    with_avionics_indication(to_export, ded, |ded_indication| {
+        let chaff_count: i8 = parse_quantity(ded_indication, "**/CMDS_CH_Scratchpad")?;
+        let flare_count: i8 = parse_quantity(ded_indication, "**/CMDS_FL_Scratchpad")?;
+
+        Some(CmdsBingo {
+            chaff: chaff_count,
+            flare: flare_count,
+            feedback: parse_bool(ded_indication, "**/CMDS_FDBK_value")?,
+            reqctr: parse_bool(ded_indication, "**/CMDS_REQCTR_value")?,
+            bingo: parse_bool(ded_indication, "**/CMDS_BINGO_value")?,
+        })
+    })?
 }
 
 #[trace(logging, disable(tree))]
-fn parse_cmds_program_page(tree: &slab_tree::Tree<IndicationNode>) -> Option<CmdsProgramSlot> {
-    if lookup_tree(tree, &vec!["CMDS_Prog_label"]).is_none() {
+fn parse_cmds_program_page(tree: &Indication) -> Option<CmdsProgramSlot> {
+    if tree.get(&["CMDS_Prog_label"]).is_none() {
         log::warn!("Not on CMDS program page!");
         return None;
     }
 
-    let bq: i8 = parse_quantity(
-        tree,
-        &vec!["CMDS_BQ_Scratchpad_placeholder", "CMDS_BQ_Scratchpad"],
-    )?;
+    let bq: i8 = parse_quantity(tree, "**/CMDS_BQ_Scratchpad")?;
 
-    let bi: f32 = parse_quantity(
-        tree,
-        &vec!["CMDS_BI_Scratchpad_placeholder", "CMDS_BI_Scratchpad"],
-    )?;
+    let bi: f32 = parse_quantity(tree, "**/CMDS_BI_Scratchpad")?;
 
-    let sq: i8 = parse_quantity(
-        tree,
-        &vec!["CMDS_SQ_Scratchpad_placeholder", "CMDS_SQ_Scratchpad"],
-    )?;
+    let sq: i8 = parse_quantity(tree, "**/CMDS_SQ_Scratchpad")?;
 
-    let si: f32 = parse_quantity(
-        tree,
-        &vec!["CMDS_SI_Scratchpad_placeholder", "CMDS_SI_Scratchpad"],
-    )?;
+    let si: f32 = parse_quantity(tree, "**/CMDS_SI_Scratchpad")?;
 
     Some(CmdsProgramSlot {
         burst_quantity: bq,
@@ -616,17 +412,26 @@ fn parse_cmds_program_page(tree: &slab_tree::Tree<IndicationNode>) -> Option<Cmd
     })
 }
 
+/// Which CMDS program the DED shows, and its settings.
+fn read_cmds_program(
+    to_export: &TaskSender<Lua>,
+) -> Option<(Countermeasure, i8, Option<CmdsProgramSlot>)> {
+    with_avionics_indication(to_export, IndicationDevice::Ded as i32, |tree| {
+        let (kind, program) = get_cmds_program(tree)?;
+        Some((kind, program, parse_cmds_program_page(tree)))
+    })?
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum AvionicsError {
     InvalidState,
 }
 
 #[trace(logging)]
-fn wait_on_cmds_program(to_export: &TaskSender<Lua>) {
-    while (get_avionics_value(to_export, IndicationDevice::Ded, &vec!["CMDS_Prog_label"])).is_none()
-    {
-        log::info!("waiting on cmds program");
-    }
+fn wait_on_cmds_program(to_gamegui: &TaskSender<Lua>, to_export: &TaskSender<Lua>) -> Option<()> {
+    wait_for(to_gamegui, "the CMDS program page", || {
+        get_avionics_value(to_export, IndicationDevice::Ded, "CMDS_Prog_label").is_some()
+    })
 }
 
 // Ensures that the DED is in the CMDS menu, on program 1, and with the chaff
@@ -636,12 +441,12 @@ fn get_to_cmds_program_root(
     to_gamegui: &TaskSender<Lua>,
     to_export: &TaskSender<Lua>,
 ) -> Result<(), AvionicsError> {
-    loop {
-        let tree = super::get_avionics_indication(to_export, IndicationDevice::Ded as i32)
-            .ok_or(AvionicsError::InvalidState)?;
-        let Some((kind, program)) = get_cmds_program(&tree) else {
-            return Err(AvionicsError::InvalidState);
-        };
+    // there are six programs for each of chaff and flare
+    for _ in 0..12 {
+        let (kind, program) =
+            with_avionics_indication(to_export, IndicationDevice::Ded as i32, get_cmds_program)
+                .flatten()
+                .ok_or(AvionicsError::InvalidState)?;
         if (kind, program) == (Countermeasure::Chaff, 1) {
             return Ok(());
         }
@@ -652,27 +457,35 @@ fn get_to_cmds_program_root(
             ded_rocker_down(to_gamegui);
         }
     }
+    log::warn!("Couldn't get to the first chaff program");
+    Err(AvionicsError::InvalidState)
 }
 
 #[trace(logging, pretty)]
 fn read_cmds(to_gamegui: &TaskSender<Lua>, to_export: &TaskSender<Lua>) -> Option<AvionicsState> {
-    while !is_on_cni(to_export) {
-        ded_return(to_gamegui);
-    }
+    wait_for(to_gamegui, "the CNI page", || {
+        let on_cni = is_on_cni(to_export);
+        if !on_cni {
+            ded_return(to_gamegui);
+        }
+        on_cni
+    })?;
     icp_list(to_gamegui);
-    while !is_on_list(to_export) {}
+    wait_for(to_gamegui, "the LIST page", || is_on_list(to_export))?;
     icp_number(to_gamegui, 7);
-    while !is_on_cmds_bingo(to_export) {}
+    wait_for(to_gamegui, "the CMDS BINGO page", || {
+        is_on_cmds_bingo(to_export)
+    })?;
     let mut avionics = AvionicsState::default();
-    avionics.cmds.bingo = retry_default(|| read_cmds_bingo_page(to_export))?;
+    let clock = DcsClock::new(to_gamegui.clone());
+    avionics.cmds.bingo = retry_default(&clock, || read_cmds_bingo_page(to_export))?;
     ded_sequence(to_gamegui);
     wait_frame(to_gamegui);
-    wait_on_cmds_program(to_export);
+    wait_on_cmds_program(to_gamegui, to_export)?;
     get_to_cmds_program_root(to_gamegui, to_export).ok()?;
 
     for ii in 0..6 {
-        let tree = super::get_avionics_indication(to_export, IndicationDevice::Ded as i32)?;
-        let (kind, program) = get_cmds_program(&tree)?;
+        let (kind, program, page) = read_cmds_program(to_export)?;
         if (Countermeasure::Chaff, ii + 1) != (kind, program) {
             log::error!(
                 "Was not on correct page, expected {:?}, {}, got {:?}, {}",
@@ -683,7 +496,7 @@ fn read_cmds(to_gamegui: &TaskSender<Lua>, to_export: &TaskSender<Lua>) -> Optio
             );
             return None;
         }
-        avionics.cmds.programs[ii as usize].chaff = parse_cmds_program_page(&tree)?;
+        avionics.cmds.programs[ii as usize].chaff = page?;
         ded_rocker_up(to_gamegui);
         wait_frame(to_gamegui);
     }
@@ -691,8 +504,7 @@ fn read_cmds(to_gamegui: &TaskSender<Lua>, to_export: &TaskSender<Lua>) -> Optio
     ded_sequence(to_gamegui);
     wait_frame(to_gamegui);
     for ii in 0..6 {
-        let tree = super::get_avionics_indication(to_export, IndicationDevice::Ded as i32)?;
-        let (kind, program) = get_cmds_program(&tree)?;
+        let (kind, program, page) = read_cmds_program(to_export)?;
 
         if (Countermeasure::Flare, ii + 1) != (kind, program) {
             log::error!(
@@ -705,7 +517,7 @@ fn read_cmds(to_gamegui: &TaskSender<Lua>, to_export: &TaskSender<Lua>) -> Optio
             return None;
         }
 
-        avionics.cmds.programs[ii as usize].flare = parse_cmds_program_page(&tree)?;
+        avionics.cmds.programs[ii as usize].flare = page?;
 
         ded_rocker_up(to_gamegui);
         wait_frame(to_gamegui);
@@ -728,47 +540,28 @@ enum IndicationDevice {
     Hmcs = 18,
 }
 
-fn get_avionics_indication(lua: &Lua, device: IndicationDevice) -> LuaResult<String> {
-    list_indication(lua, device as i32)
-}
-
 #[trace(logging)]
 fn get_avionics_value(
     to_export: &TaskSender<Lua>,
     device: IndicationDevice,
-    path: &Vec<&str>,
+    query: &str,
 ) -> Option<String> {
-    super::get_avionics_value(&to_export, device as i32, path)
-}
-
-#[trace(logging)]
-fn get_hud_align_value(to_export: &TaskSender<Lua>) -> Option<String> {
-    get_avionics_value(
-        &to_export,
-        IndicationDevice::Hud,
-        &vec![
-            "HUD_BlankRoot_PH_com",
-            "HUD_Indication_bias",
-            "HUD_Window7_origin",
-            "HUD_AlignStatus_origin",
-            "HUD_Window7_AlignmentStatus",
-        ],
-    )
+    super::get_avionics_value(to_export, device as i32, query)
 }
 
 #[trace(logging)]
 fn is_on_cni(to_export: &TaskSender<Lua>) -> bool {
-    get_avionics_value(to_export, IndicationDevice::Ded, &vec!["DED CNI TACAN PH"]).is_some()
+    get_avionics_value(to_export, IndicationDevice::Ded, "DED CNI TACAN PH").is_some()
 }
 
 #[trace(logging)]
 fn is_on_list(to_export: &TaskSender<Lua>) -> bool {
-    get_avionics_value(to_export, IndicationDevice::Ded, &vec!["LIST Label"]).is_some()
+    get_avionics_value(to_export, IndicationDevice::Ded, "LIST Label").is_some()
 }
 
 #[trace(logging)]
 fn is_on_cmds_bingo(to_export: &TaskSender<Lua>) -> bool {
-    get_avionics_value(to_export, IndicationDevice::Ded, &vec!["CMDS_BINGO_label"]).is_some()
+    get_avionics_value(to_export, IndicationDevice::Ded, "CMDS_BINGO_label").is_some()
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -778,372 +571,303 @@ enum Countermeasure {
 }
 
 #[trace(logging, disable(tree))]
-fn get_cmds_program(tree: &slab_tree::Tree<IndicationNode>) -> Option<(Countermeasure, i8)> {
-    lookup_tree(tree, &vec!["CMDS_Prog_label"])?;
-
-    let program_val = parse_quantity(tree, &vec!["CMDS_Selected_Program"]);
-    let chaff_val = lookup_tree(tree, &vec!["CMDS_CHAFF_label"]);
-    let flare_val = lookup_tree(tree, &vec!["CMDS_FLARE_label"]);
-    if chaff_val.is_some_and(|val| val.value == "CMDS CHAFF") {
-        Some((Countermeasure::Chaff, program_val?))
-    } else if flare_val.is_some_and(|val| val.value == "CMDS FLARE") {
-        Some((Countermeasure::Flare, program_val?))
-    } else {
-        None
-    }
-}
+fn get_cmds_program(tree: &Indication) -> Option<(Countermeasure, i8)> {
+    tree.get(&["CMDS_Prog_label"])?;
+
+    let program_val = parse_quantity(tree, "CMDS_Selected_Program");
+    let kind = tree
+        .query("CMDS_*_label")
+        .iter()
+        .find_map(|label| match label.value.as_ref() {
+            "CMDS CHAFF" => Some(Countermeasure::Chaff),
+            "CMDS FLARE" => Some(Countermeasure::Flare),
+            _ => None,
+        })?;
+    Some((kind, program_val?))
+}
+
+const HUD_ALIGN_STATUS: &str = "**/HUD_Window7_AlignmentStatus";
+
+/// Avionics switches and the positions they're set to during startup.
+const AVIONICS: &[(Switch, f32)] = &[
+    (Switch::MmcPower, 1.0),
+    (Switch::StoresStationPower, 1.0),
+    (Switch::MfdPower, 1.0),
+    (Switch::UfcPower, 1.0),
+    (Switch::MapPower, 1.0),
+    (Switch::GpsPower, 1.0),
+    (Switch::DlPower, 1.0),
+    (Switch::LeftHardpointPower, 1.0),
+    (Switch::RightHardpointPower, 1.0),
+    (Switch::FcrPower, 1.0),
+    (Switch::RadAltPower, 1.0),
+    (Switch::CmdsPower, 1.0),
+    (Switch::CmdsJammerPower, 1.0),
+    (Switch::CmdsMwsPower, 1.0),
+    (Switch::CmdsExpendable1Power, 1.0),
+    (Switch::CmdsExpendable2Power, 1.0),
+    (Switch::CmdsExpendable3Power, 1.0),
+    (Switch::CmdsExpendable4Power, 1.0),
+    (Switch::MidsLvtControl, 0.2),
+    (Switch::IffMasterKnob, 0.3),
+    (Switch::UhfFunctionKnob, 0.2),
+    (Switch::CmdsProgramKnob, 0.1),
+    (Switch::CmdsModeKnob, 0.2),
+    (Switch::HudBrightnessKnob, 1.0),
+    (Switch::HmdIntensityKnob, 1.0),
+    (Switch::LaserArm, 1.0),
+    (Switch::RwrPower, 1.0),
+    (Switch::EjectionSafety, 1.0),
+];
 
-fn throw_initial_switches(tx: &TaskSender<Lua>) {
-    let _ = tx
-        .send(|lua| {
-            let switch_states = [
-                (Switch::MmcPower, 1.0),
-                (Switch::StoresStationPower, 1.0),
-                (Switch::MfdPower, 1.0),
-                (Switch::UfcPower, 1.0),
-                (Switch::MapPower, 1.0),
-                (Switch::GpsPower, 1.0),
-                (Switch::DlPower, 1.0),
-                (Switch::LeftHardpointPower, 1.0),
-                (Switch::RightHardpointPower, 1.0),
-                (Switch::FcrPower, 1.0),
-                (Switch::RadAltPower, 1.0),
-                (Switch::CmdsPower, 1.0),
-                (Switch::CmdsJammerPower, 1.0),
-                (Switch::CmdsMwsPower, 1.0),
-                (Switch::CmdsExpendable1Power, 1.0),
-                (Switch::CmdsExpendable2Power, 1.0),
-                (Switch::CmdsExpendable3Power, 1.0),
-                (Switch::CmdsExpendable4Power, 1.0),
-                (Switch::MidsLvtControl, 0.2),
-                (Switch::IffMasterKnob, 0.3),
-                (Switch::UhfFunctionKnob, 0.2),
-                (Switch::CmdsProgramKnob, 0.1),
-                (Switch::CmdsModeKnob, 0.2),
-                (Switch::HudBrightnessKnob, 1.0),
-                (Switch::HmdIntensityKnob, 1.0),
-                (Switch::LaserArm, 1.0),
-                (Switch::RwrPower, 1.0),
-                (Switch::EjectionSafety, 1.0),
-                (Switch::InsKnob, 0.1),
-            ];
-            for (switch, state) in switch_states {
-                let _ = set_switch_state(lua, switch, state);
-            }
+// Steps of the startup a hand started jet can be picked up from.
+const CLOSING_CANOPY: &str = "Closing canopy";
+const SETTING_UP_AVIONICS: &str = "Setting up avionics";
+const WAITING_FOR_ALIGNMENT: &str = "Waiting for INS alignment";
+
+fn startup_procedure() -> Vec<Step<Switch>> {
+    vec![
+        Step::new(Action::Set(vec![(Switch::MainPower, 1.0)]))
+            .text("Setting up initial switches")
+            .weight(1.0),
+        Step::new(Action::Spring(Switch::Jfs, ThreePos::Down)),
+        Step::new(Action::Spring(Switch::CanopyRetract, ThreePos::Down)).text(CLOSING_CANOPY),
+        Step::new(Action::Set(AVIONICS.to_vec())).text(SETTING_UP_AVIONICS),
+        Step::new(Action::SetPosition(Switch::InsKnob, "STOR_HDG")),
+        Step::new(Action::WaitArgument(
+            Switch::CanopyValue,
+            Condition::Equals(0.0),
+        ))
+        .text("Waiting for canopy to close")
+        .weight(8.0),
+        // The F-16 continues to play a sound for a few seconds after the canopy state
+        // is reported at 0, and the aircraft seems to have a hidden/internal state that
+        // keeps track of how far "after fully closed" the canopy can be. Keep holding
+        // down the canopy close switch for a few more seconds.
+        Step::new(Action::Delay(2.3))
+            .text("Waiting for canopy to fully close")
+            .weight(2.3),
+        Step::new(Action::Spring(Switch::CanopyRetract, ThreePos::Middle))
+            .text("Releasing canopy close switch"),
+        Step::new(Action::WaitArgument(
+            Switch::CanopyRetract,
+            Condition::AtLeast(0.0),
+        )),
+        Step::new(Action::Set(vec![(Switch::CanopyLock, 1.0)])).text("Locking canopy"),
+        Step::new(Action::WaitArgument(
+            Switch::CanopyLock,
+            Condition::AtLeast(1.0),
+        ))
+        .weight(1.0),
+        Step::new(Action::WaitArgument(
+            Switch::EngineTachometer,
+            Condition::AtLeast(ENGINE_START_THRESHOLD),
+        ))
+        .text("Waiting for JFS")
+        .weight(6.0),
+        Step::new(Action::Lockon(LockonCommand::LeftEngineStart))
+            .text("Waiting for engine to spool"),
+        Step::new(Action::Set(vec![
+            (Switch::SaiCage, -1.0),
+            (Switch::SaiPitchTrim, 0.504),
+        ])),
+        Step::new(Action::Set(vec![(Switch::SaiCage, 0.0)])),
+        Step::new(Action::Spring(Switch::AltimeterModeLever, ThreePos::Down)),
+        // the DED comes on with the main generators
+        Step::new(Action::WaitIndication {
+            device: IndicationDevice::Ded as i32,
+            query: "",
+            expect: Expect::Shown,
         })
-        .wait()
-        .map_err(|_| crate::Error::CommError);
-}
-
-#[derive(Default, Debug, PartialEq, Clone)]
-struct Timer {
-    start_time: f32,
-    expire: f32,
-}
-
-impl Timer {
-    pub fn new(duration: f32, now: f32) -> Self {
-        Self {
-            start_time: now,
-            expire: duration,
-        }
+        .weight(25.0),
+        Step::new(Action::DualCommand(Switch::AntiSkid, ThreePos::Up)),
+        Step::new(Action::Spring(Switch::AltimeterModeLever, ThreePos::Middle)),
+        Step::new(Action::WaitIndication {
+            device: IndicationDevice::Hud as i32,
+            query: HUD_ALIGN_STATUS,
+            expect: Expect::Text("ALIGN"),
+        })
+        .text(WAITING_FOR_ALIGNMENT)
+        .weight(1.0),
+        Step::new(Action::WaitIndication {
+            device: IndicationDevice::Hud as i32,
+            query: HUD_ALIGN_STATUS,
+            expect: Expect::Absent,
+        })
+        .weight(90.0),
+        Step::new(Action::SetPosition(Switch::InsKnob, "NAV")),
+        // back to the CNI page
+        Step::new(Action::Spring(Switch::IcpDataRtnSeq, ThreePos::Down)),
+        Step::new(Action::WaitArgument(
+            Switch::IcpDataRtnSeq,
+            Condition::Equals(-1.0),
+        )),
+        Step::new(Action::Delay(0.1)),
+        Step::new(Action::Spring(Switch::IcpDataRtnSeq, ThreePos::Middle)),
+    ]
+}
+
+fn shutdown_procedure() -> Vec<Step<Switch>> {
+    vec![
+        Step::new(Action::SetPosition(Switch::InsKnob, "OFF"))
+            .text("Turning off INS")
+            .weight(1.0),
+        // the seat stays as it is, turning its safety off would arm it
+        Step::new(Action::Set(
+            AVIONICS
+                .iter()
+                .filter(|&&(switch, _)| !matches!(switch, Switch::EjectionSafety))
+                .map(|&(switch, _)| (switch, 0.0))
+                .collect(),
+        ))
+        .text("Turning off avionics")
+        .weight(1.0),
+        Step::new(Action::Spring(Switch::Jfs, ThreePos::Middle)),
+        Step::new(Action::Lockon(LockonCommand::LeftEngineStop))
+            .text("Waiting for engine to spool down"),
+        Step::new(Action::WaitArgument(
+            Switch::EngineTachometer,
+            Condition::AtMost(ENGINE_STOP_THRESHOLD),
+        ))
+        .weight(35.0),
+        // the canopy needs main power to move
+        Step::new(Action::Set(vec![(Switch::CanopyLock, 0.0)])).text("Opening canopy"),
+        Step::new(Action::Spring(Switch::CanopyRetract, ThreePos::Up)),
+        Step::new(Action::WaitArgument(
+            Switch::CanopyValue,
+            Condition::AtLeast(1.0),
+        ))
+        .weight(8.0),
+        Step::new(Action::Spring(Switch::CanopyRetract, ThreePos::Middle)),
+        Step::new(Action::Set(vec![(Switch::MainPower, 0.0)])).text("Turning off main power"),
+    ]
+}
+
+/// Engine RPM above which the throttle can be moved to idle.
+const ENGINE_START_THRESHOLD: f32 = 0.12;
+/// Engine RPM below which the engine is considered stopped.
+const ENGINE_STOP_THRESHOLD: f32 = 0.05;
+
+/// Finds how far the jet already is through the startup. Until the JFS turns
+/// the engine nothing in the startup does any harm to repeat; after that it
+/// is picked up past the engine start.
+fn survey(cockpit: &Cockpit<Switch>) -> Result<CockpitState, crate::Error> {
+    let [power, tachometer, canopy] = cockpit.arguments([
+        Switch::MainPower,
+        Switch::EngineTachometer,
+        Switch::CanopyValue,
+    ])?;
+    if power <= 0.0 || tachometer < ENGINE_START_THRESHOLD {
+        return Ok(CockpitState::ColdDark);
     }
-
-    pub fn is_expired(&self, now: f32) -> bool {
-        self.get_elapsed_time(now) >= self.expire
+    if canopy > 0.0 {
+        return Ok(CockpitState::StartedUpTo(CLOSING_CANOPY));
     }
-
-    pub fn get_elapsed_time(&self, now: f32) -> f32 {
-        now - self.start_time
+    let hud = IndicationDevice::Hud as i32;
+    if cockpit.indication(hud, HUD_ALIGN_STATUS).is_some() {
+        return Ok(CockpitState::StartedUpTo(WAITING_FOR_ALIGNMENT));
     }
-}
-
-#[derive(Clone, PartialEq, Debug)]
-enum StartupState {
-    ColdDark,
-    WaitCanopyClosed,
-    WaitAfterCanopyClosed,
-    WaitCanopySwitchRelease,
-    WaitCanopyLocked,
-    WaitJfsSpool,
-    WaitGeneratorsRunning,
-    WaitHudAlignMessage,
-    WaitNoHudAlignMessage,
-    Done,
+    if cockpit.is_at(Switch::InsKnob, "NAV")? {
+        return Ok(CockpitState::Running);
+    }
+    Ok(CockpitState::StartedUpTo(SETTING_UP_AVIONICS))
 }
 
 #[derive(Debug, Clone)]
 pub struct Fsm {
-    state: StartupState,
-    to_gamegui: TaskSender<Lua>,
-    to_export: TaskSender<Lua>,
-    gui: crate::gui::TxHandle,
-    sim_time: f32,
-    canopy_timer: Timer,
-    startup_timer: Timer,
+    procedures: Procedures<Switch>,
     avionics: AvionicsState,
 }
 
 const F16_STARTUP_TIME_MAX_SECONDS: f32 = 136.0;
 
-impl dcs::AircraftFsm for Fsm {
-    fn run_fsm(&mut self, event: FsmMessage, sim_time: f32) {
-        self.sim_time = sim_time;
-        match self.state {
-            StartupState::ColdDark => self.cold_dark_handler(event),
-            StartupState::WaitCanopyClosed => self.wait_canopy_closed(event),
-            StartupState::WaitAfterCanopyClosed => self.wait_after_canopy_closed(event),
-            StartupState::WaitCanopySwitchRelease => self.wait_canopy_switch_released(event),
-            StartupState::WaitCanopyLocked => self.wait_canopy_locked(event),
-            StartupState::WaitJfsSpool => self.wait_jfs_spool(event),
-            StartupState::WaitGeneratorsRunning => self.wait_generators_running(event),
-            StartupState::WaitHudAlignMessage => self.wait_hud_align_message(event),
-            StartupState::WaitNoHudAlignMessage => self.wait_no_hud_align_message(event),
-            StartupState::Done => self.done(event),
-        }
-        if self.state != StartupState::ColdDark && self.state != StartupState::Done {
-            self.gui.set_startup_progress(
-                self.startup_timer.get_elapsed_time(sim_time) / F16_STARTUP_TIME_MAX_SECONDS,
-            );
+pub struct Module;
+
+impl AircraftModule for Module {
+    fn display_name(&self) -> &'static str {
+        "F-16C block 50"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            startup: true,
+            shutdown: true,
         }
     }
-}
 
-impl Fsm {
-    pub fn new(
+    fn make_fsm(
+        &self,
         to_gamegui: TaskSender<Lua>,
         to_export: TaskSender<Lua>,
         gui: crate::gui::TxHandle,
-    ) -> Self {
-        Self {
-            state: StartupState::ColdDark,
-            to_gamegui,
-            to_export,
-            gui,
-            sim_time: 0.0,
-            canopy_timer: Timer::default(),
-            startup_timer: Timer::default(),
-            avionics: AvionicsState::default(),
-        }
-    }
-    fn cold_dark_handler(&mut self, event: crate::app::FsmMessage) {
-        match event {
-            crate::app::FsmMessage::StartupAircraft => {
-                self.startup_timer = Timer::new(0.0, self.sim_time);
-                // this should cause the progress bar to begin animating
-                self.gui.set_startup_progress(0.001);
-                self.gui.set_startup_text("Setting up initial switches");
-                self.gui.set_startup_progress(0.05);
-                self.gui.set_startup_text("Waiting for canopy to close");
-                self.to_gamegui.send(|lua| {
-                    let _ = set_switch_state(lua, Switch::MainPower, 1.0);
-                    let _ =
-                        set_three_pos_springloaded(lua, Switch::CanopyRetract, ThreePosState::Down);
-                    let _ = set_three_pos_springloaded(lua, Switch::Jfs, ThreePosState::Down);
-                });
-                throw_initial_switches(&self.to_gamegui);
-                self.state = StartupState::WaitCanopyClosed;
-            }
-            _ => {}
-        }
+    ) -> Box<dyn dcs::AircraftFsm> {
+        Box::new(Fsm::new(to_gamegui, to_export, gui))
     }
 
-    fn wait_canopy_closed(&mut self, event: crate::app::FsmMessage) {
-        let Ok(lua_result) = self
-            .to_gamegui
-            .send(|lua| get_switch_state(lua, Switch::CanopyValue))
-            .wait()
-        else {
-            return;
-        };
-
-        let Ok(canopy_position) = lua_result else {
-            log::warn!("Polling canopy position failed!");
-            return;
-        };
-
-        if canopy_position != 0.0 {
-            return;
-        }
-
-        // The F-16 continues to play a sound for a few seconds after the canopy state
-        // is reported at 0, and the aircraft seems to have a hidden/internal state that
-        // keeps track of how far "after fully closed" the canopy can be. Use a timer to
-        // continue holding down the canopy close switch for a few more seconds.
-        self.canopy_timer = Timer::new(2.3, self.sim_time);
-        self.gui
-            .set_startup_text("Waiting for canopy to fully close");
-
-        self.state = StartupState::WaitAfterCanopyClosed;
+    fn interlocks(&self) -> Interlocks {
+        let mut interlocks = Interlocks::default();
+        // the INS can't align while the jet is rolling
+        interlocks.startup.moving = Response::Refuse;
+        interlocks
     }
 
-    fn wait_after_canopy_closed(&mut self, event: crate::app::FsmMessage) {
-        if !self.canopy_timer.is_expired(self.sim_time) {
-            return;
-        }
-
-        self.gui.set_startup_progress(0.1);
-        self.gui.set_startup_text("Releasing canopy close switch");
-        let _ = self
-            .to_gamegui
-            .send(|lua| {
-                let _ = set_three_pos_springloaded(lua, Switch::CanopyRetract, ThreePosState::Stop);
-            })
-            .wait();
-
-        self.state = StartupState::WaitCanopySwitchRelease;
+    fn make_widget(&self) -> Option<Box<dyn AircraftWidget>> {
+        Some(Box::new(Gui::default()))
     }
 
-    fn wait_canopy_switch_released(&mut self, event: crate::app::FsmMessage) {
-        let Ok(lua_result) = self
-            .to_gamegui
-            .send(|lua| get_switch_state(lua, Switch::CanopyRetract))
-            .wait()
-        else {
-            return;
-        };
-
-        let Ok(canopy_switch_state) = lua_result else {
-            log::warn!("Polling canopy switch position failed!");
-            return;
-        };
-        log::debug!("Canopy switch state: {canopy_switch_state}");
-
-        if canopy_switch_state < 0.0 {
-            return;
-        }
-
-        self.to_gamegui.send(|lua| {
-            let _ = set_switch_state(lua, Switch::CanopyLock, 1.0);
-        });
-        self.gui.set_startup_text("Locking canopy");
-
-        self.state = StartupState::WaitCanopyLocked;
+    fn switches(&self) -> Option<&'static [SwitchDef]> {
+        Some(switches().defs())
     }
+}
 
-    fn wait_canopy_locked(&mut self, event: crate::app::FsmMessage) {
-        let Ok(lua_result) = self
-            .to_gamegui
-            .send(|lua| get_switch_state(lua, Switch::CanopyLock))
-            .wait()
-        else {
-            return;
-        };
-
-        let Ok(canopy_lock_lever_state) = lua_result else {
-            log::warn!("Couldn't read canopy lever state");
-            return;
-        };
-
-        if canopy_lock_lever_state < 1.0 {
-            return;
-        }
-        self.gui.set_startup_text("Waiting for JFS");
-        self.state = StartupState::WaitJfsSpool;
+impl dcs::AircraftFsm for Fsm {
+    fn run_fsm(&mut self, event: FsmMessage, clock: &dyn Clock) {
+        self.procedures.run_fsm(event, clock);
     }
 
-    fn wait_jfs_spool(&mut self, event: crate::app::FsmMessage) {
-        let Ok(lua_result) = self
-            .to_gamegui
-            .send(|lua| get_switch_state(lua, Switch::EngineTachometer))
-            .wait()
-        else {
-            return;
-        };
-
-        let Ok(engine_rpm_normalized) = lua_result else {
-            log::warn!("Couldn't read engine RPM");
-            return;
-        };
-
-        const ENGINE_START_THRESHOLD: f32 = 0.12;
-        if engine_rpm_normalized < ENGINE_START_THRESHOLD {
-            return;
-        }
+    fn subscriptions(&self) -> Vec<dcs::Subscription> {
+        self.procedures.subscriptions()
+    }
 
-        self.gui.set_startup_text("Waiting for engine to spool");
-        let _ = self
-            .to_gamegui
-            .send(|lua| set_lockon_command(lua, LockonCommand::LeftEngineStart))
-            .wait();
-
-        let _ = self
-            .to_gamegui
-            .send(|lua| {
-                let _ = set_switch_state(lua, Switch::SaiCage, -1.0);
-                let _ = set_switch_state(lua, Switch::SaiPitchTrim, 0.504);
-            })
-            .wait();
-        let _ = self.to_gamegui.send(|lua| {
-            let _ = set_switch_state(lua, Switch::SaiCage, 0.0);
-            let _ =
-                set_three_pos_springloaded(lua, Switch::AltimeterModeLever, ThreePosState::Down);
-        });
+    fn on_indication(&mut self, change: &dcs::IndicationChange) {
+        self.procedures.on_indication(change);
+    }
 
-        self.state = StartupState::WaitGeneratorsRunning;
+    fn wants_cockpit_params(&self) -> bool {
+        self.procedures.wants_cockpit_params()
     }
 
-    fn wait_generators_running(&mut self, event: crate::app::FsmMessage) {
-        let Ok(lua_result) = self
-            .to_export
-            .send(|lua| get_avionics_indication(lua, IndicationDevice::Ded))
-            .wait()
-        else {
-            return;
-        };
-        let Ok(indication) = lua_result else {
-            log::warn!("Couldn't get list indication when waiting for engine start");
-            return;
-        };
-        if indication.is_empty() {
-            return;
-        }
+    fn on_cockpit_params(&mut self, params: &std::sync::Arc<dcs::CockpitParams>) {
+        self.procedures.on_cockpit_params(params);
+    }
 
-        let _ = set_three_pos(&self.to_gamegui, Switch::AntiSkid, ThreePosToggleState::Up);
-        self.to_gamegui.send(|lua| {
-            let _ =
-                set_three_pos_springloaded(lua, Switch::AltimeterModeLever, ThreePosState::Stop);
-        });
-        self.gui.set_startup_text("Waiting for INS alignment");
-        self.state = StartupState::WaitHudAlignMessage;
+    fn wanted_arguments(&self) -> Vec<i32> {
+        self.procedures.wanted_arguments()
     }
 
-    fn wait_hud_align_message(&mut self, event: crate::app::FsmMessage) {
-        let val = get_hud_align_value(&self.to_export);
-        let Some(txt) = val else {
-            return;
-        };
-        if txt != "ALIGN" {
-            return;
-        }
-        self.state = StartupState::WaitNoHudAlignMessage;
+    fn on_arguments(&mut self, values: &std::sync::Arc<dcs::ArgumentValues>) {
+        self.procedures.on_arguments(values);
     }
+}
 
-    fn wait_no_hud_align_message(&mut self, event: crate::app::FsmMessage) {
-        let val = get_hud_align_value(&self.to_export);
-        let None = val else {
-            return;
-        };
-        self.state = StartupState::Done;
-        let _ = self
-            .to_gamegui
-            .send(|lua| set_switch_state(lua, Switch::InsKnob, 0.3))
-            .wait();
-
-        log::info!(
-            "Finished startup in {} seconds",
-            self.startup_timer.get_elapsed_time(self.sim_time)
-        );
-        self.gui.set_startup_progress(1.0);
-        ded_return(&self.to_gamegui);
-        if !is_on_cni(&self.to_export) {
-            log::warn!("Not on CNI page after dobbering left!");
+impl Fsm {
+    pub fn new(
+        to_gamegui: TaskSender<Lua>,
+        to_export: TaskSender<Lua>,
+        gui: crate::gui::TxHandle,
+    ) -> Self {
+        Self {
+            procedures: Procedures::new(
+                startup_procedure(),
+                shutdown_procedure(),
+                to_gamegui,
+                to_export,
+                gui,
+            )
+            .survey(survey),
+            avionics: AvionicsState::default(),
         }
-
-        self.gui.set_startup_text("DONE");
     }
-
-    fn done(&self, event: crate::app::FsmMessage) {}
 }
 
 fn bool_to_on_off(state: bool) -> &'static str {
@@ -1236,6 +960,12 @@ pub struct Gui {
     chaff_program_text_inputs: [CmdsProgramText; 6],
 }
 
+impl AircraftWidget for Gui {
+    fn show(&mut self, ui: &mut egui::Ui, ctx: &WidgetContext) {
+        self.make_widget(ui, ctx.app_runner);
+    }
+}
+
 impl Gui {
     fn update_text_fields(&mut self) {
         self.flare_bingo_quantity_raw = self.avionics_updated.cmds.bingo.flare.to_string();
@@ -1255,8 +985,10 @@ impl Gui {
                 if ui.button("Read").clicked() {
                     let _ = to_app
                         .send(move |(to_gamegui, to_export)| {
-                            let result = read_cmds(&to_gamegui, &to_export);
-                            *s.clone().lock().unwrap() = result.unwrap();
+                            match read_cmds(to_gamegui, to_export) {
+                                Some(avionics) => *s.clone().lock().unwrap() = avionics,
+                                None => log::warn!("Reading the CMDS programs failed"),
+                            }
                         })
                         .wait();
                     self.avionics_updated = self.avionics.lock().unwrap().clone();
@@ -1390,3 +1122,271 @@ impl Gui {
             .show(ui, countermeasures_section);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{get_switch_argument, get_switch_info, switches, Fsm, Switch};
+    use crate::app::FsmMessage;
+    use crate::clock::ManualClock;
+    use crate::dcs::sequence::{Action, Expect, SequenceState};
+    use crate::dcs::testing::Harness;
+
+    fn waiting_on_jfs(fsm: &Fsm) -> bool {
+        fsm.procedures.startup().current().is_some_and(|step| {
+            matches!(
+                step.action,
+                Action::WaitArgument(Switch::EngineTachometer, _)
+            )
+        })
+    }
+
+    #[test]
+    fn test_startup_until_jfs() {
+        let harness = Harness::new("F-16C_50");
+        let canopy_position = get_switch_argument(Switch::CanopyValue);
+        harness.dcs.state().set_argument(canopy_position, 1.0);
+
+        let retract = get_switch_info(Switch::CanopyRetract).unwrap();
+        let retract = (retract.device_id, retract.command);
+        let lock = get_switch_info(Switch::CanopyLock).unwrap();
+        let (lock, lock_argument) = ((lock.device_id, lock.command), lock.argument);
+        harness.dcs.on_click(move |state, click| {
+            let pair = (click.device_id, click.command);
+            if pair == retract && click.value < 0.0 {
+                state.set_argument(canopy_position, 0.0);
+            } else if pair == lock {
+                state.set_argument(lock_argument, click.value);
+            }
+        });
+
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            10.0,
+            waiting_on_jfs,
+        );
+        assert!(waiting_on_jfs(&fsm));
+
+        let state = harness.dcs.state();
+        // the canopy switch is held for a while after the canopy reports closed
+        assert!(state.model_time >= 2.3);
+        let main_power = get_switch_info(Switch::MainPower).unwrap();
+        let clicks = state.clicks_on(main_power.device_id, main_power.command);
+        assert_eq!(clicks.len(), 1);
+        assert_eq!(clicks[0].value, 1.0);
+    }
+
+    #[test]
+    fn test_startup_fails_when_canopy_stuck() {
+        let harness = Harness::new("F-16C_50");
+        let canopy_position = get_switch_argument(Switch::CanopyValue);
+        harness.dcs.state().set_argument(canopy_position, 1.0);
+
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            60.0,
+            |fsm: &Fsm| matches!(fsm.procedures.startup().state(), SequenceState::Failed(_)),
+        );
+        let SequenceState::Failed(failure) = fsm.procedures.startup().state() else {
+            panic!("startup didn't fail");
+        };
+        assert_eq!(failure.stage, Some("Waiting for canopy to close"));
+        assert!(failure.reason.contains("CanopyValue == 0"), "{failure}");
+        assert!(failure.reason.contains("last saw 1"), "{failure}");
+        // the deadline is the step's weight plus the margin
+        assert!(harness.dcs.state().model_time < 40.0);
+
+        // the canopy switch is let go
+        let retract = get_switch_argument(Switch::CanopyRetract);
+        assert_eq!(harness.dcs.state().argument(retract), 0.0);
+    }
+
+    #[test]
+    fn test_interrupt_releases_springs_and_restarts() {
+        use dcs_mock::models::f16c50::Model;
+        let harness = Harness::with_model("F-16C_50", Box::new(Model::new()));
+        let closing_canopy = |fsm: &Fsm| {
+            fsm.procedures.startup().current().is_some_and(|step| {
+                matches!(step.action, Action::WaitArgument(Switch::CanopyValue, _))
+            })
+        };
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            10.0,
+            closing_canopy,
+        );
+        assert!(closing_canopy(&fsm));
+        let retract = get_switch_argument(Switch::CanopyRetract);
+        let jfs = get_switch_argument(Switch::Jfs);
+        assert_eq!(harness.dcs.state().argument(retract), -1.0);
+        assert_eq!(harness.dcs.state().argument(jfs), -1.0);
+
+        let fsm = harness.run(
+            move |_, _, _| fsm,
+            FsmMessage::InterruptAircraftStart,
+            0.1,
+            20.0,
+            |_: &Fsm| true,
+        );
+        assert_eq!(*fsm.procedures.startup().state(), SequenceState::Idle);
+        assert_eq!(harness.dcs.state().argument(retract), 0.0);
+        assert_eq!(harness.dcs.state().argument(jfs), 0.0);
+
+        let fsm = harness.run(
+            move |_, _, _| fsm,
+            FsmMessage::StartupAircraft,
+            0.1,
+            300.0,
+            |fsm: &Fsm| *fsm.procedures.startup().state() == SequenceState::Done,
+        );
+        assert_eq!(*fsm.procedures.startup().state(), SequenceState::Done);
+    }
+
+    #[test]
+    fn test_full_autostart() {
+        use super::F16_STARTUP_TIME_MAX_SECONDS;
+        use dcs_mock::models::f16c50::{Model, ALIGN_TIME};
+        let harness = Harness::with_model("F-16C_50", Box::new(Model::new()));
+
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            F16_STARTUP_TIME_MAX_SECONDS * 2.0,
+            |fsm: &Fsm| *fsm.procedures.startup().state() == SequenceState::Done,
+        );
+        assert_eq!(*fsm.procedures.startup().state(), SequenceState::Done);
+
+        // the INS alignment is the long pole
+        let clock = ManualClock::default();
+        clock.set(harness.dcs.state().model_time);
+        let elapsed = fsm.procedures.startup().elapsed(&clock);
+        assert!(elapsed >= ALIGN_TIME, "finished after {elapsed}s");
+        assert!(
+            elapsed < F16_STARTUP_TIME_MAX_SECONDS,
+            "finished after {elapsed}s"
+        );
+        assert_eq!(harness.dcs.state().lockon_commands, vec![311]);
+    }
+
+    #[test]
+    fn test_hot_start_is_left_alone() {
+        use dcs_mock::models::f16c50::Model;
+        let harness = Harness::with_model("F-16C_50", Box::new(Model::new()));
+        harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            300.0,
+            |fsm: &Fsm| *fsm.procedures.startup().state() == SequenceState::Done,
+        );
+        let clicks = harness.dcs.state().clicks.len();
+
+        // entering the running jet again
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            310.0,
+            |_: &Fsm| true,
+        );
+        assert_eq!(*fsm.procedures.startup().state(), SequenceState::Idle);
+        let state = harness.dcs.state();
+        assert_eq!(state.clicks.len(), clicks);
+        assert_eq!(state.lockon_commands, vec![311]);
+    }
+
+    #[test]
+    fn test_startup_resumes_during_alignment() {
+        use dcs_mock::models::f16c50::{Model, ALIGN_TIME};
+        let harness = Harness::with_model("F-16C_50", Box::new(Model::new()));
+        let aligning = |fsm: &Fsm| {
+            fsm.procedures.startup().current().is_some_and(|step| {
+                matches!(
+                    step.action,
+                    Action::WaitIndication {
+                        expect: Expect::Absent,
+                        ..
+                    }
+                )
+            })
+        };
+        harness.run(Fsm::new, FsmMessage::StartupAircraft, 0.1, 100.0, aligning);
+        let jfs = get_switch_info(Switch::Jfs).unwrap();
+        let main_power = get_switch_info(Switch::MainPower).unwrap();
+        let (jfs_clicks, power_clicks, started) = {
+            let state = harness.dcs.state();
+            (
+                state.clicks_on(jfs.device_id, jfs.command).len(),
+                state
+                    .clicks_on(main_power.device_id, main_power.command)
+                    .len(),
+                state.model_time,
+            )
+        };
+
+        // a new FSM, as if the jet had been started by hand this far
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            started + ALIGN_TIME + 10.0,
+            |fsm: &Fsm| *fsm.procedures.startup().state() == SequenceState::Done,
+        );
+        assert_eq!(*fsm.procedures.startup().state(), SequenceState::Done);
+        let state = harness.dcs.state();
+        assert_eq!(
+            state.clicks_on(jfs.device_id, jfs.command).len(),
+            jfs_clicks
+        );
+        assert_eq!(
+            state
+                .clicks_on(main_power.device_id, main_power.command)
+                .len(),
+            power_clicks
+        );
+        assert_eq!(state.lockon_commands, vec![311]);
+        let nav = switches().position(Switch::InsKnob, "NAV").unwrap();
+        assert_eq!(state.argument(get_switch_argument(Switch::InsKnob)), nav);
+    }
+
+    #[test]
+    fn test_shutdown_after_autostart() {
+        use dcs_mock::models::f16c50::Model;
+        let harness = Harness::with_model("F-16C_50", Box::new(Model::new()));
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            300.0,
+            |fsm: &Fsm| *fsm.procedures.startup().state() == SequenceState::Done,
+        );
+
+        let fsm = harness.run(
+            move |_, _, _| fsm,
+            FsmMessage::ShutdownAircraft,
+            0.1,
+            400.0,
+            |fsm: &Fsm| *fsm.procedures.shutdown().state() == SequenceState::Done,
+        );
+        assert_eq!(*fsm.procedures.shutdown().state(), SequenceState::Done);
+
+        let state = harness.dcs.state();
+        assert_eq!(state.lockon_commands, vec![311, 313]);
+        assert!(state.argument(get_switch_argument(Switch::EngineTachometer)) <= 0.05);
+        assert!(state.argument(get_switch_argument(Switch::CanopyValue)) >= 1.0);
+        assert_eq!(
+            state.argument(get_switch_argument(Switch::CanopyRetract)),
+            0.0
+        );
+        assert_eq!(state.argument(get_switch_argument(Switch::MainPower)), 0.0);
+        assert_eq!(state.argument(get_switch_argument(Switch::InsKnob)), 0.0);
+        let seat = get_switch_argument(Switch::EjectionSafety);
+        assert_eq!(state.argument(seat), 1.0);
+    }
+}
@@ -0,0 +1,668 @@
+//! Parses the text returned by DCS's `list_indication(device)`.
+//!
+//! The text is a list of elements. Each starts with a separator line of 41
+//! dashes, then the element's name on one line, then its value. An element
+//! with children is followed by a `children are {` line, the children, and a
+//! `}` line:
+//!
+//! ```text
+//! -----------------------------------------
+//! HUD_Window7_origin
+//!
+//! children are {
+//! -----------------------------------------
+//! HUD_Window7_AlignmentStatus
+//! ALIGN
+//! }
+//! ```
+//!
+//! DCS doesn't end the last value with a newline, so an element with an empty
+//! value at the very end is just the separator and the name.
+//!
+//! A value can span several lines. The line after the name always belongs to
+//! the value, whatever it says, so an empty value or one that is just `}` is
+//! read correctly. Later lines of a value that are exactly a separator,
+//! `children are {` or `}` can't be told apart from the structure, and are
+//! read as structure.
+//!
+//! The parsed [`Indication`] borrows its names and values from the text, and
+//! indexes every element by its path of names for exact lookups. For
+//! anything else there is a small query syntax, see [`Indication::query`].
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Lines;
+
+pub const SEPARATOR: &str = "-----------------------------------------";
+const OPEN: &str = "children are {";
+const CLOSE: &str = "}";
+
+/// One element of an indication.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndicationNode<'a> {
+    pub field: &'a str,
+    /// Only owned if the value spans several lines ending in `\r\n`, which
+    /// have to be put back together with plain newlines.
+    pub value: Cow<'a, str>,
+    children: Vec<usize>,
+}
+
+impl<'a> IndicationNode<'a> {
+    fn new(field: &'a str, value: Cow<'a, str>) -> Self {
+        Self {
+            field,
+            value,
+            children: vec![],
+        }
+    }
+}
+
+/// A parsed `list_indication`.
+///
+/// Since there can be several top level elements, and a tree only has a
+/// single root, they are the children of an empty element called "root".
+#[derive(Debug, Clone)]
+pub struct Indication<'a> {
+    /// Every element in the order of the text, starting with the root.
+    nodes: Vec<IndicationNode<'a>>,
+    /// The first element at each path of names below the root.
+    index: HashMap<Vec<&'a str>, usize>,
+}
+
+const ROOT: usize = 0;
+
+impl<'a> Indication<'a> {
+    pub fn root(&self) -> &IndicationNode<'a> {
+        &self.nodes[ROOT]
+    }
+
+    pub fn children<'t>(
+        &'t self,
+        node: &'t IndicationNode<'a>,
+    ) -> impl Iterator<Item = &'t IndicationNode<'a>> + 't {
+        node.children.iter().map(|&child| &self.nodes[child])
+    }
+
+    /// The element at `path`, a name for each level below the root, or the
+    /// first one if there are several with the same names. An empty path is
+    /// the root.
+    pub fn get(&self, path: &[&str]) -> Option<&IndicationNode<'a>> {
+        if path.is_empty() {
+            return Some(self.root());
+        }
+        let index: &HashMap<Vec<&str>, usize> = &self.index;
+        index.get(path).map(|&id| &self.nodes[id])
+    }
+
+    /// Every element matching `query`, in the order of the text.
+    ///
+    /// A query is a list of segments separated by `/`, each matched against
+    /// the children of what the segments before it matched:
+    ///
+    /// - a name matches the children with exactly that name,
+    /// - a `*` in a name stands for any run of characters, so `*` matches
+    ///   every child and `CMDS_*_label` the CMDS page's labels,
+    /// - `**` followed by a segment matches the first element down each
+    ///   branch that matches the segment, however deep it is. A trailing
+    ///   `**` matches everything below.
+    ///
+    /// `**/HUD_Window7_AlignmentStatus` finds that element without naming
+    /// the placeholders it sits in. An empty query matches the root.
+    pub fn query(&self, query: &str) -> Vec<&IndicationNode<'a>> {
+        let mut matches = vec![ROOT];
+        let mut segments = query.split('/').filter(|_| !query.is_empty());
+        while let Some(segment) = segments.next() {
+            let mut next = vec![];
+            for &id in &matches {
+                match segment {
+                    "**" => match segments.clone().next() {
+                        Some(pattern) => self.first_descendants(id, pattern, &mut next),
+                        None => self.descendants(id, &mut next),
+                    },
+                    _ => next.extend(
+                        self.nodes[id]
+                            .children
+                            .iter()
+                            .filter(|&&child| glob(segment, self.nodes[child].field)),
+                    ),
+                }
+            }
+            if segment == "**" {
+                segments.next();
+            }
+            matches = next;
+        }
+        matches.into_iter().map(|id| &self.nodes[id]).collect()
+    }
+
+    /// The first element matching `query`, see [`Self::query`].
+    pub fn first(&self, query: &str) -> Option<&IndicationNode<'a>> {
+        self.query(query).into_iter().next()
+    }
+
+    fn first_descendants(&self, id: usize, pattern: &str, out: &mut Vec<usize>) {
+        for &child in &self.nodes[id].children {
+            if glob(pattern, self.nodes[child].field) {
+                out.push(child);
+            } else {
+                self.first_descendants(child, pattern, out);
+            }
+        }
+    }
+
+    fn descendants(&self, id: usize, out: &mut Vec<usize>) {
+        for &child in &self.nodes[id].children {
+            out.push(child);
+            self.descendants(child, out);
+        }
+    }
+}
+
+/// One line per element, indented by its depth.
+impl fmt::Display for Indication<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write<'a>(
+            tree: &Indication<'a>,
+            node: &IndicationNode<'a>,
+            depth: usize,
+            f: &mut fmt::Formatter<'_>,
+        ) -> fmt::Result {
+            let indent = "  ".repeat(depth);
+            writeln!(f, "{indent}{} = {:?}", node.field, node.value)?;
+            for child in tree.children(node) {
+                write(tree, child, depth + 1, f)?;
+            }
+            Ok(())
+        }
+        for child in self.children(self.root()) {
+            write(self, child, 0, f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `name` matches `pattern`, in which each `*` stands for any run of
+/// characters.
+fn glob(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let Some(mut last) = parts.next() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(last) {
+            Some(at) => rest = &rest[at + last.len()..],
+            None => return false,
+        }
+        last = part;
+    }
+    rest.ends_with(last)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Something other than a separator or a `}` where an element should
+    /// start.
+    ExpectedSeparator,
+    /// A separator that isn't followed by a name.
+    MissingName,
+    /// A name at the very end of the text, without even a newline.
+    MissingValue,
+    /// A `}` without a `children are {` to close.
+    UnmatchedClose,
+    /// The text ended inside the `children are {` on line `opened_at`.
+    Unclosed { opened_at: usize },
+}
+
+/// Where and why the text isn't a valid indication. Lines and columns count
+/// from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExpectedSeparator => write!(f, "expected a separator or `}}`"),
+            Self::MissingName => write!(f, "expected an element name"),
+            Self::MissingValue => write!(f, "expected a value"),
+            Self::UnmatchedClose => write!(f, "`}}` without a matching `children are {{`"),
+            Self::Unclosed { opened_at } => {
+                write!(f, "`children are {{` on line {opened_at} is never closed")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl From<ParseError> for crate::Error {
+    fn from(e: ParseError) -> Self {
+        crate::Error::ParseError(format!("Invalid indication at {e}"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Separator,
+    Open,
+    Close,
+    Text(&'a str),
+}
+
+impl<'a> Token<'a> {
+    fn of(line: &'a str) -> Self {
+        match line {
+            SEPARATOR => Self::Separator,
+            OPEN => Self::Open,
+            CLOSE => Self::Close,
+            _ => Self::Text(line),
+        }
+    }
+}
+
+/// The lines of the text, numbered from 1, and where the text ends.
+struct Tokens<'a> {
+    lines: Peekable<std::iter::Zip<std::ops::RangeFrom<usize>, Lines<'a>>>,
+    end: (usize, usize),
+    /// Whether the last line is empty, which `Lines` doesn't return.
+    ends_with_newline: bool,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Self {
+        let last_line = text.rsplit('\n').next().unwrap_or_default();
+        Self {
+            lines: (1..).zip(text.lines()).peekable(),
+            end: (
+                text.matches('\n').count() + 1,
+                last_line.chars().count() + 1,
+            ),
+            ends_with_newline: text.ends_with('\n'),
+        }
+    }
+
+    fn next(&mut self) -> Option<(usize, Token<'a>)> {
+        self.lines.next().map(|(n, line)| (n, Token::of(line)))
+    }
+
+    fn peek(&mut self) -> Option<Token<'a>> {
+        self.lines.peek().map(|&(_, line)| Token::of(line))
+    }
+
+    fn error_at(&self, line: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line,
+            column: 1,
+            kind,
+        }
+    }
+
+    fn error_at_end(&self, kind: ParseErrorKind) -> ParseError {
+        let (line, column) = self.end;
+        ParseError { line, column, kind }
+    }
+}
+
+/// Where `line`, a line of `text`, starts in it.
+fn offset(text: &str, line: &str) -> usize {
+    line.as_ptr() as usize - text.as_ptr() as usize
+}
+
+/// Parses the text of `list_indication`, which is empty for a display that
+/// is off.
+pub fn parse(text: &str) -> Result<Indication<'_>, ParseError> {
+    let mut nodes = vec![IndicationNode::new("root", Cow::Borrowed(""))];
+    let mut index = HashMap::new();
+    let mut tokens = Tokens::new(text);
+    // the element whose children are being read, the names on the way down
+    // to it, and the `children are {` lines of the ones it is nested in
+    let mut parent = ROOT;
+    let mut path: Vec<&str> = vec![];
+    let mut open: Vec<(usize, usize)> = vec![];
+
+    while let Some((n, token)) = tokens.next() {
+        match token {
+            Token::Separator => {}
+            Token::Close => {
+                let Some((outer, _)) = open.pop() else {
+                    return Err(tokens.error_at(n, ParseErrorKind::UnmatchedClose));
+                };
+                parent = outer;
+                path.pop();
+                continue;
+            }
+            Token::Open | Token::Text(_) => {
+                return Err(tokens.error_at(n, ParseErrorKind::ExpectedSeparator))
+            }
+        }
+
+        let field = match tokens.next() {
+            Some((_, Token::Text(field))) => field,
+            Some((n, _)) => return Err(tokens.error_at(n, ParseErrorKind::MissingName)),
+            None => return Err(tokens.error_at_end(ParseErrorKind::MissingName)),
+        };
+        let first = match tokens.lines.next() {
+            Some((_, first)) => first,
+            None if tokens.ends_with_newline => "",
+            None => return Err(tokens.error_at_end(ParseErrorKind::MissingValue)),
+        };
+        let mut last = first;
+        while let Some(Token::Text(line)) = tokens.peek() {
+            last = line;
+            tokens.next();
+        }
+        let value = if std::ptr::eq(first, last) {
+            Cow::Borrowed(first)
+        } else {
+            let span = &text[offset(text, first)..offset(text, last) + last.len()];
+            if span.contains('\r') {
+                Cow::Owned(span.lines().collect::<Vec<_>>().join("\n"))
+            } else {
+                Cow::Borrowed(span)
+            }
+        };
+
+        let id = nodes.len();
+        nodes.push(IndicationNode::new(field, value));
+        nodes[parent].children.push(id);
+        let mut key = path.clone();
+        key.push(field);
+        index.entry(key).or_insert(id);
+        if tokens.peek() == Some(Token::Open) {
+            let (n, _) = tokens.next().expect("just peeked");
+            open.push((parent, n));
+            parent = id;
+            path.push(field);
+        }
+    }
+
+    if let Some(&(_, opened_at)) = open.last() {
+        return Err(tokens.error_at_end(ParseErrorKind::Unclosed { opened_at }));
+    }
+    Ok(Indication { nodes, index })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{glob, parse, Indication, IndicationNode, ParseError, ParseErrorKind, SEPARATOR};
+    use dcs_mock::indication::{format, Element};
+    use proptest::prelude::*;
+    use std::path::PathBuf;
+
+    /// Dumps checked against a `.tree` file of the same name, which setting
+    /// `YAWE_BLESS` rewrites from what the parser returns.
+    const CORPUS: &[&str] = &[
+        "f16_hud_align",
+        "f16_ded_cni",
+        "f16_ded_cmds",
+        "f16_mfd_left",
+        "f16_rwr",
+        "f16_uhf",
+    ];
+
+    fn resource(name: &str) -> String {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join(name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn to_elements(tree: &Indication) -> Vec<Element> {
+        fn to_element<'a>(tree: &Indication<'a>, node: &IndicationNode<'a>) -> Element {
+            Element {
+                name: node.field.to_string(),
+                value: node.value.to_string(),
+                children: tree
+                    .children(node)
+                    .map(|child| to_element(tree, child))
+                    .collect(),
+            }
+        }
+        tree.children(tree.root())
+            .map(|child| to_element(tree, child))
+            .collect()
+    }
+
+    fn values<'t>(nodes: Vec<&'t IndicationNode>) -> Vec<&'t str> {
+        nodes.into_iter().map(|node| node.value.as_ref()).collect()
+    }
+
+    #[test]
+    fn test_corpus() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources");
+        for name in CORPUS {
+            let text = resource(&format!("{name}.txt"));
+            let tree = parse(&text).unwrap_or_else(|e| panic!("{name}: {e}"));
+            let rendered = tree.to_string();
+            let golden = dir.join(format!("{name}.tree"));
+            if std::env::var_os("YAWE_BLESS").is_some() {
+                std::fs::write(&golden, &rendered).unwrap();
+            }
+            let expected = std::fs::read_to_string(&golden).unwrap();
+            assert_eq!(rendered, expected, "{name} doesn't match {golden:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_awkward_values() {
+        let text = [
+            SEPARATOR,
+            "DED CNI TACAN PH",
+            "",
+            "children are {",
+            SEPARATOR,
+            "Brace",
+            "}",
+            SEPARATOR,
+            "Dashes",
+            SEPARATOR,
+            SEPARATOR,
+            "Two lines",
+            "A-A",
+            "MSL",
+            "}",
+            SEPARATOR,
+            "Last",
+            "",
+        ]
+        .join("\n");
+        let tree = parse(&text).unwrap();
+        assert_eq!(
+            tree.to_string(),
+            "DED CNI TACAN PH = \"\"\n  Brace = \"}\"\n  Dashes = \"-----------------------------------------\"\n  Two lines = \"A-A\\nMSL\"\nLast = \"\"\n"
+        );
+        assert!(parse("").unwrap().query("**").is_empty());
+
+        let crlf = text.replace('\n', "\r\n");
+        let crlf = parse(&crlf).unwrap();
+        assert_eq!(crlf.to_string(), tree.to_string());
+    }
+
+    #[test]
+    fn test_get() {
+        let text = resource("f16_hud_align.txt");
+        let tree = parse(&text).unwrap();
+        let node = tree.get(&[
+            "HUD_BlankRoot_PH_com",
+            "HUD_Indication_bias",
+            "HUD_Mach_num_origin",
+            "HUD_Window4_MachNumber_dot",
+        ]);
+        assert_eq!(node.unwrap().value, ".");
+        assert!(matches!(node.unwrap().value, std::borrow::Cow::Borrowed(_)));
+        assert!(tree.get(&["HUD_Indication_bias"]).is_none());
+        assert_eq!(tree.get(&[]).unwrap().field, "root");
+
+        let text = format(&[
+            Element::new("Label", "first"),
+            Element::new("Label", "second"),
+        ]);
+        assert_eq!(
+            parse(&text).unwrap().get(&["Label"]).unwrap().value,
+            "first"
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let text = resource("f16_ded_cmds.txt");
+        let ded = parse(&text).unwrap();
+        assert_eq!(
+            values(ded.query("CMDS_*_label")),
+            ["PRGM", "CMDS CHAFF", "BQ", "BI", "SQ", "SI"]
+        );
+        assert_eq!(
+            values(ded.query("CMDS_B*_Scratchpad_placeholder/*")),
+            ["   1", "  0.020"]
+        );
+        assert_eq!(values(ded.query("**/CMDS_SI_Scratchpad")), ["   0.50"]);
+        assert_eq!(ded.query("**/*Scratchpad").len(), 4);
+        assert_eq!(ded.query("*/*").len(), 4);
+        assert_eq!(ded.query("**").len(), ded.nodes.len() - 1);
+        assert_eq!(ded.first("").unwrap().field, "root");
+        assert!(ded.first("CMDS_FLARE_label").is_none());
+
+        let text = resource("f16_hud_align.txt");
+        let hud = parse(&text).unwrap();
+        let origins = hud.query("**/PL_pitch_line_*_origin");
+        assert!(origins.len() > 1);
+        assert!(origins.iter().all(|node| node.field.ends_with("_origin")));
+        // the first descendant on each branch, not the ones below it
+        assert_eq!(
+            values(hud.query("**/PL_pitch_line_-15_origin/**/*_left")),
+            ["", "", "15"]
+        );
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob("HUD", "HUD"));
+        assert!(!glob("HUD", "HUD_glass"));
+        assert!(glob("HUD*", "HUD_glass"));
+        assert!(glob("*glass", "HUD_glass"));
+        assert!(glob("*", ""));
+        assert!(glob("H*_*s", "HUD_glass"));
+        assert!(!glob("H*_*x", "HUD_glass"));
+        assert!(!glob("*ab*ab", "ab"));
+        assert!(glob("a*a", "aa"));
+        assert!(!glob("a*a", "a"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text: &str| parse(text).unwrap_err();
+        let at = |line, column, kind| ParseError { line, column, kind };
+
+        assert_eq!(
+            error("HUD_glass\n\n"),
+            at(1, 1, ParseErrorKind::ExpectedSeparator)
+        );
+        assert_eq!(
+            error(&format!("{SEPARATOR}\nHUD_glass\n\n}}\n")),
+            at(4, 1, ParseErrorKind::UnmatchedClose)
+        );
+        assert_eq!(
+            error(&format!("{SEPARATOR}\n}}\n")),
+            at(2, 1, ParseErrorKind::MissingName)
+        );
+        assert_eq!(
+            error(&format!("{SEPARATOR}\nHUD_glass")),
+            at(2, 10, ParseErrorKind::MissingValue)
+        );
+        assert_eq!(
+            error(&format!(
+                "{SEPARATOR}\nroot\n\nchildren are {{\n{SEPARATOR}\nleaf\n1\n"
+            )),
+            at(8, 1, ParseErrorKind::Unclosed { opened_at: 4 })
+        );
+        assert_eq!(
+            error(&format!("{SEPARATOR}\nroot\n\nchildren are {{\nleaf\n")),
+            at(5, 1, ParseErrorKind::ExpectedSeparator)
+        );
+    }
+
+    /// Lines that are mostly structure, so that generated text gets deep into
+    /// the parser rather than failing on the first line.
+    fn line() -> impl Strategy<Value = String> {
+        prop_oneof![
+            Just(String::from(SEPARATOR)),
+            Just(String::from("children are {")),
+            Just(String::from("}")),
+            Just(String::new()),
+            "[a-z{} -]{1,6}",
+        ]
+    }
+
+    fn element() -> impl Strategy<Value = Element> {
+        let value = (
+            "[^\r\n]{0,12}",
+            prop::collection::vec("[a-z0-9 .:-]{1,8}", 0..3),
+        )
+            .prop_map(|(first, rest)| {
+                std::iter::once(first)
+                    .chain(rest)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+        let leaf = ("[A-Za-z0-9_ ]{0,12}", value).prop_map(|(name, value)| Element {
+            name,
+            value,
+            children: vec![],
+        });
+        leaf.prop_recursive(4, 32, 4, |inner| {
+            (
+                "[A-Za-z0-9_ ]{0,12}",
+                "[^\r\n]{0,12}",
+                prop::collection::vec(inner, 1..4),
+            )
+                .prop_map(|(name, value, children)| Element {
+                    name,
+                    value,
+                    children,
+                })
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_never_panics(text in "\\PC*") {
+            let _ = parse(&text);
+        }
+
+        #[test]
+        fn test_query_never_panics(query in "[a-z*/]{0,12}") {
+            let text = resource("f16_hud_align.txt");
+            let _ = parse(&text).unwrap().query(&query);
+        }
+
+        #[test]
+        fn test_parse_never_panics_on_structure(lines in prop::collection::vec(line(), 0..48)) {
+            let _ = parse(&lines.join("\n"));
+            let _ = parse(&lines.join("\r\n"));
+        }
+
+        #[test]
+        fn test_parse_round_trip(elements in prop::collection::vec(element(), 0..6)) {
+            let text = format(&elements);
+            let tree = parse(&text).unwrap();
+            prop_assert_eq!(to_elements(&tree), elements);
+        }
+    }
+}
@@ -1,14 +1,16 @@
 use crate::app::FsmMessage;
-use crate::dcs;
-use egui_backend::egui;
-use mlua::prelude::LuaResult;
+use crate::clock::Clock;
+use crate::dcs::{self, LockonCommand};
 use mlua::Lua;
 use offload::TaskSender;
-use strum::IntoStaticStr;
+use std::sync::OnceLock;
+use strum::{EnumIter, IntoStaticStr};
 
-use super::get_cockpit_param;
+use super::aircraft::{AircraftModule, Capabilities};
+use super::sequence::{Action, Cockpit, CockpitState, Condition, Procedures, Step};
+use super::switches::{SwitchDef, SwitchEnum, SwitchTable};
 
-#[derive(Debug, Clone, Copy, IntoStaticStr)]
+#[derive(Debug, Clone, Copy, IntoStaticStr, EnumIter)]
 #[allow(dead_code)]
 pub enum Switch {
     FuelPump1,
@@ -69,153 +71,223 @@ pub enum Switch {
     NumSwitches,
 }
 
-type Info = dcs::SwitchInfo<Switch>;
-
-pub const SWITCH_INFO_MAP: [Info; Switch::NumSwitches as usize] = [
-    Info::new(Switch::FuelPump1, 4, 3011, 160),
-    Info::new(Switch::FuelPump3, 4, 3010, 159),
-    Info::new(Switch::FuelPumpDrain, 4, 3012, 161),
-    Info::new(Switch::BatteryOn, 1, 3001, 165),
-    Info::new(Switch::BatteryHeat, 1, 3002, 155),
-    Info::new(Switch::AcGenerator, 2, 3004, 169),
-    Info::new(Switch::DcGenerator, 1, 3003, 166),
-    Info::new(Switch::SprdPower, 48, 3106, 167),
-    Info::new(Switch::SprdDropPower, 48, 3107, 168),
-    Info::new(Switch::Po750Inverter1, 2, 3005, 153),
-    Info::new(Switch::Po750Inverter2, 2, 3006, 154),
-    Info::new(Switch::ApuPower, 3, 3014, 302),
-    Info::new(Switch::FireExtinguisherPower, 53, 3025, 303),
-    Info::new(Switch::ThrottleStopLock, 3, 3238, 616),
-    Info::new(Switch::CanopyOpen, 43, 3152, 375),
-    Info::new(Switch::CanopyClose, 43, 3194, 385),
-    Info::new(Switch::CanopyLock, 43, 3151, 329),
-    Info::new(Switch::CanopySeal, 43, 3150, 328),
-    Info::new(Switch::EngineStart, 3, 3016, 289),
-    Info::new(Switch::EngineStartLight, 0, 0, 509),
-    Info::new(Switch::Gyro1, 21, 3008, 162),
-    Info::new(Switch::Gyro2, 21, 3009, 163),
-    Info::new(Switch::SrzoPower, 38, 3087, 188),
-    Info::new(Switch::SauPower, 8, 3064, 179),
-    Info::new(Switch::SauPitchPower, 8, 3065, 180),
-    Info::new(Switch::TrimmerPower, 9, 3131, 172),
-    Info::new(Switch::NoseconePower, 17, 3133, 170),
-    Info::new(Switch::EmergencyHydroPump, 44, 3137, 171),
-    Info::new(Switch::KppMainEmergencyToggle, 28, 3139, 177),
-    Info::new(Switch::NppPower, 23, 3142, 178),
-    Info::new(Switch::RadAltPower, 33, 3145, 175),
-    Info::new(Switch::AspPower, 41, 3155, 186),
-    Info::new(Switch::MissileHeatPower, 42, 3167, 181),
-    Info::new(Switch::MissileLaunchPower, 42, 3168, 182),
-    Info::new(Switch::InboardPylonPower, 42, 3169, 183),
-    Info::new(Switch::OutboardPylonPower, 42, 3170, 184),
-    Info::new(Switch::GunPower, 42, 3171, 185),
-    Info::new(Switch::GunCameraPower, 42, 3172, 187),
-    Info::new(Switch::FlightRecorderPower, 49, 3209, 193),
-    Info::new(Switch::RadioPower, 22, 3041, 173),
-    Info::new(Switch::ArkPower, 24, 3047, 174),
-    Info::new(Switch::RadarPower, 40, 3094, 205),
-    Info::new(Switch::SpoPower, 37, 3083, 202),
-    Info::new(Switch::Srzo81Power, 38, 3089, 346),
-    Info::new(Switch::SodPower, 39, 3090, 200),
-    Info::new(Switch::SprdCover, 48, 3109, 317),
-    Info::new(Switch::PipperEnable, 41, 3160, 249),
-    Info::new(Switch::FixedNetEnable, 41, 3161, 250),
-    Info::new(Switch::GunPyro1, 42, 3185, 232),
-    Info::new(Switch::GunPyro2, 42, 3186, 233),
-    Info::new(Switch::GunPyro3, 42, 3187, 234),
-    Info::new(Switch::WeaponModeAaAg, 42, 3183, 230),
-    Info::new(Switch::GuidedMissileMode, 42, 3184, 231),
-    Info::new(Switch::WeaponSelect, 42, 3188, 235),
-    Info::new(Switch::NppAdjust, 23, 3143, 258),
-];
+impl SwitchEnum for Switch {
+    const AIRCRAFT: &'static str = "MiG-21Bis";
+    const COUNT: usize = Switch::NumSwitches as usize;
+    const EMBEDDED: &'static str = include_str!("../../resources/switches/MiG-21Bis.json");
 
-#[derive(Clone, PartialEq, Debug)]
-enum StartupState {
-    ColdDark,
-    WaitCanopyClosed,
-    WaitEngineStartBegun,
-    WaitEngineStartComplete,
-    Done,
-}
+    fn index(self) -> usize {
+        self as usize
+    }
 
-fn get_switch_info(s: Switch) -> &'static Info {
-    &SWITCH_INFO_MAP[s as usize]
+    fn table() -> &'static SwitchTable<Self> {
+        switches()
+    }
 }
 
-fn toggle_switch(lua: &Lua, s: Switch) -> LuaResult<()> {
-    let info = get_switch_info(s);
-    dcs::perform_click(lua, info.device_id, info.command, 1.0)
-}
+static SWITCHES: OnceLock<SwitchTable<Switch>> = OnceLock::new();
 
-pub fn set_switch_state(lua: &Lua, s: Switch, state: f32) -> LuaResult<()> {
-    let info = get_switch_info(s);
-    dcs::perform_click(lua, info.device_id, info.command, state)
+pub fn switches() -> &'static SwitchTable<Switch> {
+    SWITCHES.get_or_init(SwitchTable::load)
 }
 
-pub fn get_switch_state(lua: &Lua, s: Switch) -> LuaResult<f32> {
-    let info = get_switch_info(s);
-    dcs::get_switch_state(lua, 0, info.argument)
-}
+/// Seconds the NPP adjust switch is held for the NPP to align.
+const NPP_ADJUST_TIME: f32 = 6.0;
+
+/// Systems switched on once the engine start sequence is under way.
+const POST_START_SWITCHES: &[Switch] = &[
+    Switch::Gyro1,
+    Switch::Gyro2,
+    Switch::SrzoPower,
+    Switch::SauPower,
+    Switch::SauPitchPower,
+    Switch::TrimmerPower,
+    Switch::NoseconePower,
+    Switch::EmergencyHydroPump,
+    Switch::KppMainEmergencyToggle,
+    Switch::NppPower,
+    Switch::RadAltPower,
+    Switch::AspPower,
+    Switch::MissileHeatPower,
+    Switch::MissileLaunchPower,
+    Switch::InboardPylonPower,
+    Switch::OutboardPylonPower,
+    Switch::GunPower,
+    Switch::FlightRecorderPower,
+    Switch::RadioPower,
+    Switch::ArkPower,
+    Switch::SpoPower,
+    Switch::PipperEnable,
+    Switch::FixedNetEnable,
+    Switch::WeaponModeAaAg,
+    Switch::Srzo81Power,
+    Switch::SodPower,
+    Switch::SprdCover,
+];
 
-pub fn is_switch_set(lua: &Lua, s: Switch) -> LuaResult<bool> {
-    Ok(get_switch_state(lua, s)? > 0.5)
+/// The step of the startup a hand started jet is picked up from.
+const STARTING_UP_SYSTEMS: &str = "Starting up systems";
+
+fn startup_procedure() -> Vec<Step<Switch>> {
+    vec![
+        Step::new(Action::TurnOn(vec![
+            Switch::CanopyClose,
+            Switch::FuelPump1,
+            Switch::FuelPump3,
+            Switch::FuelPumpDrain,
+            Switch::BatteryOn,
+            Switch::BatteryHeat,
+            Switch::AcGenerator,
+            Switch::DcGenerator,
+            Switch::SprdPower,
+            Switch::SprdDropPower,
+            Switch::Po750Inverter1,
+            Switch::Po750Inverter2,
+            Switch::ApuPower,
+            Switch::FireExtinguisherPower,
+            Switch::ThrottleStopLock,
+        ]))
+        .text("Setting up initial switches")
+        .weight(1.0),
+        Step::new(Action::WaitParam(
+            "BASE_SENSOR_CANOPY_POS",
+            Condition::Equals(0.0),
+        ))
+        .text("Waiting for canopy to close")
+        .weight(5.0),
+        Step::new(Action::TurnOn(vec![Switch::CanopyLock, Switch::CanopySeal]))
+            .text("Sealing canopy"),
+        Step::new(Action::Set(vec![(Switch::EngineStart, 1.0)])),
+        Step::new(Action::WaitArgument(
+            Switch::EngineStartLight,
+            Condition::AtLeast(0.9),
+        ))
+        .text("Waiting for engine start sequence")
+        .weight(1.0),
+        Step::new(Action::Set(vec![(Switch::EngineStart, 0.0)])).text(STARTING_UP_SYSTEMS),
+        Step::new(Action::TurnOn(POST_START_SWITCHES.to_vec())).weight(1.0),
+        Step::new(Action::Set(vec![
+            (Switch::WeaponSelect, 0.7),
+            (Switch::GuidedMissileMode, 1.0),
+            (Switch::RadarPower, 0.5),
+            (Switch::GunPyro1, 1.0),
+        ])),
+        Step::new(Action::Set(vec![(Switch::GunPyro1, 0.0)])),
+        Step::new(Action::WaitArgument(
+            Switch::EngineStartLight,
+            Condition::AtMost(0.1),
+        ))
+        .text("Waiting for engine start sequence to complete")
+        .weight(25.0),
+        Step::new(Action::Set(vec![(Switch::NppAdjust, 1.0)])).text("Waiting for NPP adjust"),
+        Step::new(Action::Delay(NPP_ADJUST_TIME)).weight(NPP_ADJUST_TIME),
+        Step::new(Action::Set(vec![(Switch::NppAdjust, 0.0)])),
+    ]
 }
 
-pub fn set_switch(lua: &Lua, s: Switch) -> LuaResult<()> {
-    if !is_switch_set(lua, s)? {
-        toggle_switch(lua, s)
-    } else {
-        Ok(())
+/// Engine RPM below which the engine is considered stopped.
+const ENGINE_STOP_THRESHOLD: f32 = 0.05;
+
+/// Finds how far the jet already is through the startup. Once the engine
+/// turns, the start sequence is left alone and only the systems are turned
+/// on.
+fn survey(cockpit: &Cockpit<Switch>) -> Result<CockpitState, crate::Error> {
+    let [battery, gyro, start_light] =
+        cockpit.arguments([Switch::BatteryOn, Switch::Gyro1, Switch::EngineStartLight])?;
+    if battery < 1.0 || cockpit.param("BASE_SENSOR_LEFT_ENGINE_RPM")? < ENGINE_STOP_THRESHOLD {
+        return Ok(CockpitState::ColdDark);
     }
+    let systems_on = gyro >= 1.0;
+    let start_finished = start_light <= 0.1;
+    if systems_on && start_finished {
+        return Ok(CockpitState::Running);
+    }
+    Ok(CockpitState::StartedUpTo(STARTING_UP_SYSTEMS))
 }
 
-pub fn _unset_switch(lua: &Lua, s: Switch) -> LuaResult<()> {
-    if is_switch_set(lua, s)? {
-        toggle_switch(lua, s)
-    } else {
-        Ok(())
-    }
+fn shutdown_procedure() -> Vec<Step<Switch>> {
+    vec![
+        Step::new(Action::Set(vec![
+            (Switch::WeaponSelect, 0.0),
+            (Switch::GuidedMissileMode, 0.0),
+            (Switch::RadarPower, 0.0),
+        ]))
+        .text("Shutting down systems")
+        .weight(1.0),
+        Step::new(Action::TurnOff(
+            POST_START_SWITCHES.iter().rev().copied().collect(),
+        )),
+        Step::new(Action::Lockon(LockonCommand::LeftEngineStop))
+            .text("Waiting for engine to spool down"),
+        Step::new(Action::WaitParam(
+            "BASE_SENSOR_LEFT_ENGINE_RPM",
+            Condition::AtMost(ENGINE_STOP_THRESHOLD),
+        ))
+        .weight(25.0),
+        Step::new(Action::TurnOff(vec![Switch::BatteryOn])).text("Turning off battery"),
+    ]
 }
 
-fn poll_argument(to_gamegui: &TaskSender<Lua>, switch: Switch) -> Result<f32, crate::Error> {
-    to_gamegui
-        .send(move |lua| get_switch_state(lua, switch))
-        .wait()
-        .map_err(|_| crate::Error::CommError)?
-        .map_err(|e| crate::Error::LuaError(e))
+#[derive(Debug, Clone)]
+pub struct Fsm {
+    procedures: Procedures<Switch>,
 }
 
-fn handle_polling_err(r: &Result<f32, crate::Error>) {
-    if let Err(err) = r {
-        match err {
-            crate::Error::LuaError(e) => {
-                log::warn!(
-                    "Lua error encountered when polling Engine Start Light {:?}",
-                    e
-                )
-            }
-            _ => {}
+pub struct Module;
+
+impl AircraftModule for Module {
+    fn display_name(&self) -> &'static str {
+        "MiG-21Bis"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            startup: true,
+            shutdown: true,
         }
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Fsm {
-    state: StartupState,
-    to_dcs_gamegui: TaskSender<Lua>,
-    to_dcs_export: TaskSender<Lua>,
-    gui: crate::gui::TxHandle,
+    fn make_fsm(
+        &self,
+        to_gamegui: TaskSender<Lua>,
+        to_export: TaskSender<Lua>,
+        gui: crate::gui::TxHandle,
+    ) -> Box<dyn dcs::AircraftFsm> {
+        Box::new(Fsm::new(to_gamegui, to_export, gui))
+    }
+
+    fn switches(&self) -> Option<&'static [SwitchDef]> {
+        Some(switches().defs())
+    }
 }
 
 impl dcs::AircraftFsm for Fsm {
-    fn run_fsm(&mut self, event: FsmMessage, _sim_time: f32) {
-        match self.state {
-            StartupState::ColdDark => self.cold_dark_handler(event),
-            StartupState::WaitCanopyClosed => self.wait_canopy_closed(event),
-            StartupState::WaitEngineStartBegun => self.wait_engine_start_begun(event),
-            StartupState::WaitEngineStartComplete => self.wait_engine_start_complete(event),
-            StartupState::Done => self.done(event),
-        }
+    fn run_fsm(&mut self, event: FsmMessage, clock: &dyn Clock) {
+        self.procedures.run_fsm(event, clock);
+    }
+
+    fn subscriptions(&self) -> Vec<dcs::Subscription> {
+        self.procedures.subscriptions()
+    }
+
+    fn on_indication(&mut self, change: &dcs::IndicationChange) {
+        self.procedures.on_indication(change);
+    }
+
+    fn wants_cockpit_params(&self) -> bool {
+        self.procedures.wants_cockpit_params()
+    }
+
+    fn on_cockpit_params(&mut self, params: &std::sync::Arc<dcs::CockpitParams>) {
+        self.procedures.on_cockpit_params(params);
+    }
+
+    fn wanted_arguments(&self) -> Vec<i32> {
+        self.procedures.wanted_arguments()
+    }
+
+    fn on_arguments(&mut self, values: &std::sync::Arc<dcs::ArgumentValues>) {
+        self.procedures.on_arguments(values);
     }
 }
 
@@ -226,219 +298,149 @@ impl Fsm {
         gui: crate::gui::TxHandle,
     ) -> Self {
         Self {
-            state: StartupState::ColdDark,
-            to_dcs_gamegui,
-            to_dcs_export,
-            gui,
-        }
-    }
-    fn cold_dark_handler(&mut self, event: crate::app::FsmMessage) {
-        match event {
-            crate::app::FsmMessage::StartupAircraft => {
-                // this should cause the progress bar to begin animating
-                self.gui.set_startup_progress(0.001);
-                self.gui.set_startup_text("Setting up initial switches");
-                self.throw_initial_switches();
-                self.gui.set_startup_progress(0.05);
-                self.gui.set_startup_text("Waiting for canopy to close");
-
-                self.state = StartupState::WaitCanopyClosed;
-            }
-            _ => {}
+            procedures: Procedures::new(
+                startup_procedure(),
+                shutdown_procedure(),
+                to_dcs_gamegui,
+                to_dcs_export,
+                gui,
+            )
+            .survey(survey),
         }
     }
-    fn wait_canopy_closed(&mut self, _event: crate::app::FsmMessage) {
-        let r = self
-            .to_dcs_export
-            .send(|lua| get_cockpit_param(lua, "BASE_SENSOR_CANOPY_POS"))
-            .wait();
-        if let Err(_) = r {
-            return;
-        }
+}
 
-        let lua_result = r.unwrap();
-        if let Err(e) = lua_result {
-            log::warn!("Polling canopy failed {:?}", e);
-            return;
-        }
+#[cfg(test)]
+mod test {
+    use super::{switches, Fsm, Switch};
+    use crate::app::FsmMessage;
+    use crate::dcs::sequence::{Action, SequenceState};
+    use crate::dcs::testing::Harness;
+    use crate::dcs::SwitchInfo;
 
-        let cockpit_state = lua_result.unwrap();
-        if cockpit_state == 0.0 as f32 {
-            self.gui.set_startup_progress(0.1);
-            self.gui.set_startup_text("Sealing canopy");
-            let _ = self
-                .to_dcs_gamegui
-                .send(|lua| {
-                    let _ = set_switch(lua, Switch::CanopyLock);
-                    let _ = set_switch(lua, Switch::CanopySeal);
-                    let _ = set_switch_state(lua, Switch::EngineStart, 1.0);
-                })
-                .wait();
-            self.gui.set_startup_progress(0.18);
-            self.gui
-                .set_startup_text("Waiting for engine start sequence");
-            self.state = StartupState::WaitEngineStartBegun;
-        }
+    fn get_switch_info(s: Switch) -> SwitchInfo<Switch> {
+        let info = switches().info(s);
+        SwitchInfo::new(s, info.device_id(), info.command(), info.argument())
     }
 
-    fn wait_engine_start_begun(&mut self, _event: crate::app::FsmMessage) {
-        let result = poll_argument(&self.to_dcs_gamegui, Switch::EngineStartLight);
-        if result.is_err() {
-            handle_polling_err(&result);
-            return;
-        }
-        let light = result.unwrap();
-        if light < 0.9 {
-            return;
-        }
-        self.gui.set_startup_text("Starting up systems");
-        self.to_dcs_gamegui
-            .send(|lua| set_switch_state(lua, Switch::EngineStart, 0.0));
-        self.gui.set_startup_progress(0.2);
-
-        self.throw_post_engine_start_switches();
-        self.gui.set_startup_progress(0.22);
-        self.gui
-            .set_startup_text("Waiting for engine start sequence to complete");
-        self.state = StartupState::WaitEngineStartComplete;
+    fn waiting_on_start_light(fsm: &Fsm) -> bool {
+        fsm.procedures.startup().current().is_some_and(|step| {
+            matches!(
+                step.action,
+                Action::WaitArgument(Switch::EngineStartLight, _)
+            )
+        })
     }
 
-    fn wait_engine_start_complete(&mut self, _event: crate::app::FsmMessage) {
-        let result = poll_argument(&self.to_dcs_gamegui, Switch::EngineStartLight);
-        if result.is_err() {
-            handle_polling_err(&result);
-            return;
-        }
-        let light = result.unwrap();
-        if light > 0.1 {
-            return;
-        }
-        self.gui.set_startup_text("Waiting for NPP adjust");
-        self.gui.set_startup_progress(0.8);
-        // this should be moved into a separate state and made nonblocking, but for now
-        // just block the thread for 5 seconds while it aligns.
-        self.run_npp_adjust();
-
-        self.gui.set_startup_progress(1.0);
-        self.gui.set_startup_text("DONE");
-        self.state = StartupState::Done;
-    }
+    #[test]
+    fn test_startup_until_engine_start() {
+        let harness = Harness::new("MiG-21Bis");
+        harness
+            .dcs
+            .state()
+            .set_cockpit_param("BASE_SENSOR_CANOPY_POS", 1.0);
+        let canopy_close = get_switch_info(Switch::CanopyClose);
+        let (device_id, command) = (canopy_close.device_id, canopy_close.command);
+        harness.dcs.on_click(move |state, click| {
+            if (click.device_id, click.command) == (device_id, command) {
+                state.set_cockpit_param("BASE_SENSOR_CANOPY_POS", 0.0);
+            }
+        });
 
-    fn done(&mut self, _event: crate::app::FsmMessage) {}
-
-    fn throw_initial_switches(&self) {
-        let _ = self
-            .to_dcs_gamegui
-            .send(|lua| {
-                let switches_to_start = [
-                    Switch::CanopyClose,
-                    Switch::FuelPump1,
-                    Switch::FuelPump3,
-                    Switch::FuelPumpDrain,
-                    Switch::BatteryOn,
-                    Switch::BatteryHeat,
-                    Switch::AcGenerator,
-                    Switch::DcGenerator,
-                    Switch::SprdPower,
-                    Switch::SprdDropPower,
-                    Switch::Po750Inverter1,
-                    Switch::Po750Inverter2,
-                    Switch::ApuPower,
-                    Switch::FireExtinguisherPower,
-                    Switch::ThrottleStopLock,
-                ];
-
-                for s in switches_to_start {
-                    let _ = set_switch(lua, s);
-                }
-            })
-            .wait();
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            10.0,
+            waiting_on_start_light,
+        );
+        assert!(waiting_on_start_light(&fsm));
+
+        let state = harness.dcs.state();
+        let battery = get_switch_info(Switch::BatteryOn);
+        assert_eq!(state.clicks_on(battery.device_id, battery.command).len(), 1);
+        let engine_start = get_switch_info(Switch::EngineStart);
+        let start_clicks = state.clicks_on(engine_start.device_id, engine_start.command);
+        assert_eq!(start_clicks.len(), 1);
+        assert_eq!(start_clicks[0].value, 1.0);
     }
 
-    fn throw_post_engine_start_switches(&self) {
-        let _ = self
-            .to_dcs_gamegui
-            .send(|lua| {
-                let switches = [
-                    Switch::Gyro1,
-                    Switch::Gyro2,
-                    Switch::SrzoPower,
-                    Switch::SauPower,
-                    Switch::SauPitchPower,
-                    Switch::TrimmerPower,
-                    Switch::NoseconePower,
-                    Switch::EmergencyHydroPump,
-                    Switch::KppMainEmergencyToggle,
-                    Switch::NppPower,
-                    Switch::RadAltPower,
-                    Switch::AspPower,
-                    Switch::MissileHeatPower,
-                    Switch::MissileLaunchPower,
-                    Switch::InboardPylonPower,
-                    Switch::OutboardPylonPower,
-                    Switch::GunPower,
-                    Switch::FlightRecorderPower,
-                    Switch::RadioPower,
-                    Switch::ArkPower,
-                    Switch::SpoPower,
-                    Switch::PipperEnable,
-                    Switch::FixedNetEnable,
-                    Switch::WeaponModeAaAg,
-                    Switch::Srzo81Power,
-                    Switch::SodPower,
-                    Switch::SprdCover,
-                ];
-                for s in switches {
-                    let _ = set_switch(lua, s);
-                }
-                let _ = set_switch_state(lua, Switch::WeaponSelect, 0.7);
-                let _ = set_switch_state(lua, Switch::GuidedMissileMode, 1.0);
-                let _ = set_switch_state(lua, Switch::RadarPower, 0.5);
-                let _ = set_switch_state(lua, Switch::GunPyro1, 1.0);
-            })
-            .wait();
-        let _ = self
-            .to_dcs_gamegui
-            .send(|lua| set_switch_state(lua, Switch::GunPyro1, 0.0))
-            .wait();
+    #[test]
+    fn test_full_autostart() {
+        use super::NPP_ADJUST_TIME;
+        use dcs_mock::models::mig21bis::{Model, START_TIME};
+        let harness = Harness::with_model("MiG-21Bis", Box::new(Model::new()));
+
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            120.0,
+            |fsm: &Fsm| *fsm.procedures.startup().state() == SequenceState::Done,
+        );
+        assert_eq!(*fsm.procedures.startup().state(), SequenceState::Done);
+
+        // canopy travel plus the start sequence itself and the NPP adjustment
+        let elapsed = harness.dcs.state().model_time;
+        let minimum = START_TIME + NPP_ADJUST_TIME + 5.0;
+        assert!(elapsed > minimum, "finished after {elapsed}s");
+        assert!(elapsed < minimum + 5.0, "finished after {elapsed}s");
     }
 
-    fn run_npp_adjust(&self) {
-        let _ = self
-            .to_dcs_gamegui
-            .send(|lua| set_switch_state(lua, Switch::NppAdjust, 1.0))
-            .wait();
-
-        std::thread::sleep(std::time::Duration::from_secs(6));
-        let _ = self
-            .to_dcs_gamegui
-            .send(|lua| set_switch_state(lua, Switch::NppAdjust, 0.0))
-            .wait();
+    #[test]
+    fn test_shutdown_after_autostart() {
+        use dcs_mock::models::mig21bis::Model;
+        let harness = Harness::with_model("MiG-21Bis", Box::new(Model::new()));
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            120.0,
+            |fsm: &Fsm| *fsm.procedures.startup().state() == SequenceState::Done,
+        );
+
+        let fsm = harness.run(
+            move |_, _, _| fsm,
+            FsmMessage::ShutdownAircraft,
+            0.1,
+            200.0,
+            |fsm: &Fsm| *fsm.procedures.shutdown().state() == SequenceState::Done,
+        );
+        assert_eq!(*fsm.procedures.shutdown().state(), SequenceState::Done);
+
+        let state = harness.dcs.state();
+        assert!(state.lockon_commands.contains(&313));
+        let rpm: f32 = state.cockpit_params["BASE_SENSOR_LEFT_ENGINE_RPM"]
+            .parse()
+            .unwrap();
+        assert!(rpm <= 0.05, "engine still at {rpm}");
+        let battery = get_switch_info(Switch::BatteryOn);
+        assert_eq!(state.argument(battery.argument), 0.0);
+        let gyro = get_switch_info(Switch::Gyro1);
+        assert_eq!(state.argument(gyro.argument), 0.0);
     }
-}
 
-pub fn make_debug_widget(ui: &mut egui::Ui, strings: &mut Vec<String>, tx: &TaskSender<Lua>) {
-    egui::Grid::new("debug_grid").show(ui, |ui| {
-        for (ii, &ref switch_info) in SWITCH_INFO_MAP.iter().enumerate() {
-            let s: &'static str = switch_info.switch.into();
-            ui.label(s);
-            let val = &mut (strings[ii]);
-            if ui.button("Set").clicked() {
-                let result = val.parse::<f32>();
-                if let Ok(state) = result {
-                    let _ = tx.send(move |lua| set_switch_state(lua, switch_info.switch, state));
-                }
-            }
-            if ui.button("Get").clicked() {
-                let result = tx
-                    .send(|lua| get_switch_state(lua, switch_info.switch))
-                    .wait();
-                if let Ok(state) = result {
-                    val.replace_range(.., state.unwrap_or_default().to_string().as_str());
-                }
-            }
-            ui.add(egui::TextEdit::singleline(val));
-            ui.end_row();
-        }
-    });
+    #[test]
+    fn test_hot_start_is_left_alone() {
+        use dcs_mock::models::mig21bis::Model;
+        let harness = Harness::with_model("MiG-21Bis", Box::new(Model::new()));
+        harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            120.0,
+            |fsm: &Fsm| *fsm.procedures.startup().state() == SequenceState::Done,
+        );
+        let clicks = harness.dcs.state().clicks.len();
+
+        let fsm = harness.run(
+            Fsm::new,
+            FsmMessage::StartupAircraft,
+            0.1,
+            130.0,
+            |_: &Fsm| true,
+        );
+        assert_eq!(*fsm.procedures.startup().state(), SequenceState::Idle);
+        assert_eq!(harness.dcs.state().clicks.len(), clicks);
+    }
 }
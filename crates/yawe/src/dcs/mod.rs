@@ -1,13 +1,33 @@
+pub mod aircraft;
+pub mod arguments;
+pub mod cockpit_params;
+pub mod events;
 pub mod f16c50;
+pub mod indication;
 pub mod mig21bis;
+pub mod recording;
+pub mod safety;
+pub mod script;
+pub mod sequence;
+pub mod subscriptions;
+pub mod switches;
+#[cfg(test)]
+mod testing;
+
+pub use arguments::ArgumentValues;
+pub use cockpit_params::{CockpitParams, ParamValue};
+pub use indication::Indication;
+pub use subscriptions::{IndicationChange, Subscription};
 
 use crate::app::FsmMessage;
+use crate::clock::{Clock, Timer};
 use crate::Error;
 use mlua::prelude::{LuaFunction, LuaResult, LuaTable};
 use mlua::Lua;
 use offload::TaskSender;
-use slab_tree::{NodeId, NodeRef, Tree};
+use recording::{tap, Call};
 use std::str::FromStr;
+use std::sync::Arc;
 use trace::trace;
 trace::init_depth_var!();
 
@@ -40,10 +60,40 @@ impl<SwitchT> SwitchInfo<SwitchT> {
 }
 
 pub trait AircraftFsm {
-    fn run_fsm(&mut self, msg: FsmMessage, sim_time: f32);
+    fn run_fsm(&mut self, msg: FsmMessage, clock: &dyn Clock);
+
+    /// The indication elements the FSM wants to hear about, asked after
+    /// every message it handles.
+    fn subscriptions(&self) -> Vec<Subscription> {
+        vec![]
+    }
+
+    /// One of [`AircraftFsm::subscriptions`] changed. Delivered before the
+    /// frame's [`AircraftFsm::run_fsm`].
+    fn on_indication(&mut self, _change: &IndicationChange) {}
+
+    /// Whether the FSM wants [`AircraftFsm::on_cockpit_params`], asked
+    /// after every message it handles.
+    fn wants_cockpit_params(&self) -> bool {
+        false
+    }
+
+    /// The cockpit params changed. Delivered before the frame's
+    /// [`AircraftFsm::run_fsm`].
+    fn on_cockpit_params(&mut self, _params: &Arc<CockpitParams>) {}
+
+    /// The cockpit arguments the FSM wants read every frame, asked after
+    /// every message it handles.
+    fn wanted_arguments(&self) -> Vec<i32> {
+        vec![]
+    }
+
+    /// The [`AircraftFsm::wanted_arguments`] as read in the latest frame.
+    /// Delivered before the FSM next runs.
+    fn on_arguments(&mut self, _values: &Arc<ArgumentValues>) {}
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 #[allow(non_camel_case_types)]
 pub enum AircraftId {
     A_10C,
@@ -69,32 +119,7 @@ pub enum AircraftId {
     Unknown(String),
 }
 
-#[allow(non_camel_case_types, dead_code)]
-pub enum AircraftState {
-    A_10C,
-    A_10C_2,
-    AH_64D_BLK_II,
-    AJS37,
-    AV8BNA,
-    F_14B,
-    F_15ESE,
-    F_15ESE_WSO,
-    F_16C_50(f16c50::Gui),
-    FA_18C_hornet,
-    M_2000C,
-    Mi_24P,
-    Mi_8MT,
-    Mi_8MT_Copilot,
-    Mi_8MT_FO,
-    MiG_21Bis,
-    SA342L,
-    Su_25,
-    Su_25T,
-    UH_1H,
-    Unknown(String),
-}
-
-fn str_to_ship_enum(name: &str) -> AircraftId {
+pub fn str_to_ship_enum(name: &str) -> AircraftId {
     match name {
         "A-10C" => AircraftId::A_10C,
         "A-10C_2" => AircraftId::A_10C_2,
@@ -122,7 +147,7 @@ fn str_to_ship_enum(name: &str) -> AircraftId {
 
 pub struct EmptyFsm {}
 impl AircraftFsm for EmptyFsm {
-    fn run_fsm(&mut self, _: FsmMessage, _: f32) {}
+    fn run_fsm(&mut self, _: FsmMessage, _: &dyn Clock) {}
 }
 
 impl EmptyFsm {
@@ -131,24 +156,22 @@ impl EmptyFsm {
     }
 }
 
-pub fn get_aircraft(
-    aircraft: AircraftId,
-    to_gamegui: TaskSender<Lua>,
-    to_export: TaskSender<Lua>,
-    gui: crate::gui::TxHandle,
-) -> Box<dyn AircraftFsm> {
-    match aircraft {
-        AircraftId::MiG_21Bis => Box::new(mig21bis::Fsm::new(to_gamegui, to_export, gui)),
-        AircraftId::F_16C_50 => Box::new(f16c50::Fsm::new(to_gamegui, to_export, gui)),
-        _ => Box::new(EmptyFsm::new(to_gamegui, to_export, gui)),
+/// Loads and validates every aircraft's switch table, preferring the files in
+/// the `switches` directory next to the DLL.
+pub fn load_switch_tables(dll_path: &str) {
+    switches::set_table_dir(std::path::Path::new(dll_path).join("switches"));
+    for module in aircraft::registry().values() {
+        module.switches();
     }
 }
 
 pub fn get_ownship_type(lua: &Lua) -> LuaResult<AircraftId> {
-    let export: LuaTable = lua.globals().get("Export")?;
-    let get_self_data: LuaFunction = export.get("LoGetSelfData")?;
-    let self_data: LuaTable = get_self_data.call(())?;
-    let s: String = self_data.get("Name")?;
+    let s: String = tap(Call::LoGetSelfData, || {
+        let export: LuaTable = lua.globals().get("Export")?;
+        let get_self_data: LuaFunction = export.get("LoGetSelfData")?;
+        let self_data: LuaTable = get_self_data.call(())?;
+        self_data.get("Name")
+    })?;
     Ok(str_to_ship_enum(s.as_str()))
 }
 
@@ -162,80 +185,87 @@ fn get_cockpit_device(lua: &Lua, device_id: i32) -> LuaResult<LuaTable> {
 }
 
 pub fn perform_click(lua: &Lua, device_id: i32, command: i32, value: f32) -> LuaResult<()> {
-    let device: LuaTable = get_cockpit_device(lua, device_id)?;
-    let perform_click: LuaFunction = device.get("performClickableAction")?;
-    perform_click.call((device, command, value))
+    let call = Call::PerformClick {
+        device_id,
+        command,
+        value,
+    };
+    tap(call, || {
+        let device: LuaTable = get_cockpit_device(lua, device_id)?;
+        let perform_click: LuaFunction = device.get("performClickableAction")?;
+        perform_click.call((device, command, value))
+    })
 }
 
 pub fn get_switch_state(lua: &Lua, device_id: i32, command: i32) -> LuaResult<f32> {
-    let device: LuaTable = get_cockpit_device(lua, device_id)?;
-    let get_value: LuaResult<LuaFunction> = device.get("get_argument_value");
-    if let Err(e) = get_value {
-        log::warn!(
-            "Could not find function get_argument_value, result is {:?}",
-            e
-        );
-        return Err(e);
-    }
-    get_value.unwrap().call((device, command))
+    let call = Call::GetArgumentValue {
+        device_id,
+        argument: command,
+    };
+    tap(call, || {
+        let device: LuaTable = get_cockpit_device(lua, device_id)?;
+        let get_value: LuaResult<LuaFunction> = device.get("get_argument_value");
+        if let Err(e) = get_value {
+            log::warn!(
+                "Could not find function get_argument_value, result is {:?}",
+                e
+            );
+            return Err(e);
+        }
+        get_value.unwrap().call((device, command))
+    })
 }
 
 pub fn _set_command(lua: &Lua, command: i32, value: f32) -> LuaResult<f32> {
-    let export: LuaTable = lua.globals().get("Export")?;
-    let set_command: LuaResult<LuaFunction> = export.get("LoGetCommand");
-    if let Err(e) = set_command {
-        log::warn!("Could not find function LoGetCommand, result is {:?}", e);
-        return Err(e);
-    }
-    set_command.unwrap().call((command, value))
+    tap(Call::LoGetCommand { command, value }, || {
+        let export: LuaTable = lua.globals().get("Export")?;
+        let set_command: LuaResult<LuaFunction> = export.get("LoGetCommand");
+        if let Err(e) = set_command {
+            log::warn!("Could not find function LoGetCommand, result is {:?}", e);
+            return Err(e);
+        }
+        set_command.unwrap().call((command, value))
+    })
 }
 
 pub fn list_cockpit_params(lua: &Lua) -> LuaResult<String> {
-    let list_cockpit_params: LuaResult<LuaFunction> = lua.globals().get("list_cockpit_params");
-    if let Err(e) = list_cockpit_params {
-        log::warn!(
-            "Could not find global function list_cockpit_params, result is {:?}",
-            e
-        );
-        return Err(e);
-    }
-    list_cockpit_params.unwrap().call(())
+    tap(Call::ListCockpitParams, || {
+        let list_cockpit_params: LuaResult<LuaFunction> = lua.globals().get("list_cockpit_params");
+        if let Err(e) = list_cockpit_params {
+            log::warn!(
+                "Could not find global function list_cockpit_params, result is {:?}",
+                e
+            );
+            return Err(e);
+        }
+        list_cockpit_params.unwrap().call(())
+    })
 }
 
+/// A numeric parameter from `list_cockpit_params()`. Lists and parses every
+/// parameter, so when reading several use [`CockpitParams::read`].
 pub fn get_cockpit_param(lua: &Lua, param_name: &str) -> std::result::Result<f32, Error> {
-    let params = list_cockpit_params(lua).map_err(|e| Error::LuaError(e))?;
-    let pattern = [param_name, ":"].join("");
-
-    for line in params.split("\n") {
-        if line.trim().starts_with(&pattern) {
-            let mut s = line.split(":");
-            s.next();
-            let val = s.next();
-            if let None = val {
-                return Err(Error::ParseError(line.into()));
-            }
-            return Ok(val
-                .unwrap()
-                .parse()
-                .map_err(|_| Error::ParseError(line.into()))?);
-        }
-    }
-    return Err(Error::IndexError);
+    CockpitParams::read(lua)?.number(param_name)
 }
 
 pub fn is_paused(lua: &Lua) -> LuaResult<bool> {
-    let dcs: LuaTable = lua.globals().get("DCS")?;
-    let get_pause: LuaFunction = dcs.get("getPause")?;
-    get_pause.call(())
+    tap(Call::GetPause, || {
+        let dcs: LuaTable = lua.globals().get("DCS")?;
+        let get_pause: LuaFunction = dcs.get("getPause")?;
+        get_pause.call(())
+    })
 }
 
 pub fn get_sim_time(lua: &Lua) -> LuaResult<f32> {
-    let dcs: LuaTable = lua.globals().get("DCS")?;
-    let get_model_time: LuaFunction = dcs.get("getModelTime")?;
-    get_model_time.call(())
+    tap(Call::GetModelTime, || {
+        let dcs: LuaTable = lua.globals().get("DCS")?;
+        let get_model_time: LuaFunction = dcs.get("getModelTime")?;
+        get_model_time.call(())
+    })
 }
 
 #[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LockonCommand {
     LeftEngineStart = 311,
     RightEngineStart = 312,
@@ -244,252 +274,88 @@ pub enum LockonCommand {
 }
 
 pub fn set_lockon_command(lua: &Lua, command: LockonCommand) -> LuaResult<()> {
-    let export: LuaTable = lua.globals().get("Export")?;
-    let send_command: LuaFunction = export.get("LoSetCommand")?;
-    send_command.call(command as i32)
+    let command = command as i32;
+    tap(Call::LoSetCommand { command }, || {
+        let export: LuaTable = lua.globals().get("Export")?;
+        let send_command: LuaFunction = export.get("LoSetCommand")?;
+        send_command.call(command)
+    })
 }
 
 #[trace(logging)]
 pub fn list_indication(lua: &Lua, device: i32) -> LuaResult<String> {
-    let list_indication: LuaFunction = lua.globals().get("list_indication")?;
-    list_indication.call(device)
-}
-#[derive(Debug, Clone, PartialEq)]
-pub struct IndicationNode {
-    field: String,
-    value: String,
-}
-
-#[derive(Debug, Clone, PartialEq)]
-struct IndicationSegment {
-    pub indication: IndicationNode,
-    depth_change: i32,
-}
-
-fn parse_indication_segment(segment: &str) -> Option<IndicationSegment> {
-    let lines: Vec<&str> = segment.trim_start().split("\n").collect();
-    if lines.len() < 2 {
-        log::warn!(
-            "Mal-formed parse in list indication {:?}, {:?}\n",
-            segment,
-            lines
-        );
-        return None;
-    }
-
-    let mut depth_change: i32 = 0;
-
-    let field = lines[0];
-    let value = lines[1];
-
-    for line in &lines[2..] {
-        if line.starts_with("children are {") {
-            depth_change += 1;
-            continue;
-        }
-        depth_change -= line.matches("}").count() as i32;
-    }
-
-    Some(IndicationSegment {
-        indication: IndicationNode {
-            field: field.to_string(),
-            value: value.to_string(),
-        },
-        depth_change,
+    tap(Call::ListIndication { device }, || {
+        let list_indication: LuaFunction = lua.globals().get("list_indication")?;
+        list_indication.call(device)
     })
 }
 
-fn parse_indication(s: &str) -> Tree<IndicationNode> {
-    let mut tree = Tree::<IndicationNode>::new();
-    let segments: Vec<&str> = s
-        .split("-----------------------------------------")
-        .collect();
-
-    log::debug!("Parsing indication, {} segments", segments.len());
-
-    let mut curr = tree.set_root(IndicationNode {
-        field: "root".to_string(),
-        value: String::new(),
-    });
-
-    // since the string starts with a separator, `split()` will return an empty
-    // string as the first element; we just discard it.
-    for segment in &segments[1..] {
-        let Some(parsed) = parse_indication_segment(segment) else {
-            continue;
-        };
-        let depth_change = parsed.depth_change;
-
-        let mut node = tree.get_mut(curr).unwrap();
-        let new_id = node.append(parsed.indication);
-
-        if depth_change > 0 {
-            curr = new_id.node_id();
-            continue;
-        }
-
-        // depth_change is negative
-        for _ in 0..-depth_change {
-            curr = tree.get(curr).unwrap().parent().unwrap().node_id();
-        }
-    }
-    tree
-}
-
-pub fn _traverse_tree<F>(t: &Tree<IndicationNode>, visitor: F) -> ()
-where
-    F: FnOnce(&NodeRef<IndicationNode>, i32) -> () + Copy,
-{
-    _traverse_node(t.root().unwrap(), 0, visitor);
-}
-
-fn _traverse_node<F>(n: NodeRef<IndicationNode>, depth: i32, visitor: F) -> ()
-where
-    F: FnOnce(&NodeRef<IndicationNode>, i32) -> () + Copy,
-{
-    visitor(&n, depth);
-    for child in n.children() {
-        _traverse_node(child, depth + 1, visitor);
-    }
-}
-
-pub fn lookup_tree<'a>(
-    tree: &'a Tree<IndicationNode>,
-    path: &Vec<&str>,
-) -> Option<&'a IndicationNode> {
-    let get_child = |node_id: NodeId, key: &str| -> Option<NodeId> {
-        let node = tree.get(node_id).unwrap();
-        for child in node.children() {
-            if child.data().field == key {
-                return Some(child.node_id());
-            }
-        }
-        None
-    };
-
-    // Since there could be multiple top level indications, and a tree can only have
-    // a single root, we just make an empty one called "root" to support more than
-    // one top level element as children of the root.
-    if tree.root().unwrap().data().field != "root" {
-        return None;
-    }
-
-    let mut cur = tree.root_id().unwrap();
-
-    for item in path {
-        let Some(id) = get_child(cur, item) else {
-            return None;
-        };
-        cur = id;
-    }
-    Some(tree.get(cur).unwrap().data())
-}
-
-pub fn get_avionics_indication(
+/// Fetches `list_indication(device)` and hands the parsed indication to `f`.
+/// Returns `None` if the display is blank or can't be parsed.
+pub fn with_avionics_indication<R>(
     to_export: &TaskSender<Lua>,
     device: i32,
-) -> Option<Tree<IndicationNode>> {
+    f: impl FnOnce(&Indication) -> R,
+) -> Option<R> {
     let Ok(lua_result) = to_export
         .send(move |lua| list_indication(lua, device))
         .wait()
     else {
-        log::warn!("get_avionics_indication: list_indication failed (thread communication)");
+        log::warn!("with_avionics_indication: list_indication failed (thread communication)");
         return None;
     };
     let Ok(s) = lua_result else {
-        log::warn!("get_avionics_indication: list_indication failed (Lua)");
+        log::warn!("with_avionics_indication: list_indication failed (Lua)");
         return None;
     };
 
     if s.trim().is_empty() {
-        log::warn!("get_avionics_indication: list_indication empty");
+        log::warn!("with_avionics_indication: list_indication empty");
         return None;
     }
-    let tree = parse_indication(&s);
-    log::trace!("get_avionics-indication:");
-    _traverse_tree(&tree, |node, depth| {
-        let mut s = String::default();
-        for _ in 0..depth {
-            s += &("    ");
+    let tree = match indication::parse(&s) {
+        Ok(tree) => tree,
+        Err(e) => {
+            log::warn!("with_avionics_indication: invalid indication of device {device} at {e}");
+            return None;
         }
-
-        let data = node.data();
-        log::trace!("{s}{data:?}");
-    });
-    Some(parse_indication(&s))
+    };
+    log::trace!("with_avionics_indication:\n{tree}");
+    Some(f(&tree))
 }
 
-pub fn retry_default<T, F>(f: F) -> Option<T>
+/// Retries `f` for up to two seconds of simulation time.
+pub fn retry_default<T, F>(clock: &dyn Clock, f: F) -> Option<T>
 where
-    F: FnOnce() -> Option<T> + Copy,
+    F: FnMut() -> Option<T>,
 {
-    retry_until(f, std::time::Duration::from_secs(2))
+    retry_until(clock, f, 2.0)
 }
 
-pub fn retry_until<T, F>(f: F, timeout: std::time::Duration) -> Option<T>
+/// Retries `f` until it returns something or `timeout` seconds of simulation
+/// time pass. Gives up as soon as the clock can't be read, as it can't once
+/// DCS is gone.
+pub fn retry_until<T, F>(clock: &dyn Clock, mut f: F, timeout: f32) -> Option<T>
 where
-    F: FnOnce() -> Option<T> + Copy,
+    F: FnMut() -> Option<T>,
 {
-    let start = std::time::Instant::now();
+    let timer = Timer::start(clock, timeout);
     loop {
         let x = f();
         if x.is_some() {
             return x;
         }
-        let elapsed = std::time::Instant::now() - start;
-        if elapsed > timeout {
+        if timer.is_expired(clock) {
             return None;
         }
     }
 }
 
+/// The value of the first element matching `query`, see
+/// [`Indication::query`].
 #[trace(logging)]
-pub fn get_avionics_value(
-    to_export: &TaskSender<Lua>,
-    device: i32,
-    path: &Vec<&str>,
-) -> Option<String> {
-    let tree = get_avionics_indication(to_export, device)?;
-    match lookup_tree(&tree, path) {
-        None => None,
-        Some(node) => Some(node.value.clone()),
-    }
-}
-
-#[cfg(test)]
-mod test {
-    use crate::dcs::_traverse_tree;
-    use crate::dcs::lookup_tree;
-    use crate::dcs::parse_indication;
-
-    #[test]
-    fn test_parse_indication() {
-        use std::path::PathBuf;
-        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        d.push("resources/f16_hud_align.txt");
-        let Ok(s) = std::fs::read_to_string(d) else {
-            panic!("Can't find test");
-        };
-        let tree = parse_indication(&s);
-        _traverse_tree(&tree, |n, depth| {
-            let mut s = String::default();
-            for _ in 0..depth {
-                s += &("    ");
-            }
-
-            let data = n.data();
-            print!("{s}{data:?}\n");
-        });
-        let s = lookup_tree(
-            &tree,
-            &vec![
-                "HUD_BlankRoot_PH_com",
-                "HUD_Indication_bias",
-                "HUD_Mach_num_origin",
-                "HUD_Window4_MachNumber_dot",
-            ],
-        );
-        assert!(s.is_some());
-        assert!(s.unwrap().value == ".");
-    }
+pub fn get_avionics_value(to_export: &TaskSender<Lua>, device: i32, query: &str) -> Option<String> {
+    with_avionics_indication(to_export, device, |tree| {
+        tree.first(query).map(|node| node.value.to_string())
+    })?
 }
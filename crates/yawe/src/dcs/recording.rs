@@ -0,0 +1,436 @@
+//! Recording and replay of the Lua traffic between yawe and DCS.
+//!
+//! Every function in [`crate::dcs`] that talks to DCS goes through [`tap()`].
+//! FSM events from the GUI are recorded too, so a replay sees the same
+//! button presses on the same frames.
+//! With a [`Recorder`] installed on the thread running Lua, each call and its
+//! result is appended to a JSON lines file along with the frame it happened
+//! in. With a [`Player`] installed instead, calls are answered from such a
+//! file and never reach Lua, so a session captured in DCS can be run again
+//! offline. Replay is driven from tests, see `app::replay()`.
+//!
+//! Both are installed per thread rather than per Lua state: DCS runs the
+//! gamegui and export environments on the same thread, and nothing of ours
+//! can be left inside a Lua state after the DLL is unloaded.
+
+use crate::app::FsmMessage;
+use mlua::prelude::LuaResult;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+#[cfg(test)]
+use std::collections::VecDeque;
+use std::fs::File;
+#[cfg(test)]
+use std::io::{BufRead, BufReader};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A single call from yawe into DCS.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Call {
+    PerformClick {
+        device_id: i32,
+        command: i32,
+        value: f32,
+    },
+    GetArgumentValue {
+        device_id: i32,
+        argument: i32,
+    },
+    ListIndication {
+        device: i32,
+    },
+    ListCockpitParams,
+    LoGetSelfData,
+    LoGetCommand {
+        command: i32,
+        value: f32,
+    },
+    LoSetCommand {
+        command: i32,
+    },
+    GetModelTime,
+    GetPause,
+    GetMyPlayerId,
+    LoGetVectorVelocity,
+    LoGetAltitudeAboveGroundLevel,
+    LoGetMechInfo,
+}
+
+/// Where a call was made from. Replies are matched to calls in order within
+/// each channel, since the interleaving between them depends on thread timing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Channel {
+    /// Directly from a DCS hook, e.g. `on_frame`.
+    Frame,
+    /// From a job sent through the gamegui `TaskSender`.
+    Gamegui,
+    /// From a job sent through the export `TaskSender`.
+    Export,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Entry {
+    Frame {
+        frame: u64,
+    },
+    Event {
+        frame: u64,
+        event: FsmMessage,
+    },
+    Call {
+        frame: u64,
+        channel: Channel,
+        call: Call,
+        result: Result<serde_json::Value, String>,
+    },
+}
+
+#[derive(Clone)]
+enum Mode {
+    Record(Arc<Recorder>),
+    #[cfg(test)]
+    Replay(Arc<Player>),
+}
+
+struct Tap {
+    mode: Mode,
+    channel: Channel,
+}
+
+thread_local! {
+    static TAP: RefCell<Option<Tap>> = const { RefCell::new(None) };
+}
+
+/// Writes all calls made on the thread it is installed on into one file.
+pub struct Recorder {
+    path: PathBuf,
+    out: Mutex<BufWriter<File>>,
+    frame: AtomicU64,
+}
+
+impl Recorder {
+    /// Creates a new recording under `<write_dir>/Logs/Yawe`.
+    pub fn create(write_dir: &str) -> std::io::Result<Arc<Self>> {
+        let dir = Path::new(write_dir).join("Logs").join("Yawe");
+        std::fs::create_dir_all(&dir)?;
+        let name = chrono::Local::now()
+            .format("recording-%Y%m%d-%H%M%S.jsonl")
+            .to_string();
+        Self::create_at(dir.join(name))
+    }
+
+    pub fn create_at(path: PathBuf) -> std::io::Result<Arc<Self>> {
+        let out = BufWriter::new(File::create(&path)?);
+        log::info!("Recording Lua traffic to {}", path.display());
+        Ok(Arc::new(Self {
+            path,
+            out: Mutex::new(out),
+            frame: AtomicU64::new(0),
+        }))
+    }
+
+    /// Starts recording calls made on this thread.
+    pub fn install(self: &Arc<Self>) {
+        install(Mode::Record(self.clone()));
+    }
+
+    /// Records an event sent to the FSM, which can happen on any thread.
+    pub fn record_event(&self, event: FsmMessage) {
+        self.write(&Entry::Event {
+            frame: self.frame.load(Ordering::SeqCst),
+            event,
+        });
+    }
+
+    fn begin_frame(&self) {
+        let frame = self.frame.fetch_add(1, Ordering::SeqCst) + 1;
+        self.write(&Entry::Frame { frame });
+        // keep what we have if DCS goes down mid-session
+        let _ = self.out.lock().unwrap().flush();
+    }
+
+    fn record<T: Serialize>(&self, channel: Channel, call: Call, result: &LuaResult<T>) {
+        let result = match result {
+            Ok(value) => serde_json::to_value(value).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        self.write(&Entry::Call {
+            frame: self.frame.load(Ordering::SeqCst),
+            channel,
+            call,
+            result,
+        });
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut out = self.out.lock().unwrap();
+        let written = serde_json::to_writer(&mut *out, entry)
+            .map_err(std::io::Error::from)
+            .and_then(|_| out.write_all(b"\n"));
+        if let Err(e) = written {
+            log::warn!("Failed to write to {}: {e}", self.path.display());
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.out.lock().unwrap().flush();
+    }
+}
+
+/// A recorded call, with the frame it was made in and what it returned.
+#[cfg(test)]
+type Reply = (u64, Call, Result<serde_json::Value, String>);
+
+#[cfg(test)]
+struct PlayerState {
+    frames_left: usize,
+    frame: u64,
+    events: VecDeque<(u64, FsmMessage)>,
+    queues: [VecDeque<Reply>; 3],
+    divergences: Vec<String>,
+}
+
+/// Answers calls from a recording made by [`Recorder`].
+#[cfg(test)]
+pub struct Player {
+    state: Mutex<PlayerState>,
+}
+
+#[cfg(test)]
+impl Player {
+    pub fn load(path: &Path) -> Result<Arc<Self>, crate::Error> {
+        let file = File::open(path).map_err(|e| crate::Error::ParseError(e.to_string()))?;
+        let mut entries = vec![];
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| crate::Error::ParseError(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).map_err(|e| {
+                crate::Error::ParseError(format!("{}:{}: {e}", path.display(), number + 1))
+            })?;
+            entries.push(entry);
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    fn from_entries(entries: Vec<Entry>) -> Arc<Self> {
+        let mut state = PlayerState {
+            frames_left: 0,
+            frame: 0,
+            events: VecDeque::new(),
+            queues: Default::default(),
+            divergences: vec![],
+        };
+        for entry in entries {
+            match entry {
+                Entry::Frame { .. } => state.frames_left += 1,
+                Entry::Event { frame, event } => state.events.push_back((frame, event)),
+                Entry::Call {
+                    frame,
+                    channel,
+                    call,
+                    result,
+                } => state.queues[channel as usize].push_back((frame, call, result)),
+            }
+        }
+        Arc::new(Self {
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Answers calls made on this thread from the recording from now on.
+    pub fn install(self: &Arc<Self>) {
+        install(Mode::Replay(self.clone()));
+    }
+
+    /// Number of recorded frames that have not been replayed yet.
+    pub fn frames_left(&self) -> usize {
+        self.state.lock().unwrap().frames_left
+    }
+
+    /// Calls that did not match the recording, in the order they were made.
+    pub fn divergences(&self) -> Vec<String> {
+        self.state.lock().unwrap().divergences.clone()
+    }
+
+    /// Number of recorded calls that were never made during the replay.
+    pub fn calls_left(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.queues.iter().map(|q| q.len()).sum()
+    }
+
+    fn begin_frame(&self) -> Vec<FsmMessage> {
+        let mut state = self.state.lock().unwrap();
+        state.frames_left = state.frames_left.saturating_sub(1);
+        state.frame += 1;
+        let mut events = vec![];
+        while let Some(&(frame, event)) = state.events.front() {
+            if frame > state.frame {
+                break;
+            }
+            events.push(event);
+            state.events.pop_front();
+        }
+        events
+    }
+
+    fn reply<T: DeserializeOwned>(&self, channel: Channel, call: Call) -> LuaResult<T> {
+        let mut state = self.state.lock().unwrap();
+        let frame = state.frame;
+        let reply = match state.queues[channel as usize].pop_front() {
+            None => Err(format!(
+                "frame {frame}: {call:?} on {channel:?} after the recording ended"
+            )),
+            Some((_, expected, result)) if expected == call => Ok(result),
+            Some((recorded_frame, expected, _)) => Err(format!(
+                "frame {frame}: {call:?} on {channel:?}, recording has {expected:?} \
+                 from frame {recorded_frame}"
+            )),
+        };
+        match reply {
+            Ok(Ok(value)) => {
+                serde_json::from_value(value).map_err(|e| mlua::Error::RuntimeError(e.to_string()))
+            }
+            Ok(Err(e)) => Err(mlua::Error::RuntimeError(e)),
+            Err(divergence) => {
+                log::warn!("Replay diverged: {divergence}");
+                state.divergences.push(divergence.clone());
+                Err(mlua::Error::RuntimeError(divergence))
+            }
+        }
+    }
+}
+
+/// Calls made on this thread are tagged with the `Frame` channel until
+/// [`in_channel()`] says otherwise.
+fn install(mode: Mode) {
+    TAP.with(|tap| {
+        *tap.borrow_mut() = Some(Tap {
+            mode,
+            channel: Channel::Frame,
+        })
+    });
+}
+
+/// Stops recording or replaying on this thread.
+pub fn uninstall() {
+    TAP.with(|tap| tap.borrow_mut().take());
+}
+
+fn mode() -> Option<(Mode, Channel)> {
+    TAP.with(|tap| {
+        tap.borrow()
+            .as_ref()
+            .map(|tap| (tap.mode.clone(), tap.channel))
+    })
+}
+
+/// Marks the start of a DCS frame. When replaying, returns the FSM events
+/// recorded during the frame, which the caller should send to the app thread.
+pub fn begin_frame() -> Vec<FsmMessage> {
+    match mode() {
+        Some((Mode::Record(recorder), _)) => {
+            recorder.begin_frame();
+            vec![]
+        }
+        #[cfg(test)]
+        Some((Mode::Replay(player), _)) => player.begin_frame(),
+        None => vec![],
+    }
+}
+
+/// Runs `f`, tagging any calls it makes with `channel`.
+pub fn in_channel<R>(channel: Channel, f: impl FnOnce() -> R) -> R {
+    let swap = |channel| {
+        TAP.with(|tap| {
+            tap.borrow_mut()
+                .as_mut()
+                .map(|tap| std::mem::replace(&mut tap.channel, channel))
+        })
+    };
+    let previous = swap(channel);
+    let result = f();
+    if let Some(previous) = previous {
+        swap(previous);
+    }
+    result
+}
+
+/// Makes `call` by running `f`, unless a recording is being replayed, in
+/// which case the recorded result is returned instead.
+pub fn tap<T, F>(call: Call, f: F) -> LuaResult<T>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> LuaResult<T>,
+{
+    match mode() {
+        None => f(),
+        #[cfg(test)]
+        Some((Mode::Replay(player), channel)) => player.reply(channel, call),
+        Some((Mode::Record(recorder), channel)) => {
+            let result = f();
+            recorder.record(channel, call, &result);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Call, Channel, Entry, Player};
+    use mlua::Lua;
+
+    #[test]
+    fn test_replay_matches_per_channel() {
+        let reply = |channel, call, value: serde_json::Value| Entry::Call {
+            frame: 1,
+            channel,
+            call,
+            result: Ok(value),
+        };
+        let player = Player::from_entries(vec![
+            Entry::Frame { frame: 1 },
+            reply(Channel::Frame, Call::GetModelTime, 1.5.into()),
+            reply(
+                Channel::Gamegui,
+                Call::GetArgumentValue {
+                    device_id: 0,
+                    argument: 7,
+                },
+                0.25.into(),
+            ),
+            reply(
+                Channel::Export,
+                Call::ListIndication { device: 6 },
+                "".into(),
+            ),
+        ]);
+        // never touched, every call is answered by the player
+        let lua = Lua::new();
+        player.install();
+
+        super::begin_frame();
+        assert_eq!(player.frames_left(), 0);
+        let indication =
+            super::in_channel(Channel::Export, || crate::dcs::list_indication(&lua, 6));
+        assert_eq!(indication.unwrap(), "");
+        let argument = super::in_channel(Channel::Gamegui, || {
+            crate::dcs::get_switch_state(&lua, 0, 7)
+        });
+        assert_eq!(argument.unwrap(), 0.25);
+        assert_eq!(crate::dcs::get_sim_time(&lua).unwrap(), 1.5);
+        assert!(player.divergences().is_empty());
+
+        assert!(crate::dcs::get_sim_time(&lua).is_err());
+        assert_eq!(player.divergences().len(), 1);
+        super::uninstall();
+    }
+}
@@ -0,0 +1,223 @@
+//! Interlocks that keep the automation away from an aircraft that is flying
+//! or rolling, where throwing the main power or the canopy switch could be
+//! catastrophic.
+//!
+//! Before a startup or shutdown request reaches the FSM, the app thread reads
+//! the aircraft's [`FlightState`] and checks it against the aircraft's
+//! [`Interlocks`]. A request is either let through, refused, or held until
+//! the user confirms it in the GUI.
+
+use super::recording::{tap, Call};
+use crate::app::FsmMessage;
+use mlua::prelude::{LuaFunction, LuaResult, LuaTable};
+use mlua::Lua;
+
+/// Height above the ground, in meters, above which an aircraft without
+/// weight on its wheels is flying.
+const AIRBORNE_AGL: f32 = 5.0;
+/// Ground speed, in meters per second, above which an aircraft is moving.
+/// About 2 knots, so a parked jet rocking on its struts doesn't count.
+const MOVING_SPEED: f32 = 1.0;
+
+const KNOTS_PER_MPS: f32 = 1.943_844;
+
+/// What the aircraft is doing, as far as the interlocks care.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlightState {
+    /// Meters above the ground.
+    pub altitude_agl: f32,
+    /// Meters per second over the ground.
+    pub ground_speed: f32,
+    pub weight_on_wheels: bool,
+}
+
+impl FlightState {
+    pub fn read(lua: &Lua) -> LuaResult<Self> {
+        let [x, _, z] = get_velocity(lua)?;
+        Ok(Self {
+            altitude_agl: get_altitude_agl(lua)?,
+            ground_speed: x.hypot(z),
+            weight_on_wheels: get_weight_on_wheels(lua)?,
+        })
+    }
+
+    pub fn is_airborne(&self) -> bool {
+        !self.weight_on_wheels && self.altitude_agl > AIRBORNE_AGL
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.ground_speed > MOVING_SPEED
+    }
+}
+
+fn get_velocity(lua: &Lua) -> LuaResult<[f32; 3]> {
+    tap(Call::LoGetVectorVelocity, || {
+        let export: LuaTable = lua.globals().get("Export")?;
+        let get_velocity: LuaFunction = export.get("LoGetVectorVelocity")?;
+        let velocity: LuaTable = get_velocity.call(())?;
+        Ok([velocity.get("x")?, velocity.get("y")?, velocity.get("z")?])
+    })
+}
+
+fn get_altitude_agl(lua: &Lua) -> LuaResult<f32> {
+    tap(Call::LoGetAltitudeAboveGroundLevel, || {
+        let export: LuaTable = lua.globals().get("Export")?;
+        let get_altitude: LuaFunction = export.get("LoGetAltitudeAboveGroundLevel")?;
+        get_altitude.call(())
+    })
+}
+
+/// Whether either main gear strut is compressed.
+fn get_weight_on_wheels(lua: &Lua) -> LuaResult<bool> {
+    tap(Call::LoGetMechInfo, || {
+        let export: LuaTable = lua.globals().get("Export")?;
+        let get_mech_info: LuaFunction = export.get("LoGetMechInfo")?;
+        let mech_info: LuaTable = get_mech_info.call(())?;
+        let gear: LuaTable = mech_info.get("gear")?;
+        let main: LuaTable = gear.get("main")?;
+        let rod = |side: &str| -> LuaResult<f32> {
+            let strut: LuaTable = main.get(side)?;
+            strut.get("rod")
+        };
+        Ok(rod("left")? > 0.0 || rod("right")? > 0.0)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Response {
+    #[allow(dead_code)]
+    Allow,
+    /// Only go ahead once the user has confirmed.
+    Confirm,
+    Refuse,
+}
+
+/// How a request is answered, depending on what the aircraft is doing. On the
+/// ground and stopped, requests are always let through.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rule {
+    pub airborne: Response,
+    pub moving: Response,
+}
+
+/// An aircraft's rules for each request that touches its cockpit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interlocks {
+    pub startup: Rule,
+    pub shutdown: Rule,
+}
+
+impl Default for Interlocks {
+    fn default() -> Self {
+        Self {
+            startup: Rule {
+                airborne: Response::Refuse,
+                moving: Response::Confirm,
+            },
+            shutdown: Rule {
+                airborne: Response::Refuse,
+                moving: Response::Refuse,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    /// Needs the user's confirmation, for this reason.
+    Confirm(String),
+    Refuse(String),
+}
+
+impl Interlocks {
+    /// Whether `request` may go ahead in `state`. Requests that don't touch
+    /// the cockpit always may.
+    pub fn check(&self, request: FsmMessage, state: &FlightState) -> Verdict {
+        let rule = match request {
+            FsmMessage::StartupAircraft => self.startup,
+            FsmMessage::ShutdownAircraft => self.shutdown,
+            _ => return Verdict::Allow,
+        };
+        let (response, reason) = if state.is_airborne() {
+            let reason = format!("the aircraft is airborne, {:.0} m AGL", state.altitude_agl);
+            (rule.airborne, reason)
+        } else if state.is_moving() {
+            let knots = state.ground_speed * KNOTS_PER_MPS;
+            (
+                rule.moving,
+                format!("the aircraft is moving at {knots:.0} kt"),
+            )
+        } else {
+            return Verdict::Allow;
+        };
+        match response {
+            Response::Allow => Verdict::Allow,
+            Response::Confirm => Verdict::Confirm(reason),
+            Response::Refuse => Verdict::Refuse(reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FlightState, Interlocks, Verdict};
+    use crate::app::FsmMessage;
+    use dcs_mock::MockDcs;
+
+    #[test]
+    fn test_read_flight_state() {
+        let dcs = MockDcs::with_aircraft("F-16C_50").unwrap();
+        {
+            let mut state = dcs.state();
+            state.altitude_agl = 1500.0;
+            state.velocity = [120.0, -5.0, 160.0];
+        }
+        let state = FlightState::read(dcs.lua()).unwrap();
+        assert_eq!(state.ground_speed, 200.0);
+        assert!(state.is_airborne());
+
+        dcs.state().weight_on_wheels = true;
+        assert!(!FlightState::read(dcs.lua()).unwrap().is_airborne());
+    }
+
+    #[test]
+    fn test_default_interlocks() {
+        let interlocks = Interlocks::default();
+        let parked = FlightState {
+            altitude_agl: 1.2,
+            ground_speed: 0.1,
+            weight_on_wheels: true,
+        };
+        let taxiing = FlightState {
+            ground_speed: 8.0,
+            ..parked
+        };
+        let flying = FlightState {
+            altitude_agl: 3000.0,
+            ground_speed: 200.0,
+            weight_on_wheels: false,
+        };
+        let startup = FsmMessage::StartupAircraft;
+        let shutdown = FsmMessage::ShutdownAircraft;
+
+        assert_eq!(interlocks.check(startup, &parked), Verdict::Allow);
+        assert_eq!(interlocks.check(shutdown, &parked), Verdict::Allow);
+        assert_eq!(
+            interlocks.check(startup, &taxiing),
+            Verdict::Confirm(String::from("the aircraft is moving at 16 kt"))
+        );
+        assert!(matches!(
+            interlocks.check(shutdown, &taxiing),
+            Verdict::Refuse(_)
+        ));
+        assert_eq!(
+            interlocks.check(startup, &flying),
+            Verdict::Refuse(String::from("the aircraft is airborne, 3000 m AGL"))
+        );
+        assert_eq!(
+            interlocks.check(FsmMessage::InterruptAircraftStart, &flying),
+            Verdict::Allow
+        );
+    }
+}
@@ -0,0 +1,720 @@
+//! Aircraft modules written in Lua.
+//!
+//! Every `<AircraftId name>.lua` file in the `aircraft` directory under
+//! `lua_path` becomes an [`AircraftModule`]. Scripts can add aircraft yawe has
+//! no Rust module for, or replace an unsupported one; built in modules win
+//! over scripts.
+//!
+//! A script runs in its own Lua state on the app thread, with none of the
+//! standard library that can reach outside it. It may define these globals:
+//!
+//! ```lua
+//! display_name = "Yak-52"
+//! switches = {
+//!     Battery = { device = 1, command = 3001, argument = 101 },
+//! }
+//! function startup()
+//!     yawe.set_switch("Battery", 1)
+//!     yawe.wait_until(function() return yawe.param("VOLTAGE") > 20 end, 10, "voltage")
+//!     yawe.progress(0.5, "Starting engine")
+//! end
+//! function shutdown() ... end
+//! ```
+//!
+//! `startup` and `shutdown` run as coroutines, resumed once per frame, so
+//! `yawe.wait` and `yawe.wait_until` give the frame back to DCS instead of
+//! blocking. Clicks and arguments go through the game GUI thread and return
+//! once DCS answers. Cockpit params and indications are read from what the
+//! export frame sampled, so the first read of each waits for the next frame,
+//! and after that they are sampled every frame for as long as the aircraft
+//! is loaded. The `yawe` table has:
+//!
+//! - `click(device, command, value)` and `get_argument(argument)`
+//! - `set_switch(name, value)` and `get_switch(name)`, using `switches`
+//! - `param(name)`, a cockpit param as a number or a string, or `nil` if it
+//!   isn't listed
+//! - `indication(device, name, ...)`, the value at a path of element names in
+//!   `list_indication(device)`, or `nil` if there is nothing there. The names
+//!   are joined into a [query](super::Indication::query), so a `*` in one
+//!   matches as it does there
+//! - `wait(seconds)` and `wait_until(ready, timeout, what)`, which fails the
+//!   procedure if `ready()` isn't true within `timeout` seconds
+//! - `progress(fraction, text)`, `log(message)` and `now()`
+//!
+//! An error anywhere in a procedure stops it and is shown in the GUI, and so
+//! does running for too long without waiting.
+
+use super::aircraft::{AircraftModule, Capabilities};
+use super::{AircraftFsm, AircraftId, CockpitParams, IndicationChange, ParamValue, Subscription};
+use crate::app::FsmMessage;
+use crate::clock::Clock;
+use crate::gui::TxHandle;
+use mlua::prelude::{LuaError, LuaFunction, LuaResult, LuaTable, LuaThread, LuaValue};
+use mlua::{HookTriggers, Lua, RegistryKey, ThreadStatus, Variadic};
+use offload::TaskSender;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+static SCRIPT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Globals a script may use, besides `yawe`.
+const SAFE_GLOBALS: &[&str] = &[
+    "assert", "error", "ipairs", "next", "pairs", "pcall", "select", "tonumber", "tostring",
+    "type", "unpack", "math", "string", "table",
+];
+
+/// Instructions a script may run between two waits. A script that goes on
+/// longer, most likely in a loop that never waits, fails instead of holding
+/// up the app thread.
+const INSTRUCTION_BUDGET: u32 = 1_000_000;
+
+/// How many instructions run between checks of the budget.
+const INSTRUCTION_CHECK: u32 = 1000;
+
+/// The parts of the API that are easier to write in Lua. Waits yield back to
+/// [`ScriptFsm::run_fsm`] until the next frame.
+const PRELUDE: &str = r#"
+local yawe, env, yield, sampled_param, sampled_indication = ...
+
+-- the sampled values only have what was asked for by the frame before
+local function sampled(read, ...)
+    local ready, value = read(...)
+    while not ready do
+        yield()
+        ready, value = read(...)
+    end
+    return value
+end
+
+function yawe.param(name)
+    return sampled(sampled_param, name)
+end
+
+function yawe.indication(device, ...)
+    return sampled(sampled_indication, device, ...)
+end
+
+function yawe.wait(seconds)
+    local deadline = yawe.now() + seconds
+    repeat
+        yield()
+    until yawe.now() >= deadline
+end
+
+function yawe.wait_until(ready, timeout, what)
+    local deadline = yawe.now() + (timeout or 30)
+    while not ready() do
+        if yawe.now() >= deadline then
+            error("timed out waiting for " .. (what or "condition"), 2)
+        end
+        yield()
+    end
+end
+
+local function switch(name)
+    local switch = env.switches and env.switches[name]
+    if switch == nil then
+        error("no switch named " .. tostring(name), 3)
+    end
+    return switch
+end
+
+function yawe.set_switch(name, value)
+    local s = switch(name)
+    yawe.click(s.device, s.command, value)
+end
+
+function yawe.get_switch(name)
+    return yawe.get_argument(switch(name).argument)
+end
+"#;
+
+/// Where to look for aircraft scripts, normally `aircraft` under `lua_path`.
+/// Only the first call has any effect, and it has to come before the
+/// registry is first used.
+pub fn set_script_dir(dir: PathBuf) {
+    let _ = SCRIPT_DIR.set(dir);
+}
+
+/// Loads every script in the directory given to [`set_script_dir()`].
+pub fn load_modules() -> Vec<(AircraftId, ScriptModule)> {
+    match SCRIPT_DIR.get() {
+        Some(dir) => load_dir(dir),
+        None => vec![],
+    }
+}
+
+fn load_dir(dir: &Path) -> Vec<(AircraftId, ScriptModule)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        log::debug!("No aircraft scripts in {}", dir.display());
+        return vec![];
+    };
+    let mut modules = vec![];
+    for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+        if path.extension() != Some("lua".as_ref()) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let id = super::str_to_ship_enum(name);
+        match ScriptModule::load(&path, name) {
+            Ok(module) => {
+                log::info!("Loaded aircraft script {}", path.display());
+                modules.push((id, module));
+            }
+            Err(e) => log::error!("Failed to load aircraft script {}: {e}", path.display()),
+        }
+    }
+    modules
+}
+
+/// An aircraft whose procedures are a Lua script.
+#[derive(Debug)]
+pub struct ScriptModule {
+    display_name: &'static str,
+    source: String,
+    capabilities: Capabilities,
+}
+
+impl ScriptModule {
+    fn load(path: &Path, name: &str) -> Result<Self, crate::Error> {
+        let source =
+            std::fs::read_to_string(path).map_err(|e| crate::Error::ParseError(e.to_string()))?;
+        Self::from_source(name, source).map_err(crate::Error::LuaError)
+    }
+
+    /// Runs the script once, with no way to reach DCS, to find out what it
+    /// defines.
+    fn from_source(name: &str, source: String) -> LuaResult<Self> {
+        let (to_gamegui, _) = TaskSender::new();
+        let fsm = ScriptFsm::new(name, &source, to_gamegui, TxHandle::detached())?;
+        let env = fsm.env()?;
+        let display_name: Option<String> = env.get("display_name")?;
+        let capabilities = Capabilities {
+            startup: env.get::<_, Option<LuaFunction>>("startup")?.is_some(),
+            shutdown: env.get::<_, Option<LuaFunction>>("shutdown")?.is_some(),
+        };
+        let display_name = display_name.unwrap_or_else(|| name.to_string());
+        Ok(Self {
+            // registered once and kept for as long as the DLL is loaded
+            display_name: Box::leak(display_name.into_boxed_str()),
+            source,
+            capabilities,
+        })
+    }
+}
+
+impl AircraftModule for ScriptModule {
+    fn display_name(&self) -> &'static str {
+        self.display_name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn make_fsm(
+        &self,
+        to_gamegui: TaskSender<Lua>,
+        _to_export: TaskSender<Lua>,
+        gui: TxHandle,
+    ) -> Box<dyn AircraftFsm> {
+        let name = self.display_name;
+        match ScriptFsm::new(name, &self.source, to_gamegui, gui.clone()) {
+            Ok(fsm) => Box::new(fsm),
+            Err(e) => {
+                log::error!("Failed to start the script for {name}: {e}");
+                gui.set_startup_failed(format!("FAILED: {e}"));
+                Box::new(super::EmptyFsm {})
+            }
+        }
+    }
+}
+
+/// Simulation time as of the frame a script is being resumed on.
+#[derive(Debug, Clone, Copy)]
+struct ScriptTime(f32);
+
+/// Instructions the script ran since it last waited, counted by the hook in
+/// steps of [`INSTRUCTION_CHECK`].
+#[derive(Debug, Clone, Copy)]
+struct InstructionsRun(u32);
+
+/// What the export frame sampled for the script. Anything a script reads is
+/// asked for from then on, and isn't there until the frame after.
+#[derive(Debug, Default)]
+struct Sampled {
+    params_wanted: bool,
+    params: Option<Arc<CockpitParams>>,
+    /// The value of each element read, `None` until it is first reported.
+    indications: BTreeMap<Subscription, Option<Option<String>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptState {
+    Idle,
+    Running(&'static str),
+    Done,
+    Failed(String),
+}
+
+/// Runs a script's procedures in response to the GUI's requests, one step of
+/// the coroutine per frame.
+pub struct ScriptFsm {
+    name: String,
+    lua: Lua,
+    env: RegistryKey,
+    thread: Option<RegistryKey>,
+    state: ScriptState,
+    paused: bool,
+    gui: TxHandle,
+}
+
+// SAFETY: the Lua state is created by and only ever reached through the
+// FSM, and the callbacks registered in it only hold `Send` values, so moving
+// the whole FSM to another thread moves everything that touches the state.
+unsafe impl Send for ScriptFsm {}
+
+impl ScriptFsm {
+    pub fn new(
+        name: &str,
+        source: &str,
+        to_gamegui: TaskSender<Lua>,
+        gui: TxHandle,
+    ) -> LuaResult<Self> {
+        let lua = Lua::new();
+        lua.set_app_data(ScriptTime(0.0));
+        lua.set_app_data(InstructionsRun(0));
+        lua.set_app_data(Sampled::default());
+        limit_instructions(&lua)?;
+        let env = {
+            let env = sandbox(&lua, name, to_gamegui, gui.clone())?;
+            lua.load(source)
+                .set_name(name)?
+                .set_environment(env.clone())?
+                .exec()?;
+            lua.create_registry_value(env)?
+        };
+        Ok(Self {
+            name: name.to_string(),
+            lua,
+            env,
+            thread: None,
+            state: ScriptState::Idle,
+            paused: false,
+            gui,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn state(&self) -> &ScriptState {
+        &self.state
+    }
+
+    fn env(&self) -> LuaResult<LuaTable<'_>> {
+        self.lua.registry_value(&self.env)
+    }
+
+    fn start(&mut self, procedure: &'static str) {
+        let thread = self.env().and_then(|env| {
+            let Some(function) = env.get::<_, Option<LuaFunction>>(procedure)? else {
+                return Err(LuaError::RuntimeError(format!(
+                    "{} has no {procedure} procedure",
+                    self.name
+                )));
+            };
+            let thread = self.lua.create_thread(function)?;
+            self.lua.create_registry_value(thread)
+        });
+        match thread {
+            Ok(thread) => {
+                log::info!("Running {procedure} script for {}", self.name);
+                self.thread = Some(thread);
+                self.state = ScriptState::Running(procedure);
+                self.gui.set_startup_progress(0.001);
+            }
+            Err(e) => self.fail(e),
+        }
+    }
+
+    fn interrupt(&mut self) {
+        if self.stop() {
+            self.state = ScriptState::Idle;
+            self.gui.set_startup_progress(0.0);
+            self.gui.set_startup_text("Stopped");
+        }
+    }
+
+    /// Drops the running procedure, if there is one.
+    fn stop(&mut self) -> bool {
+        match self.thread.take() {
+            Some(thread) => {
+                let _ = self.lua.remove_registry_value(thread);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn fail(&mut self, e: LuaError) {
+        log::error!("{} script failed: {e}", self.name);
+        self.stop();
+        let reason = match e {
+            LuaError::CallbackError { cause, .. } => cause.to_string(),
+            e => e.to_string(),
+        };
+        self.gui.set_startup_failed(format!("FAILED: {reason}"));
+        self.state = ScriptState::Failed(reason);
+    }
+
+    /// Runs the procedure until it next waits.
+    fn resume(&mut self, clock: &dyn Clock) {
+        let Some(thread) = &self.thread else {
+            return;
+        };
+        self.lua
+            .set_app_data(ScriptTime(clock.now().unwrap_or_default()));
+        self.lua.set_app_data(InstructionsRun(0));
+        let status = self
+            .lua
+            .registry_value::<LuaThread>(thread)
+            .and_then(|thread| {
+                thread.resume::<_, ()>(())?;
+                Ok(thread.status())
+            });
+        match status {
+            Ok(ThreadStatus::Resumable) => {}
+            Ok(_) => {
+                self.stop();
+                self.state = ScriptState::Done;
+                self.gui.set_startup_progress(1.0);
+                self.gui.set_startup_text("DONE");
+            }
+            Err(e) => self.fail(e),
+        }
+    }
+}
+
+impl AircraftFsm for ScriptFsm {
+    fn run_fsm(&mut self, msg: FsmMessage, clock: &dyn Clock) {
+        match msg {
+            FsmMessage::Paused => self.paused = true,
+            FsmMessage::Resumed => self.paused = false,
+            _ if self.paused => {
+                log::warn!("Ignoring {msg:?} while paused");
+                return;
+            }
+            FsmMessage::StartupAircraft if self.thread.is_none() => self.start("startup"),
+            FsmMessage::ShutdownAircraft if self.thread.is_none() => self.start("shutdown"),
+            FsmMessage::InterruptAircraftStart => self.interrupt(),
+            _ => {}
+        }
+        if !self.paused {
+            self.resume(clock);
+        }
+    }
+
+    fn subscriptions(&self) -> Vec<Subscription> {
+        self.lua
+            .app_data_ref::<Sampled>()
+            .map_or(vec![], |sampled| {
+                sampled.indications.keys().cloned().collect()
+            })
+    }
+
+    fn on_indication(&mut self, change: &IndicationChange) {
+        if let Some(mut sampled) = self.lua.app_data_mut::<Sampled>() {
+            if let Some(value) = sampled.indications.get_mut(&change.subscription) {
+                *value = Some(change.value.clone());
+            }
+        }
+    }
+
+    fn wants_cockpit_params(&self) -> bool {
+        self.lua
+            .app_data_ref::<Sampled>()
+            .is_some_and(|sampled| sampled.params_wanted)
+    }
+
+    fn on_cockpit_params(&mut self, params: &Arc<CockpitParams>) {
+        if let Some(mut sampled) = self.lua.app_data_mut::<Sampled>() {
+            sampled.params = Some(params.clone());
+        }
+    }
+}
+
+/// Fails whatever the script is running once it uses up the
+/// [`INSTRUCTION_BUDGET`]. Coroutines created afterwards inherit the hook.
+fn limit_instructions(lua: &Lua) -> LuaResult<()> {
+    let triggers = HookTriggers {
+        every_nth_instruction: Some(INSTRUCTION_CHECK),
+        ..Default::default()
+    };
+    lua.set_hook(triggers, |lua, _| {
+        let Some(mut run) = lua.app_data_mut::<InstructionsRun>() else {
+            return Ok(());
+        };
+        run.0 = run.0.saturating_add(INSTRUCTION_CHECK);
+        if run.0 > INSTRUCTION_BUDGET {
+            return Err(LuaError::RuntimeError(format!(
+                "ran over {INSTRUCTION_BUDGET} instructions without waiting"
+            )));
+        }
+        Ok(())
+    })
+}
+
+/// The query for a path of element names. A subscription keeps its query
+/// for good, and a script reads the same few paths over and over, so each
+/// one is leaked once.
+fn path_query(path: &[String]) -> &'static str {
+    static QUERIES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let query = path.join("/");
+    let mut queries = QUERIES.lock().unwrap();
+    match queries.get(query.as_str()) {
+        Some(query) => query,
+        None => {
+            let query: &'static str = Box::leak(query.into_boxed_str());
+            queries.insert(query);
+            query
+        }
+    }
+}
+
+/// Runs `f` on the Lua state behind `to` and waits for the result.
+fn on_dcs<T, F>(to: &TaskSender<Lua>, f: F) -> LuaResult<T>
+where
+    T: std::fmt::Debug + Send + 'static,
+    F: FnOnce(&Lua) -> LuaResult<T> + Send + 'static,
+{
+    to.send(f)
+        .wait()
+        .map_err(|_| LuaError::RuntimeError(crate::Error::CommError.to_string()))?
+}
+
+/// The globals a script sees: the safe parts of the standard library and
+/// the `yawe` API.
+fn sandbox<'lua>(
+    lua: &'lua Lua,
+    name: &str,
+    to_gamegui: TaskSender<Lua>,
+    gui: TxHandle,
+) -> LuaResult<LuaTable<'lua>> {
+    let globals = lua.globals();
+    let env = lua.create_table()?;
+    for global in SAFE_GLOBALS {
+        env.set(*global, globals.get::<_, mlua::Value>(*global)?)?;
+    }
+
+    let yawe = lua.create_table()?;
+    let to = to_gamegui.clone();
+    yawe.set(
+        "click",
+        lua.create_function(move |_, (device, command, value): (i32, i32, f32)| {
+            on_dcs(&to, move |lua| {
+                super::perform_click(lua, device, command, value)
+            })
+        })?,
+    )?;
+    let to = to_gamegui;
+    yawe.set(
+        "get_argument",
+        lua.create_function(move |_, argument: i32| {
+            on_dcs(&to, move |lua| super::get_switch_state(lua, 0, argument))
+        })?,
+    )?;
+    // whether the value was sampled yet, and if so the value
+    let sampled_param = lua.create_function(|lua, name: String| {
+        let value = {
+            let Some(mut sampled) = lua.app_data_mut::<Sampled>() else {
+                return Ok((false, LuaValue::Nil));
+            };
+            sampled.params_wanted = true;
+            let Some(params) = &sampled.params else {
+                return Ok((false, LuaValue::Nil));
+            };
+            params.get(&name).cloned()
+        };
+        Ok((
+            true,
+            match value {
+                Some(ParamValue::Number(x)) => LuaValue::Number(x.into()),
+                Some(ParamValue::Text(text)) => LuaValue::String(lua.create_string(&text)?),
+                None => LuaValue::Nil,
+            },
+        ))
+    })?;
+    let sampled_indication =
+        lua.create_function(|lua, (device, path): (i32, Variadic<String>)| {
+            let subscription = Subscription {
+                device,
+                query: path_query(&path),
+            };
+            let mut sampled = lua.app_data_mut::<Sampled>();
+            let value = sampled
+                .as_mut()
+                .and_then(|sampled| sampled.indications.entry(subscription).or_default().clone());
+            Ok((value.is_some(), value.flatten()))
+        })?;
+    yawe.set(
+        "progress",
+        lua.create_function(move |_, (fraction, text): (f32, Option<String>)| {
+            gui.set_startup_progress(fraction.clamp(0.001, 1.0));
+            if let Some(text) = text {
+                gui.set_startup_text(text);
+            }
+            Ok(())
+        })?,
+    )?;
+    let name = name.to_string();
+    yawe.set(
+        "log",
+        lua.create_function(move |_, message: String| {
+            log::info!("{name}: {message}");
+            Ok(())
+        })?,
+    )?;
+    yawe.set(
+        "now",
+        lua.create_function(|lua, ()| {
+            Ok(lua.app_data_ref::<ScriptTime>().map_or(0.0, |time| time.0))
+        })?,
+    )?;
+
+    let coroutine: LuaTable = globals.get("coroutine")?;
+    let yield_: LuaFunction = coroutine.get("yield")?;
+    lua.load(PRELUDE).set_name("yawe prelude")?.call::<_, ()>((
+        yawe.clone(),
+        env.clone(),
+        yield_,
+        sampled_param,
+        sampled_indication,
+    ))?;
+    env.set("yawe", yawe)?;
+    Ok(env)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_dir, ScriptFsm, ScriptState};
+    use crate::app::FsmMessage;
+    use crate::dcs::aircraft::AircraftModule;
+    use crate::dcs::testing::Harness;
+    use crate::dcs::AircraftId;
+    use crate::gui::TxHandle;
+    use dcs_mock::indication::{format, nested};
+    use std::path::PathBuf;
+
+    fn script_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/scripts")
+    }
+
+    fn load_yak() -> String {
+        std::fs::read_to_string(script_dir().join("Yak-52.lua")).unwrap()
+    }
+
+    fn finished(fsm: &ScriptFsm) -> bool {
+        !matches!(fsm.state(), ScriptState::Running(_))
+    }
+
+    #[test]
+    fn test_load_scripts() {
+        let modules = load_dir(&script_dir());
+        let (id, yak) = modules
+            .iter()
+            .find(|(id, _)| *id == AircraftId::Unknown(String::from("Yak-52")))
+            .unwrap();
+        assert_eq!(yak.display_name(), "Yak-52", "{id:?}");
+        assert!(yak.capabilities().startup);
+        assert!(!yak.capabilities().shutdown);
+    }
+
+    #[test]
+    fn test_scripted_startup() {
+        let harness = Harness::new("Yak-52");
+        harness.dcs.state().set_cockpit_param("VOLTAGE", 0.0);
+        harness.dcs.on_click(|state, click| {
+            if (click.device_id, click.command) == (1, 3001) {
+                state.set_argument(101, click.value);
+                state.set_cockpit_param("VOLTAGE", 24.0 * click.value);
+            }
+            if (click.device_id, click.command) == (2, 3005) {
+                let ready = format(&[nested(&["Panel", "Status"], "READY")]);
+                state.indications.insert(5, ready);
+            }
+        });
+
+        let source = load_yak();
+        let fsm = harness.run(
+            move |to_gamegui, _, gui| ScriptFsm::new("Yak-52", &source, to_gamegui, gui).unwrap(),
+            FsmMessage::StartupAircraft,
+            0.1,
+            30.0,
+            finished,
+        );
+        assert_eq!(*fsm.state(), ScriptState::Done);
+
+        let state = harness.dcs.state();
+        assert_eq!(state.argument(101), 1.0);
+        let starter = state.clicks_on(2, 3005);
+        assert_eq!(starter.len(), 1);
+        // the script holds the starter for two seconds after the voltage is up
+        assert!(starter[0].time >= 2.0, "started at {}", starter[0].time);
+    }
+
+    #[test]
+    fn test_script_is_sandboxed_and_times_out() {
+        let source = r#"
+            function startup()
+                assert(os == nil and io == nil and require == nil and coroutine == nil)
+                assert(yawe.param("MODE") == "NAV")
+                yawe.wait_until(function() return yawe.param("VOLTAGE") ~= nil end, 1, "power")
+            end
+        "#;
+        let harness = Harness::new("Yak-52");
+        harness.dcs.state().set_cockpit_param("MODE", "\"NAV\"");
+        let fsm = harness.run(
+            move |to_gamegui, _, gui| ScriptFsm::new("Yak-52", source, to_gamegui, gui).unwrap(),
+            FsmMessage::StartupAircraft,
+            0.1,
+            10.0,
+            finished,
+        );
+        let ScriptState::Failed(reason) = fsm.state() else {
+            panic!("script ended in {:?}", fsm.state());
+        };
+        assert!(reason.contains("timed out waiting for power"), "{reason}");
+        assert!(harness.dcs.state().model_time < 2.0);
+    }
+
+    #[test]
+    fn test_script_that_never_waits_fails() {
+        let source = r#"
+            function startup()
+                yawe.wait(1)
+                while true do end
+            end
+        "#;
+        let harness = Harness::new("Yak-52");
+        let fsm = harness.run(
+            move |to_gamegui, _, gui| ScriptFsm::new("Yak-52", source, to_gamegui, gui).unwrap(),
+            FsmMessage::StartupAircraft,
+            0.1,
+            10.0,
+            finished,
+        );
+        let ScriptState::Failed(reason) = fsm.state() else {
+            panic!("script ended in {:?}", fsm.state());
+        };
+        assert!(reason.contains("without waiting"), "{reason}");
+        assert!(harness.dcs.state().model_time < 2.0);
+
+        // the same goes for the script itself, run when it is loaded
+        let source = "while true do end";
+        let (to_gamegui, _) = offload::TaskSender::new();
+        let fsm = ScriptFsm::new("Yak-52", source, to_gamegui, TxHandle::detached());
+        assert!(fsm.is_err());
+    }
+}
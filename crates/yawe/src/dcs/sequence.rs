@@ -0,0 +1,988 @@
+//! Aircraft procedures as data.
+//!
+//! A procedure is an ordered list of [`Step`]s, each of which does one thing
+//! to the cockpit or waits for something to happen in it. A [`Sequence`] runs
+//! the steps from an aircraft's FSM, one frame at a time: every step that
+//! finishes immediately is run in the same frame, and the first one that has
+//! to wait is polled again on the next frame.
+//!
+//! Each step has a weight, which is roughly how many seconds it takes, so the
+//! progress bar moves at an even pace. Steps that take no time usually have no
+//! weight. A step can also change the text shown under the progress bar; the
+//! text stays until a later step replaces it.
+//!
+//! Every wait has a deadline in simulation time. A wait that misses it fails
+//! the whole sequence, and the failure, with the last value the wait saw, is
+//! shown under the progress bar instead of leaving the bar frozen.
+//!
+//! An aircraft can also give a [`Survey`] that reads its cockpit and tells how
+//! far it already is through the startup, so a jet that is running, or was
+//! started partly by hand, isn't put through the whole procedure again. The
+//! survey names the step to carry on from by its text.
+
+use super::arguments::Batch;
+use super::subscriptions::{IndicationChange, Subscription};
+use super::switches::{Info, SwitchEnum};
+use super::{get_avionics_value, get_cockpit_param, ArgumentValues, CockpitParams};
+use super::{perform_click, set_lockon_command, LockonCommand};
+use crate::app::FsmMessage;
+use crate::clock::{Clock, Timer};
+use crate::gui::TxHandle;
+use mlua::prelude::LuaResult;
+use mlua::Lua;
+use offload::TaskSender;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// How long a three position switch with a command for each direction is held
+/// up before it is let go, otherwise the jet doesn't let it spring back.
+const DUAL_COMMAND_HOLD_TIME: f32 = 0.1;
+
+/// Seconds a wait may take beyond its weight before the sequence fails.
+const TIMEOUT_MARGIN: f32 = 30.0;
+
+/// How close a switch's argument has to be to a named position to be at it.
+const POSITION_TOLERANCE: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThreePos {
+    Down,
+    Middle,
+    Up,
+}
+
+/// What a polled value is waited for to be.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    Equals(f32),
+    AtLeast(f32),
+    AtMost(f32),
+}
+
+impl Condition {
+    pub fn holds(self, value: f32) -> bool {
+        match self {
+            Condition::Equals(x) => value == x,
+            Condition::AtLeast(x) => value >= x,
+            Condition::AtMost(x) => value <= x,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Equals(x) => write!(f, "== {x}"),
+            Condition::AtLeast(x) => write!(f, ">= {x}"),
+            Condition::AtMost(x) => write!(f, "<= {x}"),
+        }
+    }
+}
+
+/// What an indication is waited for to show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expect {
+    /// The first element matching the query shows this text.
+    Text(&'static str),
+    /// An element matches the query.
+    Shown,
+    /// No element matches the query, or the display is blank.
+    Absent,
+}
+
+impl fmt::Display for Expect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expect::Text(text) => write!(f, "to show \"{text}\""),
+            Expect::Shown => write!(f, "to be shown"),
+            Expect::Absent => write!(f, "to go away"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Action<S> {
+    /// Clicks each switch to its value.
+    Set(Vec<(S, f32)>),
+    /// Moves a switch to one of the named positions in its switch table entry.
+    SetPosition(S, &'static str),
+    /// Clicks each toggle switch that isn't already on.
+    TurnOn(Vec<S>),
+    /// Clicks each toggle switch that is on to 0.
+    TurnOff(Vec<S>),
+    /// Holds a spring loaded switch up or down, or lets it go with
+    /// [`ThreePos::Middle`].
+    Spring(S, ThreePos),
+    /// Moves a three position switch which has a command for each direction.
+    DualCommand(S, ThreePos),
+    /// Waits on a switch's argument, from the arguments `on_frame` reads for
+    /// the FSM.
+    WaitArgument(S, Condition),
+    /// Waits on a numeric value from `list_cockpit_params`, from the params
+    /// the export frame samples for the FSM.
+    WaitParam(&'static str, Condition),
+    /// Waits on an element of an indication device, found by a query on the
+    /// `list_indication` tree, see [`Indication::query`]. An empty query
+    /// means the whole display. The element is subscribed to rather than
+    /// polled, so it is only known from the first sample after the step
+    /// begins.
+    ///
+    /// [`Indication::query`]: super::Indication::query
+    WaitIndication {
+        device: i32,
+        query: &'static str,
+        expect: Expect,
+    },
+    /// Waits for this many seconds of simulation time.
+    Delay(f32),
+    Lockon(LockonCommand),
+}
+
+impl<S: fmt::Debug> Action<S> {
+    /// What the action waits for, if it is a wait that can time out.
+    fn waiting_for(&self) -> Option<String> {
+        match self {
+            Action::WaitArgument(switch, condition) => Some(format!("{switch:?} {condition}")),
+            Action::WaitParam(name, condition) => Some(format!("{name} {condition}")),
+            Action::WaitIndication {
+                device,
+                query,
+                expect,
+            } => Some(format!("indication {device} `{query}` {expect}")),
+            _ => None,
+        }
+    }
+
+    /// The indication element the action waits on, if any.
+    fn subscription(&self) -> Option<Subscription> {
+        match *self {
+            Action::WaitIndication { device, query, .. } => Some(Subscription { device, query }),
+            _ => None,
+        }
+    }
+
+    fn wants_cockpit_params(&self) -> bool {
+        matches!(self, Action::WaitParam(..))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Step<S> {
+    pub action: Action<S>,
+    pub text: Option<&'static str>,
+    pub weight: f32,
+}
+
+impl<S> Step<S> {
+    pub fn new(action: Action<S>) -> Self {
+        Self {
+            action,
+            text: None,
+            weight: 0.0,
+        }
+    }
+
+    /// Shows `text` under the progress bar once the step begins.
+    pub fn text(mut self, text: &'static str) -> Self {
+        self.text = Some(text);
+        self
+    }
+
+    pub fn weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Seconds the step may wait before the sequence fails.
+    fn timeout(&self) -> f32 {
+        self.weight + TIMEOUT_MARGIN
+    }
+}
+
+/// Why a sequence gave up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    /// Index of the step that failed.
+    pub step: usize,
+    /// The progress text when the step failed, e.g. "Waiting for JFS".
+    pub stage: Option<&'static str>,
+    pub reason: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stage {
+            Some(stage) => write!(f, "{stage} (step {}): {}", self.step, self.reason),
+            None => write!(f, "step {}: {}", self.step, self.reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SequenceState {
+    Idle,
+    Running,
+    Done,
+    Failed(Failure),
+}
+
+/// How far an aircraft already is through its startup, judged from the
+/// cockpit rather than from what the autostart has done.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CockpitState {
+    /// Nothing is running, so the startup can run from the first step.
+    ColdDark,
+    /// Started by hand, and the startup carries on from the step with this
+    /// text.
+    StartedUpTo(&'static str),
+    Running,
+}
+
+/// Reads the cockpit to find its [`CockpitState`]. Runs on the app thread and
+/// blocks on DCS.
+pub type Survey<S> = fn(&Cockpit<S>) -> Result<CockpitState, crate::Error>;
+
+/// What a [`Survey`] can read.
+pub struct Cockpit<'a, S> {
+    to_gamegui: &'a TaskSender<Lua>,
+    to_export: &'a TaskSender<Lua>,
+    _switch: PhantomData<S>,
+}
+
+impl<S: SwitchEnum + Send> Cockpit<'_, S> {
+    pub fn argument(&self, switch: S) -> Result<f32, crate::Error> {
+        self.to_gamegui
+            .send(move |lua| read_argument(lua, switch))
+            .wait()
+            .map_err(|_| crate::Error::CommError)?
+            .map_err(crate::Error::LuaError)
+    }
+
+    /// Reads several switches' arguments in the same frame.
+    pub fn arguments<const N: usize>(&self, switches: [S; N]) -> Result<[f32; N], crate::Error> {
+        let batch = switches.iter().fold(Batch::new(), |batch, &s| {
+            batch.read(S::table().info(s).argument())
+        });
+        let values = batch.send(self.to_gamegui)?;
+        Ok(std::array::from_fn(|i| values[i]))
+    }
+
+    /// Whether a switch is at one of the named positions in its switch table
+    /// entry.
+    pub fn is_at(&self, switch: S, position: &'static str) -> Result<bool, crate::Error> {
+        let Some(value) = S::table().position(switch, position) else {
+            log::warn!("{switch:?} has no position {position} in the switch table");
+            return Ok(false);
+        };
+        Ok((self.argument(switch)? - value).abs() < POSITION_TOLERANCE)
+    }
+
+    pub fn param(&self, name: &'static str) -> Result<f32, crate::Error> {
+        self.to_export
+            .send(move |lua| get_cockpit_param(lua, name))
+            .wait()
+            .map_err(|_| crate::Error::CommError)?
+    }
+
+    /// The value of the first indication element matching `query`, or
+    /// `None` if there isn't one.
+    pub fn indication(&self, device: i32, query: &str) -> Option<String> {
+        get_avionics_value(self.to_export, device, query)
+    }
+}
+
+/// Runs a procedure against the cockpit.
+#[derive(Debug, Clone)]
+pub struct Sequence<S> {
+    name: &'static str,
+    steps: Vec<Step<S>>,
+    state: SequenceState,
+    current: usize,
+    /// Whether the current step has done its action and is now waiting.
+    entered: bool,
+    /// Runs from the start of the sequence.
+    started: Timer,
+    /// Runs from the start of the current step and expires at its timeout.
+    step_timer: Timer,
+    done_weight: f32,
+    total_weight: f32,
+    /// Spring loaded switches the sequence is holding away from the middle.
+    held: Vec<S>,
+    /// The progress text last shown.
+    stage: Option<&'static str>,
+    /// What the current wait last saw, for reporting a failure.
+    last_value: Option<String>,
+    /// The last value sampled for the current step's subscription, if any.
+    indications: HashMap<Subscription, Option<String>>,
+    /// The cockpit params last sampled while the current step waits on them.
+    cockpit_params: Option<Arc<CockpitParams>>,
+    /// The arguments last read since the current step began.
+    arguments: Option<Arc<ArgumentValues>>,
+    to_gamegui: TaskSender<Lua>,
+    gui: TxHandle,
+}
+
+impl<S: SwitchEnum + Send> Sequence<S> {
+    /// `name` is only used for logging, e.g. "startup".
+    pub fn new(
+        name: &'static str,
+        steps: Vec<Step<S>>,
+        to_gamegui: TaskSender<Lua>,
+        gui: TxHandle,
+    ) -> Self {
+        let total_weight = steps.iter().map(|s| s.weight).sum();
+        Self {
+            name,
+            steps,
+            state: SequenceState::Idle,
+            current: 0,
+            entered: false,
+            started: Timer::default(),
+            step_timer: Timer::default(),
+            done_weight: 0.0,
+            total_weight,
+            held: vec![],
+            stage: None,
+            last_value: None,
+            indications: HashMap::new(),
+            cockpit_params: None,
+            arguments: None,
+            to_gamegui,
+            gui,
+        }
+    }
+
+    pub fn state(&self) -> &SequenceState {
+        &self.state
+    }
+
+    /// The step being run, while the sequence is running.
+    pub fn current(&self) -> Option<&Step<S>> {
+        match self.state {
+            SequenceState::Running => self.steps.get(self.current),
+            _ => None,
+        }
+    }
+
+    /// The indication element the current step waits on, if any.
+    pub fn subscription(&self) -> Option<Subscription> {
+        self.current()?.action.subscription()
+    }
+
+    /// Takes in a sampled indication element, which the current step sees
+    /// when it is next polled.
+    pub fn on_indication(&mut self, change: &IndicationChange) {
+        if self.subscription().as_ref() == Some(&change.subscription) {
+            self.indications
+                .insert(change.subscription.clone(), change.value.clone());
+        }
+    }
+
+    pub fn wants_cockpit_params(&self) -> bool {
+        self.current()
+            .is_some_and(|step| step.action.wants_cockpit_params())
+    }
+
+    pub fn on_cockpit_params(&mut self, params: &Arc<CockpitParams>) {
+        if self.wants_cockpit_params() {
+            self.cockpit_params = Some(params.clone());
+        }
+    }
+
+    /// The argument the current step waits on, if any.
+    pub fn wanted_arguments(&self) -> Vec<i32> {
+        match self.current().map(|step| &step.action) {
+            Some(&Action::WaitArgument(switch, _)) => vec![S::table().info(switch).argument()],
+            _ => vec![],
+        }
+    }
+
+    pub fn on_arguments(&mut self, values: &Arc<ArgumentValues>) {
+        if !self.wanted_arguments().is_empty() {
+            self.arguments = Some(values.clone());
+        }
+    }
+
+    /// Seconds of simulation time since the sequence was started.
+    pub fn elapsed(&self, clock: &dyn Clock) -> f32 {
+        self.started.elapsed(clock).unwrap_or_default()
+    }
+
+    /// Starts the sequence from the first step.
+    pub fn start(&mut self, clock: &dyn Clock) {
+        self.start_at(clock, 0);
+    }
+
+    /// Starts the sequence from the step with `text`, as if every step before
+    /// it had been done.
+    pub fn start_from(&mut self, clock: &dyn Clock, text: &'static str) {
+        match self.steps.iter().position(|step| step.text == Some(text)) {
+            Some(index) => self.start_at(clock, index),
+            None => {
+                log::warn!("{} has no step \"{text}\", starting over", self.name);
+                self.start_at(clock, 0);
+            }
+        }
+    }
+
+    fn start_at(&mut self, clock: &dyn Clock, index: usize) {
+        log::info!("Starting {} sequence at step {index}", self.name);
+        self.state = SequenceState::Running;
+        self.current = index;
+        self.entered = false;
+        self.started = Timer::start(clock, 0.0);
+        self.done_weight = self.steps[..index].iter().map(|s| s.weight).sum();
+        self.stage = None;
+        self.indications.clear();
+        self.cockpit_params = None;
+        self.arguments = None;
+        // this should cause the progress bar to begin animating
+        let done = self.done_weight / self.total_weight.max(1.0);
+        self.gui.set_startup_progress(done.max(0.001));
+    }
+
+    /// Stops the sequence if it is running, letting go of every spring loaded
+    /// switch it holds. It can then be started again from the beginning.
+    pub fn interrupt(&mut self) {
+        if self.state != SequenceState::Running {
+            return;
+        }
+        log::info!(
+            "Interrupted {} sequence at step {}",
+            self.name,
+            self.current
+        );
+        self.release_switches();
+
+        self.state = SequenceState::Idle;
+        self.current = 0;
+        self.entered = false;
+        self.done_weight = 0.0;
+        self.gui.set_startup_progress(0.0);
+        self.gui.set_startup_text("Stopped");
+    }
+
+    /// Lets go of every spring loaded switch the sequence holds.
+    fn release_switches(&mut self) {
+        let held = std::mem::take(&mut self.held);
+        let holding_dual_command = match self.steps.get(self.current).map(|s| &s.action) {
+            Some(&Action::DualCommand(switch, ThreePos::Up)) if self.entered => Some(switch),
+            _ => None,
+        };
+        let result = self.on_gamegui(move |lua| {
+            for switch in held {
+                set_spring(lua, switch, ThreePos::Middle)?;
+            }
+            if let Some(switch) = holding_dual_command {
+                release_dual_command(lua, switch)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            log::warn!(
+                "Releasing switches held by the {} sequence failed: {e}",
+                self.name
+            );
+        }
+    }
+
+    /// Gives up on the sequence, leaving the progress bar where it stopped.
+    fn fail(&mut self, clock: &dyn Clock) {
+        let step = &self.steps[self.current];
+        let waiting_for = step.action.waiting_for().unwrap_or_default();
+        let last_value = self.last_value.as_deref().unwrap_or("nothing");
+        let failure = Failure {
+            step: self.current,
+            stage: self.stage,
+            reason: format!(
+                "timed out after {:.0} s waiting for {waiting_for}, last saw {last_value}",
+                self.step_timer.elapsed(clock).unwrap_or_default()
+            ),
+        };
+        log::error!("{} sequence failed at {failure}", self.name);
+        self.release_switches();
+        self.gui.set_startup_failed(format!("FAILED: {failure}"));
+        self.state = SequenceState::Failed(failure);
+        self.entered = false;
+    }
+
+    /// Runs as many steps as can be finished this frame. Returns true on the
+    /// frame the sequence finishes.
+    pub fn run(&mut self, clock: &dyn Clock) -> bool {
+        if self.state != SequenceState::Running {
+            return false;
+        }
+        while self.current < self.steps.len() {
+            if !self.entered {
+                self.entered = true;
+                self.last_value = None;
+                let step = &self.steps[self.current];
+                self.step_timer = Timer::start(clock, step.timeout());
+                // a value for another element is stale by the time it is
+                // subscribed to again
+                let subscription = step.action.subscription();
+                self.indications
+                    .retain(|s, _| Some(s) == subscription.as_ref());
+                if !step.action.wants_cockpit_params() {
+                    self.cockpit_params = None;
+                }
+                // only arguments read after the step's clicks count
+                self.arguments = None;
+                log::debug!("{} step {}: {:?}", self.name, self.current, step.action);
+                if let Some(text) = step.text {
+                    self.stage = Some(text);
+                    self.gui.set_startup_text(text);
+                }
+                self.begin(self.current);
+            }
+            if !self.poll(self.current, clock) {
+                let waiting = self.steps[self.current].action.waiting_for().is_some();
+                if waiting && self.step_timer.is_expired(clock) {
+                    self.fail(clock);
+                    return false;
+                }
+                self.update_progress(clock);
+                return false;
+            }
+            self.done_weight += self.steps[self.current].weight;
+            self.current += 1;
+            self.entered = false;
+        }
+
+        log::info!(
+            "Finished {} sequence in {} seconds",
+            self.name,
+            self.elapsed(clock)
+        );
+        self.state = SequenceState::Done;
+        self.gui.set_startup_progress(1.0);
+        self.gui.set_startup_text("DONE");
+        true
+    }
+
+    fn update_progress(&self, clock: &dyn Clock) {
+        let Some(step) = self.current() else {
+            return;
+        };
+        if self.total_weight <= 0.0 {
+            return;
+        }
+        let weight = step.weight;
+        let partial = self
+            .step_timer
+            .elapsed(clock)
+            .unwrap_or_default()
+            .clamp(0.0, weight);
+        self.gui
+            .set_startup_progress((self.done_weight + partial) / self.total_weight);
+    }
+
+    /// Does the step's action, if it has one.
+    fn begin(&mut self, index: usize) {
+        let action = self.steps[index].action.clone();
+        let result = match action {
+            Action::Set(switches) => {
+                let batch = switches
+                    .into_iter()
+                    .fold(Batch::new(), |batch, (switch, value)| {
+                        match click_command(switch) {
+                            Some((device_id, command)) => batch.click(device_id, command, value),
+                            None => batch,
+                        }
+                    });
+                batch.send(&self.to_gamegui).map(drop)
+            }
+            Action::SetPosition(switch, position) => self.on_gamegui(move |lua| {
+                let Some(value) = S::table().position(switch, position) else {
+                    log::warn!("{switch:?} has no position {position} in the switch table");
+                    return Ok(());
+                };
+                click(lua, switch, value)
+            }),
+            Action::TurnOn(switches) => self.on_gamegui(move |lua| {
+                for switch in switches {
+                    if read_argument(lua, switch)? <= 0.5 {
+                        click(lua, switch, 1.0)?;
+                    }
+                }
+                Ok(())
+            }),
+            Action::TurnOff(switches) => self.on_gamegui(move |lua| {
+                for switch in switches {
+                    if read_argument(lua, switch)? > 0.5 {
+                        click(lua, switch, 0.0)?;
+                    }
+                }
+                Ok(())
+            }),
+            Action::Spring(switch, position) => {
+                self.held.retain(|s| s.index() != switch.index());
+                if position != ThreePos::Middle {
+                    self.held.push(switch);
+                }
+                self.on_gamegui(move |lua| set_spring(lua, switch, position))
+            }
+            Action::DualCommand(switch, position) => {
+                self.on_gamegui(move |lua| set_dual_command(lua, switch, position))
+            }
+            Action::Lockon(command) => self.on_gamegui(move |lua| set_lockon_command(lua, command)),
+            Action::WaitArgument(..)
+            | Action::WaitParam(..)
+            | Action::WaitIndication { .. }
+            | Action::Delay(_) => Ok(()),
+        };
+        if let Err(e) = result {
+            log::warn!("{} step {index} failed: {e}", self.name);
+        }
+    }
+
+    /// Whether the step is finished.
+    fn poll(&mut self, index: usize, clock: &dyn Clock) -> bool {
+        let elapsed = self.step_timer.elapsed(clock);
+        match self.steps[index].action {
+            Action::WaitArgument(switch, condition) => {
+                let argument = S::table().info(switch).argument();
+                let value = self
+                    .arguments
+                    .as_ref()
+                    .and_then(|values| values.get(argument));
+                let Some(value) = value else {
+                    return false;
+                };
+                self.last_value = Some(value.to_string());
+                condition.holds(value)
+            }
+            Action::WaitParam(name, condition) => {
+                let Some(params) = &self.cockpit_params else {
+                    return false;
+                };
+                match params.get(name) {
+                    Some(value) => {
+                        self.last_value = Some(value.to_string());
+                        value.number().is_some_and(|value| condition.holds(value))
+                    }
+                    None => {
+                        self.last_value = Some(String::from("no such param"));
+                        false
+                    }
+                }
+            }
+            Action::WaitIndication {
+                device,
+                query,
+                expect,
+            } => {
+                let subscription = Subscription { device, query };
+                let Some(value) = self.indications.get(&subscription) else {
+                    return false;
+                };
+                let value = value.as_deref();
+                self.last_value = Some(match value {
+                    Some(value) => format!("\"{value}\""),
+                    None => String::from("no such element"),
+                });
+                match expect {
+                    Expect::Text(text) => value == Some(text),
+                    Expect::Shown => value.is_some(),
+                    Expect::Absent => value.is_none(),
+                }
+            }
+            Action::Delay(seconds) => elapsed.is_none_or(|elapsed| elapsed >= seconds),
+            Action::DualCommand(switch, ThreePos::Up) => {
+                if elapsed.is_some_and(|elapsed| elapsed < DUAL_COMMAND_HOLD_TIME) {
+                    return false;
+                }
+                let result = self.on_gamegui(move |lua| release_dual_command(lua, switch));
+                if let Err(e) = result {
+                    log::warn!("Releasing {switch:?} failed: {e}");
+                }
+                true
+            }
+            Action::Set(_)
+            | Action::SetPosition(..)
+            | Action::TurnOn(_)
+            | Action::TurnOff(_)
+            | Action::Spring(..)
+            | Action::DualCommand(..)
+            | Action::Lockon(_) => true,
+        }
+    }
+
+    fn on_gamegui<T, F>(&self, f: F) -> Result<T, crate::Error>
+    where
+        T: std::fmt::Debug + Send + 'static,
+        F: FnOnce(&Lua) -> LuaResult<T> + Send + 'static,
+    {
+        self.to_gamegui
+            .send(f)
+            .wait()
+            .map_err(|_| crate::Error::CommError)?
+            .map_err(crate::Error::LuaError)
+    }
+}
+
+/// An aircraft's startup and shutdown procedures, run in response to the
+/// GUI's requests. Only one of them runs at a time.
+#[derive(Debug, Clone)]
+pub struct Procedures<S> {
+    startup: Sequence<S>,
+    shutdown: Sequence<S>,
+    paused: bool,
+    survey: Option<Survey<S>>,
+    /// Whether the cockpit has been looked at since the aircraft was entered.
+    surveyed: bool,
+    to_gamegui: TaskSender<Lua>,
+    to_export: TaskSender<Lua>,
+    gui: TxHandle,
+}
+
+impl<S: SwitchEnum + Send> Procedures<S> {
+    pub fn new(
+        startup: Vec<Step<S>>,
+        shutdown: Vec<Step<S>>,
+        to_gamegui: TaskSender<Lua>,
+        to_export: TaskSender<Lua>,
+        gui: TxHandle,
+    ) -> Self {
+        Self {
+            startup: Sequence::new("startup", startup, to_gamegui.clone(), gui.clone()),
+            shutdown: Sequence::new("shutdown", shutdown, to_gamegui.clone(), gui.clone()),
+            paused: false,
+            survey: None,
+            surveyed: false,
+            to_gamegui,
+            to_export,
+            gui,
+        }
+    }
+
+    /// Looks at the cockpit with `survey` when the aircraft is entered and
+    /// whenever the startup is asked for, instead of assuming it is cold and
+    /// dark.
+    pub fn survey(mut self, survey: Survey<S>) -> Self {
+        self.survey = Some(survey);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn startup(&self) -> &Sequence<S> {
+        &self.startup
+    }
+
+    #[cfg(test)]
+    pub fn shutdown(&self) -> &Sequence<S> {
+        &self.shutdown
+    }
+
+    fn cockpit_state(&self) -> CockpitState {
+        let Some(survey) = self.survey else {
+            return CockpitState::ColdDark;
+        };
+        let cockpit = Cockpit {
+            to_gamegui: &self.to_gamegui,
+            to_export: &self.to_export,
+            _switch: PhantomData,
+        };
+        match survey(&cockpit) {
+            Ok(state) => {
+                log::info!("Cockpit is {state:?}");
+                state
+            }
+            Err(e) => {
+                log::warn!("Couldn't survey the cockpit, assuming it is cold and dark: {e}");
+                CockpitState::ColdDark
+            }
+        }
+    }
+
+    fn show_cockpit_state(&self, state: CockpitState) {
+        match state {
+            CockpitState::ColdDark => {}
+            CockpitState::StartedUpTo(text) => {
+                self.gui.set_startup_progress(0.0);
+                self.gui
+                    .set_startup_text(format!("Started by hand, autostart resumes at: {text}"));
+            }
+            CockpitState::Running => {
+                self.gui.set_startup_progress(1.0);
+                self.gui.set_startup_text("Already running");
+            }
+        }
+    }
+
+    fn start_startup(&mut self, clock: &dyn Clock) {
+        self.surveyed = true;
+        match self.cockpit_state() {
+            CockpitState::ColdDark => self.startup.start(clock),
+            CockpitState::StartedUpTo(text) => self.startup.start_from(clock, text),
+            state @ CockpitState::Running => self.show_cockpit_state(state),
+        }
+    }
+
+    /// The indication elements the running procedure waits on.
+    pub fn subscriptions(&self) -> Vec<Subscription> {
+        [self.startup.subscription(), self.shutdown.subscription()]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    pub fn on_indication(&mut self, change: &IndicationChange) {
+        self.startup.on_indication(change);
+        self.shutdown.on_indication(change);
+    }
+
+    pub fn wants_cockpit_params(&self) -> bool {
+        self.startup.wants_cockpit_params() || self.shutdown.wants_cockpit_params()
+    }
+
+    pub fn wanted_arguments(&self) -> Vec<i32> {
+        let mut arguments = self.startup.wanted_arguments();
+        arguments.extend(self.shutdown.wanted_arguments());
+        arguments
+    }
+
+    pub fn on_arguments(&mut self, values: &Arc<ArgumentValues>) {
+        self.startup.on_arguments(values);
+        self.shutdown.on_arguments(values);
+    }
+
+    pub fn on_cockpit_params(&mut self, params: &Arc<CockpitParams>) {
+        self.startup.on_cockpit_params(params);
+        self.shutdown.on_cockpit_params(params);
+    }
+
+    fn is_running(&self) -> bool {
+        *self.startup.state() == SequenceState::Running
+            || *self.shutdown.state() == SequenceState::Running
+    }
+
+    /// Handles a request from the GUI, then runs whichever procedure is in
+    /// progress. Called once per frame, starting with the frame the aircraft
+    /// is entered on, when the cockpit is surveyed. Nothing runs while the
+    /// simulation is paused.
+    pub fn run_fsm(&mut self, event: FsmMessage, clock: &dyn Clock) {
+        match event {
+            FsmMessage::Paused => self.paused = true,
+            FsmMessage::Resumed => self.paused = false,
+            _ if self.paused => {
+                log::warn!("Ignoring {event:?} while paused");
+                return;
+            }
+            FsmMessage::StartupAircraft if !self.is_running() => self.start_startup(clock),
+            FsmMessage::ShutdownAircraft if !self.is_running() => self.shutdown.start(clock),
+            FsmMessage::InterruptAircraftStart => {
+                self.startup.interrupt();
+                self.shutdown.interrupt();
+            }
+            _ => {}
+        }
+        if self.paused {
+            return;
+        }
+        if !self.surveyed {
+            self.surveyed = true;
+            self.show_cockpit_state(self.cockpit_state());
+        }
+        self.startup.run(clock);
+        self.shutdown.run(clock);
+    }
+}
+
+pub fn read_argument<S: SwitchEnum>(lua: &Lua, s: S) -> LuaResult<f32> {
+    super::get_switch_state(lua, 0, S::table().info(s).argument())
+}
+
+/// Clicks a switch's command, the downwards one for spring loaded switches,
+/// with `value`.
+pub fn click<S: SwitchEnum>(lua: &Lua, s: S, value: f32) -> LuaResult<()> {
+    match click_command(s) {
+        Some((device_id, command)) => perform_click(lua, device_id, command, value),
+        None => Ok(()),
+    }
+}
+
+/// The device and command [`click()`] uses for a switch, if it can be clicked.
+fn click_command<S: SwitchEnum>(s: S) -> Option<(i32, i32)> {
+    match *S::table().info(s) {
+        Info::Toggle {
+            device_id, command, ..
+        }
+        | Info::MultiToggle {
+            device_id, command, ..
+        }
+        | Info::Momentary {
+            device_id, command, ..
+        }
+        | Info::Axis {
+            device_id, command, ..
+        }
+        | Info::SpringLoaded3Pos {
+            device_id,
+            command_down: command,
+            ..
+        } => Some((device_id, command)),
+        Info::DualCommand3Pos { .. } | Info::FloatValue { .. } => {
+            log::warn!("Tried to click {s:?} which is not possible");
+            None
+        }
+    }
+}
+
+pub fn set_spring<S: SwitchEnum>(lua: &Lua, s: S, position: ThreePos) -> LuaResult<()> {
+    let &Info::SpringLoaded3Pos {
+        device_id,
+        command_down,
+        command_up,
+        ..
+    } = S::table().info(s)
+    else {
+        log::warn!("Tried to interpret {s:?} as a three pos springloaded switch");
+        return Ok(());
+    };
+    perform_click(lua, device_id, command_down, 0.0)?;
+    perform_click(lua, device_id, command_up, 0.0)?;
+
+    match position {
+        ThreePos::Down => perform_click(lua, device_id, command_down, -1.0),
+        ThreePos::Middle => Ok(()),
+        ThreePos::Up => perform_click(lua, device_id, command_up, 1.0),
+    }
+}
+
+fn dual_command_info<S: SwitchEnum>(s: S) -> Option<(i32, i32, i32)> {
+    let &Info::DualCommand3Pos {
+        device_id,
+        command_down,
+        command_up,
+        ..
+    } = S::table().info(s)
+    else {
+        log::warn!("Tried to interpret {s:?} as a dual command three pos switch, but it is not");
+        return None;
+    };
+    Some((device_id, command_down, command_up))
+}
+
+fn set_dual_command<S: SwitchEnum>(lua: &Lua, s: S, position: ThreePos) -> LuaResult<()> {
+    let Some((device_id, command_down, command_up)) = dual_command_info(s) else {
+        return Ok(());
+    };
+    match position {
+        ThreePos::Down => perform_click(lua, device_id, command_down, -1.0),
+        ThreePos::Middle => {
+            perform_click(lua, device_id, command_down, 0.0)?;
+            perform_click(lua, device_id, command_up, -1.0)
+        }
+        ThreePos::Up => perform_click(lua, device_id, command_up, 1.0),
+    }
+}
+
+fn release_dual_command<S: SwitchEnum>(lua: &Lua, s: S) -> LuaResult<()> {
+    let Some((device_id, _, command_up)) = dual_command_info(s) else {
+        return Ok(());
+    };
+    perform_click(lua, device_id, command_up, 0.0)
+}
@@ -0,0 +1,313 @@
+//! Indication elements and cockpit params the app thread wants to hear about.
+//!
+//! Rather than fetching and parsing a whole display every time it looks at
+//! one element, an FSM lists the (device, query) pairs it is waiting on in
+//! [`AircraftFsm::subscriptions`]. The export frame then fetches each of
+//! those devices once, and when a device's text differs from the frame
+//! before, parses it and reports every subscribed element whose value
+//! changed. The changes reach the FSM through
+//! [`AircraftFsm::on_indication`].
+//!
+//! The cockpit params work the same way, all of them at once: while an FSM
+//! says it [wants them](super::AircraftFsm::wants_cockpit_params), they are
+//! listed once per frame, and parsed and handed to
+//! [`AircraftFsm::on_cockpit_params`] whenever they changed.
+//!
+//! Cockpit arguments change too often to be worth comparing. The ones an FSM
+//! [wants](super::AircraftFsm::wanted_arguments) are read in one pass every
+//! frame into a snapshot, which the app thread takes the next time it wakes.
+//!
+//! [`AircraftFsm::subscriptions`]: super::AircraftFsm::subscriptions
+//! [`AircraftFsm::on_indication`]: super::AircraftFsm::on_indication
+//! [`AircraftFsm::on_cockpit_params`]: super::AircraftFsm::on_cockpit_params
+
+use super::arguments::read_arguments;
+use super::indication;
+use super::{list_cockpit_params, list_indication, ArgumentValues, CockpitParams};
+use mlua::Lua;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// The first element matching `query` on indication `device`, see
+/// [`Indication::query`](super::Indication::query).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Subscription {
+    pub device: i32,
+    pub query: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndicationChange {
+    pub subscription: Subscription,
+    /// `None` if nothing matches, or the display is blank.
+    pub value: Option<String>,
+}
+
+/// The subscriptions, shared between the app thread, which says what it
+/// wants, and the export frame, which samples it.
+#[derive(Debug, Clone, Default)]
+pub struct Subscriptions {
+    sampler: Arc<Mutex<Sampler>>,
+}
+
+#[derive(Debug, Default)]
+struct Sampler {
+    /// The value last reported for each subscription, `None` until it is
+    /// first sampled.
+    wanted: BTreeMap<Subscription, Option<Option<String>>>,
+    /// The text each subscribed device showed when it was last sampled.
+    texts: HashMap<i32, String>,
+    cockpit_params_wanted: bool,
+    /// The cockpit params as last listed, `None` until they are first sampled.
+    cockpit_params: Option<String>,
+    arguments: Vec<i32>,
+    /// The arguments as last read, until they are taken.
+    argument_values: Option<Arc<ArgumentValues>>,
+}
+
+impl Subscriptions {
+    /// Replaces the subscriptions with `wanted`. Ones that were already
+    /// there are only reported again once they change, new ones are reported
+    /// on the next sample.
+    pub fn set(&self, wanted: Vec<Subscription>) {
+        let mut sampler = self.sampler.lock().unwrap();
+        let mut old = std::mem::take(&mut sampler.wanted);
+        sampler.wanted = wanted
+            .into_iter()
+            .map(|subscription| {
+                let last = old.remove(&subscription).flatten();
+                (subscription, last)
+            })
+            .collect();
+        let Sampler { wanted, texts, .. } = &mut *sampler;
+        texts.retain(|device, _| wanted.keys().any(|s| s.device == *device));
+    }
+
+    /// Fetches every subscribed device once and returns what changed since
+    /// the last sample. Runs in the export frame.
+    pub fn sample(&self, lua: &Lua) -> Vec<IndicationChange> {
+        let mut sampler = self.sampler.lock().unwrap();
+        let Sampler { wanted, texts, .. } = &mut *sampler;
+        let mut devices: Vec<i32> = wanted.keys().map(|s| s.device).collect();
+        devices.dedup();
+
+        let mut changes = vec![];
+        for device in devices {
+            let text = match list_indication(lua, device) {
+                Ok(text) => text,
+                Err(e) => {
+                    log::warn!("Sampling indication {device} failed: {e}");
+                    continue;
+                }
+            };
+            let unsampled = wanted
+                .iter()
+                .any(|(s, last)| s.device == device && last.is_none());
+            if !unsampled && texts.get(&device) == Some(&text) {
+                continue;
+            }
+            let tree = match indication::parse(&text) {
+                Ok(tree) => tree,
+                Err(e) => {
+                    log::warn!("Invalid indication of device {device} at {e}");
+                    continue;
+                }
+            };
+            for (subscription, last) in wanted.iter_mut() {
+                if subscription.device != device {
+                    continue;
+                }
+                let value = tree
+                    .first(subscription.query)
+                    .map(|node| node.value.to_string());
+                if last.as_ref() == Some(&value) {
+                    continue;
+                }
+                *last = Some(value.clone());
+                changes.push(IndicationChange {
+                    subscription: subscription.clone(),
+                    value,
+                });
+            }
+            texts.insert(device, text);
+        }
+        changes
+    }
+
+    /// Whether the cockpit params are wanted. Once they are wanted again,
+    /// they are handed out on the next sample even if they didn't change.
+    pub fn set_cockpit_params(&self, wanted: bool) {
+        let mut sampler = self.sampler.lock().unwrap();
+        sampler.cockpit_params_wanted = wanted;
+        if !wanted {
+            sampler.cockpit_params = None;
+        }
+    }
+
+    /// Lists the cockpit params if they are wanted, and returns them if they
+    /// changed since the last sample. Runs in the export frame.
+    pub fn sample_cockpit_params(&self, lua: &Lua) -> Option<Arc<CockpitParams>> {
+        let mut sampler = self.sampler.lock().unwrap();
+        if !sampler.cockpit_params_wanted {
+            return None;
+        }
+        let text = match list_cockpit_params(lua) {
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!("Sampling the cockpit params failed: {e}");
+                return None;
+            }
+        };
+        if sampler.cockpit_params.as_ref() == Some(&text) {
+            return None;
+        }
+        let params = CockpitParams::parse(&text);
+        sampler.cockpit_params = Some(text);
+        Some(Arc::new(params))
+    }
+
+    /// Replaces the arguments read every frame.
+    pub fn set_arguments(&self, wanted: Vec<i32>) {
+        let mut sampler = self.sampler.lock().unwrap();
+        if sampler.arguments != wanted {
+            sampler.arguments = wanted;
+            sampler.argument_values = None;
+        }
+    }
+
+    /// Reads the wanted arguments, replacing the values that weren't taken
+    /// yet. Runs in the game GUI frame.
+    pub fn sample_arguments(&self, lua: &Lua) {
+        let mut sampler = self.sampler.lock().unwrap();
+        if sampler.arguments.is_empty() {
+            return;
+        }
+        match read_arguments(lua, &sampler.arguments) {
+            Ok(values) => {
+                let values = sampler.arguments.iter().copied().zip(values).collect();
+                sampler.argument_values = Some(Arc::new(values));
+            }
+            Err(e) => log::warn!("Sampling the cockpit arguments failed: {e}"),
+        }
+    }
+
+    /// The arguments read since they were last taken, if any.
+    pub fn take_arguments(&self) -> Option<Arc<ArgumentValues>> {
+        self.sampler.lock().unwrap().argument_values.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IndicationChange, Subscription, Subscriptions};
+    use crate::dcs::ParamValue;
+    use dcs_mock::indication::{format, nested, Element};
+    use dcs_mock::MockDcs;
+
+    #[test]
+    fn test_sample_changes() {
+        let dcs = MockDcs::new().unwrap();
+        let status = Subscription {
+            device: 1,
+            query: "**/Status",
+        };
+        let mode = Subscription {
+            device: 1,
+            query: "Mode",
+        };
+        let show = |status: &str| {
+            let hud = [
+                nested(&["Root", "Window", "Status"], status),
+                Element::new("Mode", "NAV"),
+            ];
+            dcs.state().indications.insert(1, format(&hud));
+        };
+        let change = |subscription: &Subscription, value: Option<&str>| IndicationChange {
+            subscription: subscription.clone(),
+            value: value.map(String::from),
+        };
+
+        let subscriptions = Subscriptions::default();
+        subscriptions.set(vec![status.clone()]);
+        assert_eq!(subscriptions.sample(dcs.lua()), vec![change(&status, None)]);
+
+        show("ALIGN");
+        subscriptions.set(vec![status.clone(), mode.clone()]);
+        assert_eq!(
+            subscriptions.sample(dcs.lua()),
+            vec![change(&status, Some("ALIGN")), change(&mode, Some("NAV"))]
+        );
+        assert!(subscriptions.sample(dcs.lua()).is_empty());
+
+        // only what changed is reported
+        show("ALIGN READY");
+        assert_eq!(
+            subscriptions.sample(dcs.lua()),
+            vec![change(&status, Some("ALIGN READY"))]
+        );
+
+        // a subscription that is dropped and taken again starts over
+        subscriptions.set(vec![mode.clone()]);
+        assert!(subscriptions.sample(dcs.lua()).is_empty());
+        subscriptions.set(vec![mode.clone(), status.clone()]);
+        assert_eq!(
+            subscriptions.sample(dcs.lua()),
+            vec![change(&status, Some("ALIGN READY"))]
+        );
+
+        // a blank display has none of them
+        dcs.state().indications.remove(&1);
+        assert_eq!(
+            subscriptions.sample(dcs.lua()),
+            vec![change(&status, None), change(&mode, None)]
+        );
+    }
+
+    #[test]
+    fn test_sample_cockpit_params() {
+        let dcs = MockDcs::new().unwrap();
+        dcs.state().set_cockpit_param("BASE_SENSOR_CANOPY_POS", 1.0);
+        let subscriptions = Subscriptions::default();
+        assert_eq!(subscriptions.sample_cockpit_params(dcs.lua()), None);
+
+        subscriptions.set_cockpit_params(true);
+        let params = subscriptions.sample_cockpit_params(dcs.lua()).unwrap();
+        let canopy = params.get("BASE_SENSOR_CANOPY_POS");
+        assert_eq!(canopy, Some(&ParamValue::Number(1.0)));
+        assert_eq!(subscriptions.sample_cockpit_params(dcs.lua()), None);
+
+        dcs.state().set_cockpit_param("BASE_SENSOR_CANOPY_POS", 0.5);
+        let params = subscriptions.sample_cockpit_params(dcs.lua()).unwrap();
+        assert_eq!(params.number("BASE_SENSOR_CANOPY_POS").unwrap(), 0.5);
+
+        // wanted again, they are handed out even though they didn't change
+        subscriptions.set_cockpit_params(false);
+        assert_eq!(subscriptions.sample_cockpit_params(dcs.lua()), None);
+        subscriptions.set_cockpit_params(true);
+        assert!(subscriptions.sample_cockpit_params(dcs.lua()).is_some());
+    }
+
+    #[test]
+    fn test_sample_arguments() {
+        let dcs = MockDcs::new().unwrap();
+        dcs.state().set_argument(101, 1.0);
+        let subscriptions = Subscriptions::default();
+        subscriptions.sample_arguments(dcs.lua());
+        assert_eq!(subscriptions.take_arguments(), None);
+
+        subscriptions.set_arguments(vec![101, 102]);
+        subscriptions.sample_arguments(dcs.lua());
+        dcs.state().set_argument(102, 0.5);
+        subscriptions.sample_arguments(dcs.lua());
+        let values = subscriptions.take_arguments().unwrap();
+        assert_eq!(values.get(101), Some(1.0));
+        assert_eq!(values.get(102), Some(0.5));
+        assert_eq!(values.get(103), None);
+        assert_eq!(subscriptions.take_arguments(), None);
+
+        // values read for other arguments are dropped
+        subscriptions.sample_arguments(dcs.lua());
+        subscriptions.set_arguments(vec![103]);
+        assert_eq!(subscriptions.take_arguments(), None);
+    }
+}
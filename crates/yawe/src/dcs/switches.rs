@@ -0,0 +1,212 @@
+//! Cockpit switch tables, loaded from JSON files rather than compiled in.
+//!
+//! Each aircraft has a `<AircraftId name>.json` file in `resources/switches`,
+//! which should be copied into a `switches` directory next to yawe.dll. A
+//! table lists every switch of the aircraft's `Switch` enum by name, so the
+//! order of the file doesn't matter, and is checked against the enum when it
+//! is loaded. If the file next to the DLL is missing or invalid, the problems
+//! are logged and the copy built into the DLL is used instead.
+//!
+//! The file format lives in the `switch_schema` crate, and the
+//! `clickabledata` crate can generate tables from a module's cockpit scripts.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use strum::IntoEnumIterator;
+
+pub use switch_schema::{Info, SwitchDef, SwitchFile, SCHEMA_VERSION};
+
+static TABLE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// An aircraft's `Switch` enum, which a table is checked against.
+pub trait SwitchEnum: Copy + Debug + IntoEnumIterator + Into<&'static str> + 'static {
+    /// Name of the aircraft as in `AircraftId`, which is also the table's
+    /// file name.
+    const AIRCRAFT: &'static str;
+    /// Number of switches, not counting the `NumSwitches` marker.
+    const COUNT: usize;
+    /// The built in copy of the table.
+    const EMBEDDED: &'static str;
+
+    fn index(self) -> usize;
+
+    /// The aircraft's loaded table.
+    fn table() -> &'static SwitchTable<Self>;
+}
+
+/// A validated switch table, indexed by `Switch`.
+#[derive(Debug)]
+pub struct SwitchTable<S> {
+    switches: Vec<SwitchDef>,
+    _switch: std::marker::PhantomData<S>,
+}
+
+impl<S: SwitchEnum> SwitchTable<S> {
+    /// Loads the table from the directory given to [`set_table_dir()`],
+    /// falling back to the built in copy.
+    pub fn load() -> Self {
+        if let Some(dir) = TABLE_DIR.get() {
+            let path = dir.join(format!("{}.json", S::AIRCRAFT));
+            match Self::load_file(&path) {
+                Ok(table) => {
+                    log::info!("Loaded switch table {}", path.display());
+                    return table;
+                }
+                Err(e) => {
+                    log::error!("{e}");
+                    log::error!("Using the built in switch table for {}", S::AIRCRAFT);
+                }
+            }
+        }
+        match Self::parse(S::EMBEDDED) {
+            Ok(table) => table,
+            Err(e) => panic!("Built in switch table is invalid: {e}"),
+        }
+    }
+
+    pub fn load_file(path: &Path) -> Result<Self, crate::Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| crate::Error::ParseError(format!("Can't read {}: {e}", path.display())))?;
+        Self::parse(&text).map_err(|e| match e {
+            crate::Error::ParseError(s) => {
+                crate::Error::ParseError(format!("In {}: {s}", path.display()))
+            }
+            e => e,
+        })
+    }
+
+    /// Parses and validates a table, reporting every problem found rather
+    /// than just the first one.
+    pub fn parse(text: &str) -> Result<Self, crate::Error> {
+        let file: SwitchFile =
+            serde_json::from_str(text).map_err(|e| crate::Error::ParseError(e.to_string()))?;
+        Self::from_file(file)
+    }
+
+    pub fn from_file(file: SwitchFile) -> Result<Self, crate::Error> {
+        let mut problems = vec![];
+        if file.version != SCHEMA_VERSION {
+            problems.push(format!(
+                "version is {}, expected {SCHEMA_VERSION}",
+                file.version
+            ));
+        }
+        if file.aircraft != S::AIRCRAFT {
+            problems.push(format!(
+                "table is for {}, expected {}",
+                file.aircraft,
+                S::AIRCRAFT
+            ));
+        }
+
+        let mut by_name: HashMap<&str, &SwitchDef> = HashMap::new();
+        for def in &file.switches {
+            if by_name.insert(&def.name, def).is_some() {
+                problems.push(format!("{} is defined more than once", def.name));
+            }
+            for (position, value) in &def.positions {
+                if !(-1.0..=1.0).contains(value) {
+                    problems.push(format!(
+                        "{} position {position} is {value}, outside of -1 to 1",
+                        def.name
+                    ));
+                }
+            }
+            if def.info.argument() < 0 {
+                problems.push(format!("{} has a negative argument", def.name));
+            }
+            if !matches!(def.info, Info::FloatValue { .. }) && def.info.device_id() <= 0 {
+                problems.push(format!("{} needs a device id", def.name));
+            }
+        }
+
+        let mut switches = Vec::with_capacity(S::COUNT);
+        for switch in S::iter().take(S::COUNT) {
+            let name: &'static str = switch.into();
+            match by_name.remove(name) {
+                Some(def) => switches.push(def.clone()),
+                None => problems.push(format!("{name} is missing")),
+            }
+        }
+        let mut unknown: Vec<&str> = by_name.into_keys().collect();
+        unknown.sort();
+        for name in unknown {
+            problems.push(format!("{name} is not a {} switch", S::AIRCRAFT));
+        }
+
+        if !problems.is_empty() {
+            return Err(crate::Error::ParseError(format!(
+                "invalid {} switch table:\n    {}",
+                S::AIRCRAFT,
+                problems.join("\n    ")
+            )));
+        }
+        Ok(Self {
+            switches,
+            _switch: std::marker::PhantomData,
+        })
+    }
+
+    /// Every switch, in the order of the enum.
+    pub fn defs(&self) -> &[SwitchDef] {
+        &self.switches
+    }
+
+    pub fn get(&self, s: S) -> &SwitchDef {
+        &self.switches[s.index()]
+    }
+
+    pub fn info(&self, s: S) -> &Info {
+        &self.get(s).info
+    }
+
+    pub fn position(&self, s: S, name: &str) -> Option<f32> {
+        self.get(s).positions.get(name).copied()
+    }
+}
+
+/// Where to look for switch tables, normally the `switches` directory next to
+/// the DLL. Only the first call has any effect.
+pub fn set_table_dir(dir: PathBuf) {
+    let _ = TABLE_DIR.set(dir);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SwitchTable, SCHEMA_VERSION};
+    use crate::dcs::{f16c50, mig21bis};
+
+    #[test]
+    fn test_embedded_tables_are_valid() {
+        use super::SwitchEnum;
+        SwitchTable::<f16c50::Switch>::parse(f16c50::Switch::EMBEDDED).unwrap();
+        SwitchTable::<mig21bis::Switch>::parse(mig21bis::Switch::EMBEDDED).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_table_reports_every_problem() {
+        let text = format!(
+            r#"{{
+                "version": {},
+                "aircraft": "F-16C_50",
+                "switches": [
+                    {{"name": "MainPower", "kind": "MultiToggle", "device_id": 3, "command": 3001, "argument": 510}},
+                    {{"name": "MainPower", "kind": "Toggle", "device_id": 3, "command": 3001, "argument": 510}},
+                    {{"name": "Warp", "kind": "Toggle", "device_id": 3, "command": 3001, "argument": 1}},
+                    {{"name": "InsKnob", "kind": "MultiToggle", "device_id": 14, "command": 3001, "argument": 719, "positions": {{"NAV": 3.0}}}}
+                ]
+            }}"#,
+            SCHEMA_VERSION + 1
+        );
+        let Err(crate::Error::ParseError(e)) = SwitchTable::<f16c50::Switch>::parse(&text) else {
+            panic!("table should not be valid");
+        };
+        assert!(e.contains("version is"));
+        assert!(e.contains("MainPower is defined more than once"));
+        assert!(e.contains("Warp is not a F-16C_50 switch"));
+        assert!(e.contains("InsKnob position NAV is 3"));
+        assert!(e.contains("Jfs is missing"));
+    }
+}
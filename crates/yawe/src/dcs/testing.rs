@@ -0,0 +1,136 @@
+//! Runs aircraft FSMs end to end against a [`MockDcs`], the same way the app
+//! thread runs them against DCS.
+
+use crate::app::FsmMessage;
+use crate::clock::ManualClock;
+use crate::dcs::subscriptions::{IndicationChange, Subscriptions};
+use crate::dcs::{AircraftFsm, ArgumentValues, CockpitParams};
+use crate::gui::TxHandle;
+use dcs_mock::{MockDcs, Model};
+use mlua::Lua;
+use offload::{PackagedTask, TaskSender};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+
+/// What the FSM thread is given each frame.
+struct Tick {
+    msg: FsmMessage,
+    sim_time: f32,
+    changes: Vec<IndicationChange>,
+    cockpit_params: Option<Arc<CockpitParams>>,
+    arguments: Option<Arc<ArgumentValues>>,
+}
+
+pub struct Harness {
+    pub dcs: MockDcs,
+    to_gamegui: TaskSender<Lua>,
+    to_export: TaskSender<Lua>,
+    rx_gamegui: Receiver<PackagedTask<Lua>>,
+    rx_export: Receiver<PackagedTask<Lua>>,
+}
+
+impl Harness {
+    pub fn new(aircraft_name: &str) -> Self {
+        Self::with_dcs(MockDcs::with_aircraft(aircraft_name).unwrap())
+    }
+
+    /// A harness whose cockpit is simulated by `model`.
+    pub fn with_model(aircraft_name: &str, model: Box<dyn Model>) -> Self {
+        Self::with_dcs(MockDcs::with_model(aircraft_name, model).unwrap())
+    }
+
+    fn with_dcs(dcs: MockDcs) -> Self {
+        let (to_gamegui, rx_gamegui) = TaskSender::new();
+        let (to_export, rx_export) = TaskSender::new();
+        Self {
+            dcs,
+            to_gamegui,
+            to_export,
+            rx_gamegui,
+            rx_export,
+        }
+    }
+
+    /// Runs the FSM built by `make_fsm` on its own thread, ticking it once per
+    /// simulated frame of `dt` seconds. `first` is delivered on the first
+    /// frame, `FsmMessage::None` on every later one, each after the
+    /// indication changes, cockpit params and arguments sampled for the
+    /// frame. Stops once `stop` holds
+    /// after a tick or the model time passes `max_time`, and returns the FSM.
+    pub fn run<F, M, P>(&self, make_fsm: M, first: FsmMessage, dt: f32, max_time: f32, stop: P) -> F
+    where
+        F: AircraftFsm + Send + 'static,
+        M: FnOnce(TaskSender<Lua>, TaskSender<Lua>, TxHandle) -> F + Send + 'static,
+        P: Fn(&F) -> bool + Send + 'static,
+    {
+        let (tx_tick, rx_tick) = channel::<Tick>();
+        let (tx_done, rx_done) = channel::<bool>();
+        let to_gamegui = self.to_gamegui.clone();
+        let to_export = self.to_export.clone();
+        let subscriptions = Subscriptions::default();
+        let fsm_subscriptions = subscriptions.clone();
+
+        let thread = std::thread::spawn(move || {
+            let mut fsm = make_fsm(to_gamegui, to_export, TxHandle::detached());
+            let clock = ManualClock::default();
+            while let Ok(tick) = rx_tick.recv() {
+                clock.set(tick.sim_time);
+                for change in &tick.changes {
+                    fsm.on_indication(change);
+                }
+                if let Some(params) = &tick.cockpit_params {
+                    fsm.on_cockpit_params(params);
+                }
+                if let Some(values) = &tick.arguments {
+                    fsm.on_arguments(values);
+                }
+                fsm.run_fsm(tick.msg, &clock);
+                fsm_subscriptions.set(fsm.subscriptions());
+                fsm_subscriptions.set_cockpit_params(fsm.wants_cockpit_params());
+                fsm_subscriptions.set_arguments(fsm.wanted_arguments());
+                if tx_done.send(stop(&fsm)).is_err() {
+                    break;
+                }
+            }
+            fsm
+        });
+
+        let mut msg = first;
+        loop {
+            let now = self.dcs.state().model_time;
+            subscriptions.sample_arguments(self.dcs.lua());
+            let tick = Tick {
+                msg,
+                sim_time: now,
+                changes: subscriptions.sample(self.dcs.lua()),
+                cockpit_params: subscriptions.sample_cockpit_params(self.dcs.lua()),
+                arguments: subscriptions.take_arguments(),
+            };
+            if tx_tick.send(tick).is_err() {
+                break;
+            }
+            msg = FsmMessage::None;
+            let finished = loop {
+                self.dcs.run_pending(&self.rx_gamegui);
+                self.dcs.run_pending(&self.rx_export);
+                match rx_done.try_recv() {
+                    Ok(finished) => break finished,
+                    Err(TryRecvError::Empty) => std::thread::yield_now(),
+                    // the FSM thread panicked, `join()` below reports it
+                    Err(TryRecvError::Disconnected) => break true,
+                }
+            };
+            if finished || now >= max_time {
+                break;
+            }
+            self.dcs.advance(dt);
+        }
+        drop(tx_tick);
+        let fsm = thread.join().unwrap();
+
+        // jobs the FSM sent without waiting on them
+        self.dcs.run_pending(&self.rx_gamegui);
+        self.dcs.run_pending(&self.rx_export);
+        fsm
+    }
+}
@@ -1,22 +1,13 @@
 use crate::app;
 use crate::dcs;
+use crate::dcs::aircraft::{self, AircraftModule, AircraftWidget, WidgetContext};
+use crate::platform;
 use egui_backend::{egui, BackendConfig, GfxBackend, UserApp, WindowBackend};
 use egui_render_glow::GlowBackend;
-use egui_window_glfw_passthrough::glfw;
-use egui_window_glfw_passthrough::glfw::Context;
 use egui_window_glfw_passthrough::GlfwBackend;
 use mlua::Lua;
 use offload::TaskSender;
 use std::sync::mpsc::{self, Receiver, Sender};
-use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, POINTS, RECT, WPARAM};
-use windows::Win32::Graphics::Gdi::ScreenToClient;
-use windows::Win32::UI::WindowsAndMessaging::HTRIGHT;
-use windows::Win32::UI::WindowsAndMessaging::{
-    GetClientRect, GetWindowLongPtrW, GetWindowRect, SetWindowLongPtrW, SetWindowPos, GWLP_WNDPROC,
-    GWL_STYLE, HTBOTTOM, HTBOTTOMLEFT, HTBOTTOMRIGHT, HTCAPTION, HTLEFT, HTTOP, HTTOPLEFT,
-    HTTOPRIGHT, NCCALCSIZE_PARAMS, SWP_FRAMECHANGED, SWP_NOMOVE, WM_NCACTIVATE, WM_NCCALCSIZE,
-    WM_NCHITTEST, WM_NCPAINT, WNDPROC, WS_CAPTION, WS_THICKFRAME,
-};
 struct Gui {
     rx: Receiver<Message>,
     tx: Sender<app::AppMessage>,
@@ -31,120 +22,15 @@ struct Gui {
     is_on_top: bool,
     debug_widget_visible: bool,
     startup_text: String,
+    startup_failed: bool,
     paused: bool,
-    aircraft_state: dcs::AircraftState,
+    /// A request held by the interlocks until the user confirms it, and why.
+    confirmation: Option<(app::FsmMessage, String)>,
+    aircraft: Option<&'static dyn AircraftModule>,
+    aircraft_widget: Option<Box<dyn AircraftWidget>>,
     app_runner: TaskSender<(TaskSender<Lua>, TaskSender<Lua>)>,
 }
 
-// The following hackery is based on the thread:
-// https://reddit.com/r/opengl/comments/13x3sw0/custom_title_bar_with_glfw/
-
-// this needs to be global (I don't think there is any other way to get it into
-// an extern "C" function)
-static mut GLFW_PROC: WNDPROC = None;
-
-fn hit_test(
-    mouse_pos: &POINT,
-    window_rect: &RECT,
-    hwnd: HWND,
-    msg: u32,
-    wparam: WPARAM,
-    lparam: LPARAM,
-) -> LRESULT {
-    let border_width = 8;
-    let caption_height = 32;
-    if mouse_pos.y >= window_rect.bottom - border_width {
-        if mouse_pos.x <= border_width {
-            return LRESULT(HTBOTTOMLEFT as isize);
-        } else if mouse_pos.x >= window_rect.right - border_width {
-            return LRESULT(HTBOTTOMRIGHT as isize);
-        } else {
-            return LRESULT(HTBOTTOM as isize);
-        }
-    } else if mouse_pos.y <= border_width {
-        if mouse_pos.x <= border_width {
-            return LRESULT(HTTOPLEFT as isize);
-        } else if mouse_pos.x >= window_rect.right - border_width {
-            return LRESULT(HTTOPRIGHT as isize);
-        } else {
-            return LRESULT(HTTOP as isize);
-        }
-    } else if mouse_pos.y <= border_width + caption_height {
-        return LRESULT(HTCAPTION as isize);
-    } else if mouse_pos.x <= border_width {
-        return LRESULT(HTLEFT as isize);
-    } else if mouse_pos.x >= window_rect.right - border_width {
-        return LRESULT(HTRIGHT as isize);
-    }
-    unsafe { GLFW_PROC.unwrap()(hwnd, msg, wparam, lparam) }
-}
-
-unsafe extern "system" fn modified_proc(
-    hwnd: HWND,
-    msg: u32,
-    wparam: WPARAM,
-    lparam: LPARAM,
-) -> LRESULT {
-    match msg {
-        WM_NCCALCSIZE => {
-            if wparam.0 == 1 && lparam.0 != 0 {
-                let params = lparam.0 as *mut NCCALCSIZE_PARAMS;
-                (*params).rgrc[0].top += 1;
-                (*params).rgrc[0].right -= 1;
-                (*params).rgrc[0].bottom -= 1;
-                (*params).rgrc[0].left += 1;
-            }
-            LRESULT(0)
-        }
-        WM_NCHITTEST => {
-            let x: i16 = (lparam.0 & 0xFFFF) as i16;
-            let y: i16 = ((lparam.0 >> 16) & 0xFFFF) as i16;
-            let mouse_pos = POINTS { x, y };
-            let mut client_mouse_pos = POINT {
-                x: mouse_pos.x as i32,
-                y: mouse_pos.y as i32,
-            };
-            ScreenToClient(hwnd, &mut client_mouse_pos as *mut POINT);
-            let mut window_rect = RECT::default();
-            GetClientRect(hwnd, &mut window_rect as *mut RECT);
-            let result = hit_test(&client_mouse_pos, &window_rect, hwnd, msg, wparam, lparam);
-            result
-        }
-        WM_NCPAINT => LRESULT(0),
-        WM_NCACTIVATE => LRESULT(0),
-        _ => GLFW_PROC.unwrap()(hwnd, msg, wparam, lparam),
-    }
-}
-
-fn disable_titlebar(window: &glfw::Window) {
-    let window_handle = unsafe { HWND(glfw::ffi::glfwGetWin32Window(window.window_ptr()) as _) };
-    let mut style = unsafe { GetWindowLongPtrW(window_handle, GWL_STYLE) };
-    style |= WS_THICKFRAME.0 as isize;
-    style &= !WS_CAPTION.0 as isize;
-    unsafe { SetWindowLongPtrW(window_handle, GWL_STYLE, style) };
-    let mut rect: RECT = RECT::default();
-    unsafe { GetWindowRect(window_handle, &mut rect) };
-    let width = rect.right - rect.left;
-    let height = rect.bottom - rect.top;
-
-    let raw = unsafe { GetWindowLongPtrW(window_handle, GWLP_WNDPROC) };
-    unsafe {
-        GLFW_PROC = Some(std::mem::transmute(raw));
-    };
-    unsafe { SetWindowLongPtrW(window_handle, GWLP_WNDPROC, modified_proc as isize) };
-    unsafe {
-        SetWindowPos(
-            window_handle,
-            HWND(0 as isize),
-            0,
-            0,
-            width,
-            height,
-            SWP_FRAMECHANGED | SWP_NOMOVE,
-        )
-    };
-}
-
 impl Gui {
     pub fn new(
         rx: Receiver<Message>,
@@ -155,9 +41,9 @@ impl Gui {
         app_runner: TaskSender<(TaskSender<Lua>, TaskSender<Lua>)>,
     ) -> Self {
         let mut glfw_backend = GlfwBackend::new(Default::default(), BackendConfig::default());
-        glfw_backend.window.set_decorated(false);
+        glfw_backend.window.set_decorated(platform::DECORATED);
         glfw_backend.window.set_title("DCS YAWE");
-        disable_titlebar(&glfw_backend.window);
+        platform::customize_window(&glfw_backend.window);
 
         // creating gfx backend. It uses Window backend to load things like fn pointers
         // or window handle for swapchain etc.. behind the scenes.
@@ -172,16 +58,15 @@ impl Gui {
             glfw_backend: glfw_backend,
             glow_backend: glow_backend,
             egui_context: context,
-            switch_vals: {
-                let mut v = Vec::with_capacity(dcs::mig21bis::Switch::NumSwitches as usize);
-                v.resize(dcs::mig21bis::Switch::NumSwitches as usize, String::new());
-                v
-            },
+            switch_vals: vec![],
             is_on_top: true,
             debug_widget_visible: false,
             startup_text: String::default(),
+            startup_failed: false,
             paused: false,
-            aircraft_state: dcs::AircraftState::Unknown("".to_string()),
+            confirmation: None,
+            aircraft: None,
+            aircraft_widget: None,
             app_runner: app_runner,
         }
     }
@@ -192,23 +77,43 @@ impl Gui {
 
     fn make_aircraft_specific_widget(&mut self, ui: &mut egui::Ui) {
         ui.label("Aircraft options");
-        match &mut self.aircraft_state {
-            dcs::AircraftState::MiG_21Bis => {
-                dcs::mig21bis::make_debug_widget(ui, &mut self.switch_vals, &self.to_dcs_gamegui)
-            }
-            dcs::AircraftState::F_16C_50(gui) => gui.make_widget(ui, &self.app_runner),
-            _ => (),
-        };
+        if let Some(widget) = &mut self.aircraft_widget {
+            let ctx = WidgetContext {
+                app_runner: &self.app_runner,
+            };
+            widget.show(ui, &ctx);
+        }
     }
 
     fn make_debug_widget(&mut self, ui: &mut egui::Ui) {
         ui.label("Debug switches:");
-        match self.aircraft_type {
-            dcs::AircraftId::MiG_21Bis => {
-                dcs::mig21bis::make_debug_widget(ui, &mut self.switch_vals, &self.to_dcs_gamegui)
-            }
-            _ => (),
+        let Some(switches) = self.aircraft.and_then(|module| module.switches()) else {
+            return;
         };
+        let tx = &self.to_dcs_gamegui;
+        egui::Grid::new("debug_grid").show(ui, |ui| {
+            for (def, val) in switches.iter().zip(self.switch_vals.iter_mut()) {
+                let info = def.info;
+                ui.label(def.name.as_str());
+                if ui.button("Set").clicked() {
+                    if let Ok(state) = val.parse::<f32>() {
+                        let _ = tx.send(move |lua| {
+                            dcs::perform_click(lua, info.device_id(), info.command(), state)
+                        });
+                    }
+                }
+                if ui.button("Get").clicked() {
+                    let result = tx
+                        .send(move |lua| dcs::get_switch_state(lua, 0, info.argument()))
+                        .wait();
+                    if let Ok(state) = result {
+                        val.replace_range(.., state.unwrap_or_default().to_string().as_str());
+                    }
+                }
+                ui.add(egui::TextEdit::singleline(val));
+                ui.end_row();
+            }
+        });
     }
 }
 
@@ -241,57 +146,100 @@ impl UserApp for Gui {
                     return;
                 }
                 Message::UpdateOwnship(kind) => {
-                    let num_switches = match &kind {
-                        dcs::AircraftId::MiG_21Bis => dcs::mig21bis::Switch::NumSwitches as usize,
-                        dcs::AircraftId::F_16C_50 => dcs::f16c50::Switch::NumSwitches as usize,
-                        _ => 0 as usize,
-                    };
-                    let mut v = Vec::with_capacity(num_switches as usize);
-                    v.resize(num_switches as usize, String::new());
-                    self.switch_vals = v;
-                    self.aircraft_state = match &kind {
-                        dcs::AircraftId::MiG_21Bis => dcs::AircraftState::MiG_21Bis,
-                        dcs::AircraftId::F_16C_50 => {
-                            dcs::AircraftState::F_16C_50(Default::default())
-                        }
-                        _ => dcs::AircraftState::Unknown("".to_string()),
-                    };
+                    self.aircraft = aircraft::module(&kind);
+                    let num_switches = self
+                        .aircraft
+                        .and_then(|module| module.switches())
+                        .map_or(0, |switches| switches.len());
+                    self.switch_vals = vec![String::new(); num_switches];
+                    self.aircraft_widget = self.aircraft.and_then(|module| module.make_widget());
                     self.aircraft_type = kind;
                 }
-                Message::UpdateStartupProgress(progress) => self.startup_progress = progress,
-                Message::UpdateStartupText(s) => self.startup_text = s,
+                Message::UpdateStartupProgress(progress) => {
+                    self.startup_progress = progress;
+                    self.startup_failed = false;
+                }
+                Message::UpdateStartupText(s) => {
+                    self.startup_text = s;
+                    self.startup_failed = false;
+                }
+                Message::StartupFailed(s) => {
+                    self.startup_text = s;
+                    self.startup_failed = true;
+                }
+                Message::Confirm(request, reason) => self.confirmation = Some((request, reason)),
                 Message::Paused => self.paused = true,
                 Message::Unpaused => self.paused = false,
             }
         }
         self.glfw_backend.window.set_floating(self.is_on_top);
-        self.glfw_backend.window.set_decorated(false);
+        self.glfw_backend.window.set_decorated(platform::DECORATED);
 
         egui::CentralPanel::default().show(&ctx, |ui| {
             ui.heading("DCS YAWE");
             ui.separator();
             ui.horizontal(|ui| {
                 ui.label("Aircraft type:");
-                let name = aircraft_display_name(&self.aircraft_type);
+                let name = aircraft::display_name(&self.aircraft_type);
                 ui.label(name.as_str());
             });
             ui.separator();
             ui.horizontal(|ui| {
                 ui.label("Autostart");
                 let enabled = !self.paused;
-                let start_button = ui.add_enabled(enabled, egui::Button::new("Start"));
+                let capabilities = self
+                    .aircraft
+                    .map(|module| module.capabilities())
+                    .unwrap_or_default();
+                let start_button =
+                    ui.add_enabled(enabled && capabilities.startup, egui::Button::new("Start"));
                 if start_button.clicked() {
                     let _ = self
                         .tx
                         .send(app::AppMessage::FsmEvent(app::FsmMessage::StartupAircraft));
                 }
-
-                ui.add(
-                    egui::ProgressBar::new(self.startup_progress)
-                        .text(self.startup_text.as_str())
-                        .animate(self.startup_progress > 0.0),
+                let shutdown_button = ui.add_enabled(
+                    enabled && capabilities.shutdown,
+                    egui::Button::new("Shutdown"),
                 );
+                if shutdown_button.clicked() {
+                    let _ = self
+                        .tx
+                        .send(app::AppMessage::FsmEvent(app::FsmMessage::ShutdownAircraft));
+                }
+                let running = self.startup_progress > 0.0
+                    && self.startup_progress < 1.0
+                    && !self.startup_failed;
+                let stop_button = ui.add_enabled(enabled && running, egui::Button::new("Stop"));
+                if stop_button.clicked() {
+                    let _ = self.tx.send(app::AppMessage::FsmEvent(
+                        app::FsmMessage::InterruptAircraftStart,
+                    ));
+                }
+
+                let mut progress_bar = egui::ProgressBar::new(self.startup_progress)
+                    .text(self.startup_text.as_str())
+                    .animate(self.startup_progress > 0.0 && !self.startup_failed);
+                if self.startup_failed {
+                    progress_bar = progress_bar.fill(egui::Color32::DARK_RED);
+                }
+                ui.add(progress_bar);
             });
+            if let Some((request, reason)) = &self.confirmation {
+                let request = *request;
+                let mut answered = false;
+                ui.horizontal(|ui| {
+                    ui.colored_label(egui::Color32::YELLOW, format!("Careful, {reason}."));
+                    if ui.button("Go ahead").clicked() {
+                        let _ = self.tx.send(app::AppMessage::Confirmed(request));
+                        answered = true;
+                    }
+                    answered |= ui.button("Cancel").clicked();
+                });
+                if answered {
+                    self.confirmation = None;
+                }
+            }
             ui.separator();
             ui.horizontal(|ui| {
                 ui.checkbox(&mut self.debug_widget_visible, "Debug panel");
@@ -324,38 +272,13 @@ fn do_gui(
     log::info!("Gui closed");
 }
 
-fn aircraft_display_name(kind: &dcs::AircraftId) -> String {
-    match kind {
-        dcs::AircraftId::F_16C_50 => String::from("F-16C block 50"),
-        dcs::AircraftId::A_10C => String::from("A-10C"),
-        dcs::AircraftId::A_10C_2 => String::from("A-10C II"),
-        dcs::AircraftId::AH_64D_BLK_II => String::from("AH-64D Apache"),
-        dcs::AircraftId::AJS37 => String::from("AJS37 Viggen"),
-        dcs::AircraftId::AV8BNA => String::from("AV8BNA Harrier"),
-        dcs::AircraftId::F_14B => String::from("F-14B Tomcat"),
-        dcs::AircraftId::F_15ESE => String::from("F-15E Strike Eagle"),
-        dcs::AircraftId::F_15ESE_WSO => String::from("F-15E Strike Eagle (WSO)"),
-        dcs::AircraftId::FA_18C_hornet => String::from("F/A-18C Hornet"),
-        dcs::AircraftId::M_2000C => String::from("Mirage 2000C"),
-        dcs::AircraftId::Mi_24P => String::from("Mi-24P \"Hind E\""),
-        dcs::AircraftId::Mi_8MT => String::from("Mi-8MT \"Hip\""),
-        dcs::AircraftId::Mi_8MT_Copilot => String::from("Mi-8MT \"Hip\" (Copilot)"),
-        dcs::AircraftId::Mi_8MT_FO => String::from("Mi-8MT \"Hip\" (First Officer)"),
-        dcs::AircraftId::MiG_21Bis => String::from("MiG-21Bis"),
-        dcs::AircraftId::SA342L => String::from("SA342L Gazelle"),
-        dcs::AircraftId::Su_25 => String::from("Su-25 \"Frogfoot\""),
-        dcs::AircraftId::Su_25T => String::from("Su-25T \"Frogfoot\""),
-        dcs::AircraftId::UH_1H => String::from("UH-1H Huey"),
-        // TODO: this is a hack
-        dcs::AircraftId::Unknown(s) => s.to_string(),
-    }
-}
-
 enum Message {
     Stop,
     UpdateStartupProgress(f32),
     UpdateOwnship(dcs::AircraftId),
     UpdateStartupText(String),
+    StartupFailed(String),
+    Confirm(app::FsmMessage, String),
     Paused,
     Unpaused,
 }
@@ -369,6 +292,16 @@ pub struct TxHandle {
 }
 
 impl TxHandle {
+    /// A handle that isn't connected to any GUI; everything sent to it is
+    /// dropped.
+    pub fn detached() -> Self {
+        let (tx, _) = mpsc::channel::<Message>();
+        TxHandle {
+            context: egui::Context::default(),
+            tx,
+        }
+    }
+
     pub fn set_ownship_type(&self, kind: dcs::AircraftId) {
         let _ = self.tx.send(Message::UpdateOwnship(kind));
         self.context.request_repaint();
@@ -379,8 +312,25 @@ impl TxHandle {
         self.context.request_repaint();
     }
 
-    pub fn set_startup_text(&self, text: &'static str) {
-        let _ = self.tx.send(Message::UpdateStartupText(String::from(text)));
+    pub fn set_startup_text(&self, text: impl Into<String>) {
+        let _ = self.tx.send(Message::UpdateStartupText(text.into()));
+        self.context.request_repaint();
+    }
+
+    /// Shows why a procedure gave up in place of the progress text.
+    pub fn set_startup_failed(&self, text: String) {
+        let _ = self.tx.send(Message::StartupFailed(text));
+        self.context.request_repaint();
+    }
+
+    /// Shows why the interlocks turned a request down.
+    pub fn set_refused(&self, reason: &str) {
+        self.set_startup_failed(format!("REFUSED: {reason}"));
+    }
+
+    /// Asks the user whether to go ahead with `request` anyway.
+    pub fn ask_confirmation(&self, request: app::FsmMessage, reason: String) {
+        let _ = self.tx.send(Message::Confirm(request, reason));
         self.context.request_repaint();
     }
 
@@ -442,7 +392,7 @@ impl Handle {
     }
 
     pub fn set_ownship_type(&self, kind: dcs::AircraftId) {
-        let _ = self.tx_handle().set_ownship_type(kind);
+        self.tx_handle().set_ownship_type(kind);
     }
 
     pub fn _set_startup_progress(&self, progress: f32) {
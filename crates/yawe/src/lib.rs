@@ -1,10 +1,17 @@
+use config::abi;
 use mlua::prelude::{LuaFunction, LuaTable};
-use mlua::Lua;
+use mlua::{lua_State, Lua};
+use std::os::raw::c_int;
+use std::panic::AssertUnwindSafe;
 use std::string::String;
+use std::sync::atomic::{AtomicBool, Ordering};
 mod app;
+mod clock;
 mod dcs;
 mod gui;
 mod logging;
+mod platform;
+mod wrap;
 
 #[derive(Debug, Clone)]
 pub enum Error {
@@ -14,6 +21,17 @@ pub enum Error {
     LuaError(mlua::Error),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IndexError => write!(f, "index error"),
+            Error::CommError => write!(f, "couldn't communicate with the DCS thread"),
+            Error::ParseError(s) => write!(f, "{s}"),
+            Error::LuaError(e) => write!(f, "Lua error: {e}"),
+        }
+    }
+}
+
 struct LibState {
     main_app: app::App,
 }
@@ -33,44 +51,185 @@ fn get_writedir(lua: &Lua) -> String {
     get_writedir.call::<_, String>(()).unwrap()
 }
 
-#[no_mangle]
-pub fn start(lua: &Lua, mut config: config::Config) -> i32 {
-    config.write_dir = get_writedir(lua);
-    logging::init(&config);
-    unsafe {
-        LIB_STATE = Some(LibState {
-            main_app: app::App::new(),
-        });
+/// Set once an entry point has panicked. The app may be in any state after
+/// that, so only `stop` still runs, and clears it once the app is gone.
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Runs the body of an entry point unless the library is disabled.
+fn guard(name: &str, body: impl FnOnce() -> c_int) -> c_int {
+    if DISABLED.load(Ordering::SeqCst) {
+        return -1;
     }
-    get_lib_state().main_app.on_start(lua)
+    contain(name, body)
+}
+
+/// Keeps a panic in the body of an entry point from unwinding into DCS, which
+/// would take the game down. The panic hook has already logged it with a
+/// backtrace by the time it is caught; the library is disabled until it is
+/// stopped, and DCS gets a negative status.
+fn contain(name: &str, body: impl FnOnce() -> c_int) -> c_int {
+    match std::panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(_) => {
+            DISABLED.store(true, Ordering::SeqCst);
+            log::error!("`{name}` panicked, yawe is disabled until it is stopped");
+            -1
+        }
+    }
+}
+
+/// Wraps the raw Lua state handed over by the shim. It stays valid for the
+/// duration of the call.
+fn wrap(state: *mut lua_State) -> Lua {
+    unsafe { wrap::wrap(state) }
+}
+
+/// Removes what the wrappers of the state left in it, see [`wrap::detach`].
+fn release(state: *mut lua_State) -> bool {
+    unsafe { wrap::detach(state) }
+}
+
+#[no_mangle]
+pub extern "C" fn yawe_abi_version() -> u32 {
+    abi::VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn start(state: *mut lua_State, config: abi::Bytes) -> c_int {
+    guard("start", || {
+        let lua = wrap(state);
+        let Ok(mut config) = abi::decode_config(unsafe { config.as_slice() }) else {
+            return -1;
+        };
+        config.write_dir = get_writedir(&lua);
+        logging::init(&config);
+        dcs::script::set_script_dir(std::path::Path::new(&config.lua_path).join("aircraft"));
+        dcs::load_switch_tables(&config.dll_path);
+        let recorder = if config.record_lua {
+            dcs::recording::Recorder::create(&config.write_dir)
+                .map_err(|e| log::warn!("Failed to start recording Lua traffic: {e}"))
+                .ok()
+        } else {
+            None
+        };
+        unsafe {
+            LIB_STATE = Some(LibState {
+                main_app: app::App::new(recorder),
+            });
+        }
+        get_lib_state().main_app.on_start(&lua, &config.aircraft)
+    })
 }
 
 #[no_mangle]
-pub fn on_frame(lua: &Lua) -> i32 {
-    get_lib_state().main_app.on_frame(&lua)
+pub extern "C" fn on_frame(state: *mut lua_State) -> c_int {
+    guard("on_frame", || {
+        get_lib_state().main_app.on_frame(&wrap(state))
+    })
 }
 
 #[no_mangle]
-pub fn on_frame_export(lua: &Lua) -> i32 {
-    get_lib_state().main_app.on_frame_export(&lua)
+pub extern "C" fn on_frame_export(state: *mut lua_State) -> c_int {
+    guard("on_frame_export", || {
+        get_lib_state().main_app.on_frame_export(&wrap(state))
+    })
 }
 
 #[no_mangle]
-pub fn on_simulation_pause(lua: &Lua) -> i32 {
-    get_lib_state().main_app.on_simulation_pause(&lua)
+pub extern "C" fn on_simulation_pause(state: *mut lua_State) -> c_int {
+    guard("on_simulation_pause", || {
+        get_lib_state().main_app.on_simulation_pause(&wrap(state))
+    })
 }
 
 #[no_mangle]
-pub fn on_simulation_resume(lua: &Lua) -> i32 {
-    get_lib_state().main_app.on_simulation_resume(&lua)
+pub extern "C" fn on_simulation_resume(state: *mut lua_State) -> c_int {
+    guard("on_simulation_resume", || {
+        get_lib_state().main_app.on_simulation_resume(&wrap(state))
+    })
 }
 
 #[no_mangle]
-pub fn stop(_lua: &Lua) -> i32 {
-    log::info!("stop!!");
-    get_lib_state().main_app.stop();
-    unsafe {
-        LIB_STATE = None;
+pub extern "C" fn on_player_change_slot(state: *mut lua_State, player_id: c_int) -> c_int {
+    guard("on_player_change_slot", || {
+        get_lib_state()
+            .main_app
+            .on_player_change_slot(&wrap(state), player_id)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn on_game_event(state: *mut lua_State, event: abi::Bytes) -> c_int {
+    guard("on_game_event", || {
+        let event: abi::GameEvent = match serde_json::from_slice(unsafe { event.as_slice() }) {
+            Ok(event) => event,
+            Err(e) => {
+                log::warn!("Failed to decode a game event: {e}");
+                return -1;
+            }
+        };
+        get_lib_state().main_app.on_game_event(&wrap(state), &event)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn on_simulation_stop(state: *mut lua_State) -> c_int {
+    guard("on_simulation_stop", || {
+        get_lib_state().main_app.on_simulation_stop(&wrap(state))
+    })
+}
+
+/// Runs even once the library is disabled, to close the GUI and stop the
+/// threads if they can be, and enables it again for the next `start`.
+#[no_mangle]
+pub extern "C" fn stop(_state: *mut lua_State) -> c_int {
+    contain("stop", || {
+        log::info!("stop!!");
+        let result = match unsafe { LIB_STATE.take() } {
+            Some(lib_state) => {
+                let mut main_app = lib_state.main_app;
+                main_app.stop();
+                0
+            }
+            None => -1,
+        };
+        // nothing is left of the app a panic may have broken, so the next
+        // start begins afresh
+        DISABLED.store(false, Ordering::SeqCst);
+        result
+    })
+}
+
+/// Lets go of a Lua state, so that the shim can unload the library. The shim
+/// calls it after `stop`, from within each environment the library was
+/// given. Returns -1 if the library never wrapped the state.
+#[no_mangle]
+pub extern "C" fn detach(state: *mut lua_State) -> c_int {
+    contain("detach", || if release(state) { 0 } else { -1 })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{guard, stop, DISABLED};
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn test_panic_disables_library_until_stopped() {
+        assert_eq!(guard("test", || 3), 3);
+        assert_eq!(guard("test", || panic!("boom")), -1);
+        assert!(DISABLED.load(Ordering::SeqCst));
+        let mut ran = false;
+        assert_eq!(
+            guard("test", || {
+                ran = true;
+                0
+            }),
+            -1
+        );
+        assert!(!ran);
+
+        assert_eq!(stop(std::ptr::null_mut()), -1);
+        assert!(!DISABLED.load(Ordering::SeqCst));
+        assert_eq!(guard("test", || 3), 3);
     }
-    0
 }
@@ -1,12 +1,10 @@
+use crate::platform;
 use fern::colors::{Color, ColoredLevelConfig};
-use std::fs::File;
 use std::io::Write;
-use std::os::windows::io::FromRawHandle;
 use std::path::Path;
-use windows::Win32::System::Console;
 
 pub fn init(config: &config::Config) {
-    let mut console_out = create_console().expect("Created console");
+    let mut console_out = platform::console().expect("Created console");
     writeln!(
         console_out,
         "Console creation complete, setting up logging."
@@ -16,15 +14,10 @@ pub fn init(config: &config::Config) {
     setup_logging(&config, console_out).expect("Couldn't set up logging, very sad.");
 }
 
-fn create_console() -> windows::core::Result<File> {
-    unsafe {
-        Console::AllocConsole();
-        let h_stdout = Console::GetStdHandle(Console::STD_OUTPUT_HANDLE)?;
-        Ok(File::from_raw_handle(h_stdout.0 as *mut libc::c_void))
-    }
-}
-
-fn setup_logging(config: &config::Config, mut console: File) -> Result<(), fern::InitError> {
+fn setup_logging(
+    config: &config::Config,
+    mut console: Box<dyn Write + Send>,
+) -> Result<(), fern::InitError> {
     writeln!(console, "Setting up logging").unwrap();
     let colors_line = ColoredLevelConfig::new()
         .error(Color::Red)
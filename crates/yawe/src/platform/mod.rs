@@ -0,0 +1,19 @@
+//! What yawe needs from the OS. Inside DCS that is Windows, but the
+//! Windows-only parts are kept here so that everything else builds and runs
+//! its tests anywhere.
+//!
+//! Both modules provide the same items:
+//!
+//! - `console()`, where log lines are echoed besides the log file.
+//! - `DECORATED`, whether the GUI window keeps the system title bar.
+//! - `customize_window()`, called once on the GUI window after it is made.
+
+#[cfg(not(windows))]
+mod portable;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(windows)]
+pub use self::windows::*;
+#[cfg(not(windows))]
+pub use portable::*;
@@ -0,0 +1,15 @@
+//! Fallbacks for anything but Windows, where yawe only runs for development.
+
+use egui_window_glfw_passthrough::glfw;
+use std::io::Write;
+
+/// Logs go to stderr.
+pub fn console() -> std::io::Result<Box<dyn Write + Send>> {
+    Ok(Box::new(std::io::stderr()))
+}
+
+/// Without the hit testing the Windows title bar replacement relies on, the
+/// window couldn't be moved or resized without its title bar.
+pub const DECORATED: bool = true;
+
+pub fn customize_window(_window: &glfw::Window) {}
@@ -0,0 +1,213 @@
+//! Wrapping the Lua states DCS hands over, and letting go of them again.
+//!
+//! The first time mlua wraps a state it keeps what it needs in the state's
+//! registry: metatables whose `__gc` is code in this library, a userdata
+//! holding its own bookkeeping and a thread for the values it references.
+//! Left there, DCS would call into the library when it collects them, long
+//! after the shim has unloaded it. So the first [`wrap`] of a state notes
+//! which registry entries mlua added, and [`detach`] removes them and
+//! collects the garbage, which runs those finalizers while the library is
+//! still loaded.
+
+use mlua::{lua_State, Lua};
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+
+const LUA_REGISTRYINDEX: c_int = -10000;
+const LUA_TLIGHTUSERDATA: c_int = 2;
+const LUA_TNUMBER: c_int = 3;
+const LUA_TTABLE: c_int = 5;
+const LUA_GCCOLLECT: c_int = 2;
+
+// mlua doesn't expose the raw API, but links the library that has it
+extern "C" {
+    fn lua_gettop(state: *mut lua_State) -> c_int;
+    fn lua_settop(state: *mut lua_State, index: c_int);
+    fn lua_pushnil(state: *mut lua_State);
+    fn lua_pushnumber(state: *mut lua_State, n: f64);
+    fn lua_pushlightuserdata(state: *mut lua_State, p: *mut c_void);
+    fn lua_type(state: *mut lua_State, index: c_int) -> c_int;
+    fn lua_tonumber(state: *mut lua_State, index: c_int) -> f64;
+    fn lua_touserdata(state: *mut lua_State, index: c_int) -> *mut c_void;
+    fn lua_topointer(state: *mut lua_State, index: c_int) -> *const c_void;
+    fn lua_objlen(state: *mut lua_State, index: c_int) -> usize;
+    fn lua_createtable(state: *mut lua_State, narr: c_int, nrec: c_int);
+    fn lua_rawget(state: *mut lua_State, index: c_int);
+    fn lua_rawset(state: *mut lua_State, index: c_int);
+    fn lua_rawgeti(state: *mut lua_State, index: c_int, n: c_int);
+    fn lua_rawseti(state: *mut lua_State, index: c_int, n: c_int);
+    fn lua_next(state: *mut lua_State, index: c_int) -> c_int;
+    fn lua_gc(state: *mut lua_State, what: c_int, data: c_int) -> c_int;
+    fn luaL_unref(state: *mut lua_State, table: c_int, reference: c_int);
+}
+
+/// Its address keys the list of entries mlua added to a state's registry.
+static ADDED_BY_MLUA: u8 = 0;
+
+fn marker() -> *mut c_void {
+    &ADDED_BY_MLUA as *const u8 as *mut c_void
+}
+
+/// A registry key mlua might have added: the address of one of its statics,
+/// or a reference from `luaL_ref`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Light(usize),
+    Ref(c_int),
+}
+
+/// The registry's light userdata and numeric keys, with the type and
+/// address of their values.
+unsafe fn registry_entries(state: *mut lua_State) -> HashMap<Key, (c_int, usize)> {
+    let mut entries = HashMap::new();
+    lua_pushnil(state);
+    while lua_next(state, LUA_REGISTRYINDEX) != 0 {
+        let key = match lua_type(state, -2) {
+            LUA_TLIGHTUSERDATA => Some(Key::Light(lua_touserdata(state, -2) as usize)),
+            LUA_TNUMBER => Some(Key::Ref(lua_tonumber(state, -2) as c_int)),
+            _ => None,
+        };
+        if let Some(key) = key {
+            let value = (lua_type(state, -1), lua_topointer(state, -1) as usize);
+            entries.insert(key, value);
+        }
+        lua_settop(state, -2);
+    }
+    entries
+}
+
+/// Pushes the entries [`wrap`] noted for the state, and returns whether it
+/// noted any, i.e. whether the state was wrapped.
+unsafe fn push_added(state: *mut lua_State) -> bool {
+    lua_pushlightuserdata(state, marker());
+    lua_rawget(state, LUA_REGISTRYINDEX);
+    lua_type(state, -1) == LUA_TTABLE
+}
+
+/// Wraps the raw Lua state handed over by the shim. It stays valid for the
+/// duration of the call.
+///
+/// # Safety
+/// `state` has to be a live Lua state, with room for a few values on its
+/// stack.
+pub unsafe fn wrap(state: *mut lua_State) -> Lua {
+    let wrapped = push_added(state);
+    lua_settop(state, -2);
+    if wrapped {
+        return Lua::init_from_ptr(state);
+    }
+
+    let before = registry_entries(state);
+    let lua = Lua::init_from_ptr(state);
+    let added: Vec<Key> = registry_entries(state)
+        .into_iter()
+        .filter(|(key, value)| match key {
+            Key::Light(_) => !before.contains_key(key),
+            // free references hold the next free one, as numbers
+            Key::Ref(_) => value.0 != LUA_TNUMBER && before.get(key) != Some(value),
+        })
+        .map(|(key, _)| key)
+        .collect();
+
+    lua_pushlightuserdata(state, marker());
+    lua_createtable(state, added.len() as c_int, 0);
+    for (i, key) in added.iter().enumerate() {
+        match *key {
+            Key::Light(p) => lua_pushlightuserdata(state, p as *mut c_void),
+            Key::Ref(r) => lua_pushnumber(state, r.into()),
+        }
+        lua_rawseti(state, -2, i as c_int + 1);
+    }
+    lua_rawset(state, LUA_REGISTRYINDEX);
+    lua
+}
+
+/// Removes everything the library's [`wrap`]s left in `state` and collects
+/// the garbage. Returns false if the state was never wrapped. Nothing the
+/// library got out of the state may be used afterwards.
+///
+/// # Safety
+/// `state` has to be a live Lua state, with room for a few values on its
+/// stack.
+pub unsafe fn detach(state: *mut lua_State) -> bool {
+    let top = lua_gettop(state);
+    if !push_added(state) {
+        lua_settop(state, top);
+        return false;
+    }
+    let added = lua_gettop(state);
+    for i in 1..=lua_objlen(state, added) as c_int {
+        lua_rawgeti(state, added, i);
+        match lua_type(state, -1) {
+            LUA_TLIGHTUSERDATA => {
+                lua_pushnil(state);
+                lua_rawset(state, LUA_REGISTRYINDEX);
+            }
+            LUA_TNUMBER => {
+                let reference = lua_tonumber(state, -1) as c_int;
+                lua_settop(state, -2);
+                luaL_unref(state, LUA_REGISTRYINDEX, reference);
+            }
+            _ => lua_settop(state, -2),
+        }
+    }
+    lua_settop(state, top);
+    lua_pushlightuserdata(state, marker());
+    lua_pushnil(state);
+    lua_rawset(state, LUA_REGISTRYINDEX);
+    lua_gc(state, LUA_GCCOLLECT, 0);
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detach, registry_entries, wrap, Key, LUA_TNUMBER};
+    use mlua::lua_State;
+    use mlua::prelude::LuaFunction;
+    use std::collections::HashSet;
+
+    extern "C" {
+        fn luaL_newstate() -> *mut lua_State;
+        fn luaL_openlibs(state: *mut lua_State);
+        fn lua_close(state: *mut lua_State);
+    }
+
+    /// The registry's keys, less the free references.
+    fn keys(state: *mut lua_State) -> HashSet<Key> {
+        unsafe { registry_entries(state) }
+            .into_iter()
+            .filter(|(key, value)| matches!(key, Key::Light(_)) || value.0 != LUA_TNUMBER)
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    #[test]
+    fn test_reload_leaves_nothing_of_the_old_build() {
+        unsafe {
+            let state = luaL_newstate();
+            luaL_openlibs(state);
+            let untouched = keys(state);
+            assert!(!detach(state));
+
+            // as on every frame, each call wraps the state anew
+            for _ in 0..3 {
+                let lua = wrap(state);
+                let tostring: LuaFunction = lua.globals().get("tostring").unwrap();
+                assert_eq!(tostring.call::<_, String>(1).unwrap(), "1");
+            }
+            assert!(keys(state).len() > untouched.len());
+
+            assert!(detach(state));
+            assert_eq!(keys(state), untouched);
+            assert!(!detach(state));
+
+            // the next build wraps it like a state it has never seen
+            let lua = wrap(state);
+            assert_eq!(lua.load("return 1 + 1").eval::<i32>().unwrap(), 2);
+            drop(lua);
+            assert!(detach(state));
+            assert_eq!(keys(state), untouched);
+            lua_close(state);
+        }
+    }
+}
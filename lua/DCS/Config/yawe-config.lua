@@ -3,3 +3,5 @@ dll_path = [[F:\projects\dcs\yawe\target\release\]]
 lua_path = [[F:\projects\dcs\yawe\lua\]]
 log_level = "debug"
 enable_object_log = false
+-- record all Lua traffic to Logs/Yawe/recording-*.jsonl for offline replay
+record_lua = false
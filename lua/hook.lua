@@ -54,6 +54,10 @@ do
     end
 
     function yaweCallbacks.onSimulationStop()
+        if not YAWE.lib then
+            return
+        end
+        YAWE.lib.on_simulation_stop()
         YAWE.lib.stop()
         YAWE.lib = nil
         YAWE = {}
@@ -81,6 +85,20 @@ do
         YAWE.lib.on_simulation_resume()
     end
 
+    function yaweCallbacks.onPlayerChangeSlot(playerID)
+        if not YAWE.lib then
+            return
+        end
+        YAWE.lib.on_player_change_slot(playerID)
+    end
+
+    function yaweCallbacks.onGameEvent(eventName, ...)
+        if not YAWE.lib then
+            return
+        end
+        YAWE.lib.on_game_event(eventName, ...)
+    end
+
     DCS.setUserCallbacks(yaweCallbacks)
     writeLog(log.INFO, "Set up Yawe hook callbacks.")
 end